@@ -0,0 +1,105 @@
+// Time of variable lookup, scope entry and global lookup with many live bindings, on both
+// engines. Run with `cargo bench --bench deep_scopes`.
+//
+// Absolute times depend on the machine, so every time is also printed relative to the
+// smallest number of bindings. Ratios should stay close to 1 as the number grows; before
+// scopes stopped copying the environment and large environments got a name index, the
+// tree-walker entered scopes over 30 times slower with 1000 bindings than with 10.
+
+use std::time::{Duration, Instant};
+use tk_lisp_test_1::{Atom, Engine, Interpreter, SExpr};
+
+const ITERATIONS: usize = 20_000;
+const DEPTHS: [usize; 3] = [10, 100, 1000];
+
+// `let` binding `depth` variables around `body`. Each binding opens a scope nested in the
+// previous one, without nesting source code deeply.
+fn nested_lets(depth: usize, body: &str) -> String {
+    let mut code = String::from("(let ");
+    for i in 0..depth {
+        code.push_str(&format!("(x{} {}) ", i, i));
+    }
+    code.push_str(body);
+    code.push(')');
+    code
+}
+
+// Loop reading the outermost variable on every iteration
+fn lookup_program(depth: usize) -> String {
+    nested_lets(
+        depth,
+        &format!(
+            "(let (i 0) (s 0) ((while (< i {}) ((set s (+ s x0)) (set i (+ i 1)))) s))",
+            ITERATIONS
+        ),
+    )
+}
+
+// Loop entering a `let` on every iteration
+fn scope_entry_program(depth: usize) -> String {
+    nested_lets(
+        depth,
+        &format!(
+            "(let (i 0) ((while (< i {}) (let (y i) (set i (+ y 1)))) i))",
+            ITERATIONS
+        ),
+    )
+}
+
+// Loop reading the first of `count` globals defined by the embedder
+fn run_with_globals(count: usize, engine: Engine) -> Duration {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_engine(engine);
+    for i in 0..count {
+        interpreter.set_global(&format!("g{}", i), SExpr::Atom(Atom::Number(i as f64)));
+    }
+    let code = format!(
+        "(let (i 0) (s 0) ((while (< i {}) ((set s (+ s g0)) (set i (+ i 1)))) s))",
+        ITERATIONS
+    );
+    let start = Instant::now();
+    interpreter.eval_str(&code).unwrap();
+    start.elapsed()
+}
+
+fn run(code: &str, engine: Engine) -> Duration {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_engine(engine);
+    let start = Instant::now();
+    interpreter.eval_str(code).unwrap();
+    start.elapsed()
+}
+
+// Prints times of `measure` for every number of bindings, relative to the first one
+fn report(engine: Engine, what: &str, measure: impl Fn(usize) -> Duration) {
+    let base = measure(DEPTHS[0]);
+    for depth in DEPTHS {
+        let time = if depth == DEPTHS[0] {
+            base
+        } else {
+            measure(depth)
+        };
+        println!(
+            "{:?} {}, {} bindings: {:?} ({:.2}x)",
+            engine,
+            what,
+            depth,
+            time,
+            time.as_secs_f64() / base.as_secs_f64()
+        );
+    }
+}
+
+fn main() {
+    for engine in [Engine::TreeWalker, Engine::Bytecode] {
+        report(engine, "lookup", |depth| {
+            run(&lookup_program(depth), engine)
+        });
+        report(engine, "scope entry", |depth| {
+            run(&scope_entry_program(depth), engine)
+        });
+        report(engine, "global lookup", |count| {
+            run_with_globals(count, engine)
+        });
+    }
+}
@@ -0,0 +1,1897 @@
+use crate::concurrency;
+use crate::conditions;
+use crate::continuations;
+use crate::evaluator;
+use crate::gc;
+use crate::generators;
+use crate::parser;
+use crate::symbols::{self, SpecialForm};
+
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex};
+
+/// Builtin function called with already evaluated arguments
+pub type BuiltinFn = fn(
+    &[parser::SExpr],
+    &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>>;
+
+// Builtin functions by name, some of which have several names
+static BUILTINS: LazyLock<HashMap<symbols::Symbol, BuiltinFn>> = LazyLock::new(|| {
+    let builtins: Vec<(&str, BuiltinFn)> = vec![
+        ("+", builtin_add),
+        (">", |args, _ctx| builtin_compare(">", args, |a, b| a > b)),
+        ("<", |args, _ctx| builtin_compare("<", args, |a, b| a < b)),
+        (">=", |args, _ctx| {
+            builtin_compare(">=", args, |a, b| a >= b)
+        }),
+        ("<=", |args, _ctx| {
+            builtin_compare("<=", args, |a, b| a <= b)
+        }),
+        ("=", |args, _ctx| builtin_compare("=", args, |a, b| a == b)),
+        ("list", builtin_list),
+        ("print", builtin_print),
+        ("readnum", builtin_readnum),
+        ("cons", builtin_cons),
+        ("first", builtin_first),
+        ("car", builtin_first),
+        ("rest", builtin_rest),
+        ("cdr", builtin_rest),
+        ("length", builtin_length),
+        ("nth", builtin_nth),
+        ("last", builtin_last),
+        ("append", builtin_append),
+        ("reverse", builtin_reverse),
+        ("null?", builtin_null),
+        ("empty?", builtin_null),
+        ("list?", builtin_is_list),
+        ("equal?", builtin_equal),
+        ("eqv?", builtin_eqv),
+        ("eq?", builtin_eq),
+        ("symbol?", builtin_is_symbol),
+        ("symbol->string", builtin_symbol_to_string),
+        ("string->symbol", builtin_string_to_symbol),
+        ("number?", builtin_is_number),
+        ("string?", builtin_is_string),
+        ("procedure?", builtin_is_procedure),
+        ("map", builtin_map),
+        ("for-each", builtin_for_each),
+        ("filter", builtin_filter),
+        ("reduce", builtin_reduce),
+        ("fold-left", builtin_fold_left),
+        ("fold-right", builtin_fold_right),
+        ("apply", builtin_apply),
+        ("any", builtin_any),
+        ("every", builtin_every),
+        ("sort", builtin_sort),
+        ("range", builtin_range),
+        ("make-hash", builtin_make_hash),
+        ("hash-get", builtin_hash_get),
+        ("hash-set!", builtin_hash_set),
+        ("hash-remove!", builtin_hash_remove),
+        ("hash-keys", builtin_hash_keys),
+        ("hash-values", builtin_hash_values),
+        ("hash-contains?", builtin_hash_contains),
+        ("hash-count", builtin_hash_count),
+        ("vector", builtin_vector),
+        ("make-vector", builtin_make_vector),
+        ("vector-ref", builtin_vector_ref),
+        ("vector-set!", builtin_vector_set),
+        ("vector-length", builtin_vector_length),
+        ("vector-push!", builtin_vector_push),
+        ("vector->list", builtin_vector_to_list),
+        ("list->vector", builtin_list_to_vector),
+        ("record-construct", builtin_record_construct),
+        ("record-of-type?", builtin_record_of_type),
+        ("record-field", builtin_record_field),
+        ("record-set-field!", builtin_record_set_field),
+        ("macroexpand-1", builtin_macroexpand_1),
+        ("macroexpand", builtin_macroexpand),
+        ("gensym", builtin_gensym),
+        ("raise", builtin_raise),
+        ("error", builtin_error_raise),
+        ("condition?", builtin_is_condition),
+        ("condition-kind", builtin_condition_kind),
+        ("condition-message", builtin_condition_message),
+        ("call/cc", builtin_call_cc),
+        ("call-with-current-continuation", builtin_call_cc),
+        ("generator", builtin_generator),
+        ("yield", builtin_yield),
+        ("next", builtin_next),
+        ("done?", builtin_is_done),
+        ("generator?", builtin_is_generator),
+        ("generator->list", builtin_generator_to_list),
+        ("spawn", builtin_spawn),
+        ("join", builtin_join),
+        ("channel", builtin_channel),
+        ("send", builtin_send),
+        ("recv", builtin_recv),
+        ("make-mutex", builtin_make_mutex),
+        ("with-lock", builtin_with_lock),
+        ("make-atomic", builtin_make_atomic),
+        ("atomic-get", builtin_atomic_get),
+        ("atomic-set!", builtin_atomic_set),
+        ("atomic-add!", builtin_atomic_add),
+        ("atomic-compare-and-set!", builtin_atomic_compare_and_set),
+        ("gc", builtin_gc),
+        ("heap-stats", builtin_heap_stats),
+    ];
+    builtins
+        .into_iter()
+        .map(|(name, builtin)| (symbols::Symbol::intern(name), builtin))
+        .collect()
+});
+
+/// Builtin function named `name`, if any
+pub fn lookup(name: &symbols::Symbol) -> Option<BuiltinFn> {
+    BUILTINS.get(name).copied()
+}
+
+/// Value of builtin function `name`: list `(builtin name)`, whose tag is an uninterned
+/// symbol, so that lists read from source code are never builtins
+pub fn builtin_value(name: &symbols::Symbol) -> parser::SExpr {
+    parser::SExpr::List(vec![
+        parser::SExpr::Atom(parser::Atom::Symbol(SpecialForm::Builtin.symbol())),
+        parser::SExpr::Atom(parser::Atom::Symbol(name.clone().at_line(None))),
+    ])
+}
+
+pub fn as_builtin(value: &parser::SExpr) -> Option<BuiltinFn> {
+    if let parser::SExpr::List(list) = value {
+        if let [parser::SExpr::Atom(parser::Atom::Symbol(tag)), parser::SExpr::Atom(parser::Atom::Symbol(name))] =
+            list.as_slice()
+        {
+            if SpecialForm::Builtin.is(tag) {
+                return lookup(name);
+            }
+        }
+    }
+    None
+}
+
+pub(crate) fn builtin_error(message: String) -> Box<dyn std::error::Error + Send + Sync> {
+    Box::new(std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        message,
+    ))
+}
+
+pub(crate) fn expect_arg_count(
+    name: &str,
+    args: &[parser::SExpr],
+    count: usize,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if args.len() == count {
+        Ok(())
+    } else {
+        Err(builtin_error(format!(
+            "`{}` expects {} argument(s), got {}.",
+            name,
+            count,
+            args.len()
+        )))
+    }
+}
+
+pub(crate) fn expect_list(
+    name: &str,
+    value: &parser::SExpr,
+) -> Result<Vec<parser::SExpr>, Box<dyn std::error::Error + Send + Sync>> {
+    match evaluator::resolve_reference(value) {
+        parser::SExpr::List(list) => Ok(list),
+        _ => Err(builtin_error(format!("`{}` expects a list.", name))),
+    }
+}
+
+pub(crate) fn expect_number(
+    name: &str,
+    value: &parser::SExpr,
+) -> Result<f64, Box<dyn std::error::Error + Send + Sync>> {
+    match evaluator::resolve_reference(value) {
+        parser::SExpr::Atom(parser::Atom::Number(num)) => Ok(num),
+        _ => Err(builtin_error(format!("`{}` expects a number.", name))),
+    }
+}
+
+// Longest list or vector a builtin creates at once. Memory limit, if set, is usually reached
+// much earlier; this bounds allocation of scripts running without one.
+pub(crate) const MAX_NEW_SEQUENCE_LEN: usize = 1 << 24;
+
+// Checks length of list or vector about to be created by `name`
+pub(crate) fn expect_new_len(
+    name: &str,
+    len: f64,
+) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+    if !len.is_finite() {
+        Err(builtin_error(format!(
+            "`{}`: length {} is not a finite number.",
+            name, len
+        )))
+    } else if len < 0.0 || len.fract() != 0.0 {
+        Err(builtin_error(format!(
+            "`{}`: length {} is not a non-negative integer.",
+            name, len
+        )))
+    } else if len > MAX_NEW_SEQUENCE_LEN as f64 {
+        Err(builtin_error(format!(
+            "`{}`: length {} is too large (at most {}).",
+            name, len, MAX_NEW_SEQUENCE_LEN
+        )))
+    } else {
+        Ok(len as usize)
+    }
+}
+
+pub(crate) fn expect_index(
+    name: &str,
+    value: &parser::SExpr,
+    len: usize,
+) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+    let index = expect_number(name, value)?;
+    if index >= 0.0 && index.fract() == 0.0 && (index as usize) < len {
+        Ok(index as usize)
+    } else {
+        Err(builtin_error(format!(
+            "`{}`: index {} out of bounds for length {}.",
+            name, index, len
+        )))
+    }
+}
+
+pub(crate) fn bool_value(value: bool) -> parser::SExpr {
+    parser::SExpr::Atom(parser::Atom::Number(if value { 1.0 } else { 0.0 }))
+}
+
+fn builtin_add(
+    args: &[parser::SExpr],
+    _ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    if args.len() >= 2 {
+        let mut result: f64 = 0.0;
+        for arg in args {
+            if let parser::SExpr::Atom(parser::Atom::Number(num)) =
+                evaluator::resolve_reference(arg)
+            {
+                result += num;
+            } else {
+                return Err(builtin_error(String::from(
+                    "2+nd argument of statement list `+` must evaluate to a number atom.",
+                )));
+            }
+        }
+        Ok(parser::SExpr::Atom(parser::Atom::Number(result)))
+    } else {
+        Err(builtin_error(String::from(
+            "Statement list `+` must have more than 2 elements: `+`, values... .",
+        )))
+    }
+}
+
+fn builtin_compare(
+    name: &str,
+    args: &[parser::SExpr],
+    op: fn(f64, f64) -> bool,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    if let [val1, val2] = args {
+        if let (
+            parser::SExpr::Atom(parser::Atom::Number(val1_num)),
+            parser::SExpr::Atom(parser::Atom::Number(val2_num)),
+        ) = (
+            evaluator::resolve_reference(val1),
+            evaluator::resolve_reference(val2),
+        ) {
+            Ok(bool_value(op(val1_num, val2_num)))
+        } else {
+            Err(builtin_error(format!(
+                "Comparison operator `{}` expects numbers; use `equal?` to compare other values.",
+                name
+            )))
+        }
+    } else {
+        Err(builtin_error(String::from(
+            "Comparison statement list must have exactly 3 elements: operator, val1, val2.",
+        )))
+    }
+}
+
+fn builtin_list(
+    args: &[parser::SExpr],
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    ctx.charge_list_memory(args.len())?;
+    Ok(parser::SExpr::List(args.to_vec()))
+}
+
+fn builtin_print(
+    args: &[parser::SExpr],
+    _ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    if args.is_empty() {
+        return Err(builtin_error(String::from(
+            "Statement list `print` must have at least 2 elements: `print`, value+.",
+        )));
+    }
+    for arg in args {
+        match evaluator::resolve_reference(arg) {
+            parser::SExpr::Atom(parser::Atom::Number(num)) => print!("{}", num),
+            parser::SExpr::Record(record) => print!("{}", display_record(&record)),
+            parser::SExpr::List(print_list) => {
+                for elem in print_list {
+                    if let parser::SExpr::Atom(parser::Atom::Number(ch)) = elem {
+                        print!("{}", ch as u8 as char);
+                    } else {
+                        return Err(builtin_error(String::from(
+                            "2+nd argument to statement list `print` must evaluate to a number atom or list of number atoms (char codes).",
+                        )));
+                    }
+                }
+            }
+            _ => {
+                return Err(builtin_error(String::from(
+                    "2+nd argument to statement list `print` must evaluate to a number atom or list of number atoms (char codes).",
+                )));
+            }
+        }
+    }
+    Ok(parser::SExpr::List(vec![]))
+}
+
+fn builtin_readnum(
+    _args: &[parser::SExpr],
+    _ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    let mut buf: String = String::new();
+    std::io::stdin().read_line(&mut buf)?;
+    let num = buf.trim().parse::<f64>()?;
+    Ok(parser::SExpr::Atom(parser::Atom::Number(num)))
+}
+
+fn builtin_cons(
+    args: &[parser::SExpr],
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("cons", args, 2)?;
+    let mut list = expect_list("cons", &args[1])?;
+    list.insert(0, args[0].clone());
+    ctx.charge_list_memory(list.len())?;
+    Ok(parser::SExpr::List(list))
+}
+
+fn builtin_first(
+    args: &[parser::SExpr],
+    _ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("first", args, 1)?;
+    match expect_list("first", &args[0])?.into_iter().next() {
+        Some(elem) => Ok(elem),
+        None => Err(builtin_error(String::from("`first` of empty list."))),
+    }
+}
+
+fn builtin_rest(
+    args: &[parser::SExpr],
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("rest", args, 1)?;
+    let mut list = expect_list("rest", &args[0])?;
+    if list.is_empty() {
+        return Err(builtin_error(String::from("`rest` of empty list.")));
+    }
+    list.remove(0);
+    ctx.charge_list_memory(list.len())?;
+    Ok(parser::SExpr::List(list))
+}
+
+fn builtin_length(
+    args: &[parser::SExpr],
+    _ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("length", args, 1)?;
+    let list = expect_list("length", &args[0])?;
+    Ok(parser::SExpr::Atom(parser::Atom::Number(list.len() as f64)))
+}
+
+// (nth index list)
+fn builtin_nth(
+    args: &[parser::SExpr],
+    _ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("nth", args, 2)?;
+    let mut list = expect_list("nth", &args[1])?;
+    let index = expect_index("nth", &args[0], list.len())?;
+    Ok(list.swap_remove(index))
+}
+
+fn builtin_last(
+    args: &[parser::SExpr],
+    _ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("last", args, 1)?;
+    match expect_list("last", &args[0])?.pop() {
+        Some(elem) => Ok(elem),
+        None => Err(builtin_error(String::from("`last` of empty list."))),
+    }
+}
+
+fn builtin_append(
+    args: &[parser::SExpr],
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    let mut result: Vec<parser::SExpr> = Vec::new();
+    for arg in args {
+        result.extend(expect_list("append", arg)?);
+    }
+    ctx.charge_list_memory(result.len())?;
+    Ok(parser::SExpr::List(result))
+}
+
+fn builtin_reverse(
+    args: &[parser::SExpr],
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("reverse", args, 1)?;
+    let mut list = expect_list("reverse", &args[0])?;
+    list.reverse();
+    ctx.charge_list_memory(list.len())?;
+    Ok(parser::SExpr::List(list))
+}
+
+fn builtin_null(
+    args: &[parser::SExpr],
+    _ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("null?", args, 1)?;
+    Ok(bool_value(matches!(
+        evaluator::resolve_reference(&args[0]),
+        parser::SExpr::List(list) if list.is_empty()
+    )))
+}
+
+fn builtin_is_list(
+    args: &[parser::SExpr],
+    _ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("list?", args, 1)?;
+    Ok(bool_value(matches!(
+        evaluator::resolve_reference(&args[0]),
+        parser::SExpr::List(_)
+    )))
+}
+
+// Calls `func` with i-th elements of all `lists`, for each i up to length of the shortest list
+fn map_lists(
+    name: &str,
+    args: &[parser::SExpr],
+    ctx: &mut evaluator::EvalContext,
+    mut consume: impl FnMut(parser::SExpr),
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if args.len() < 2 {
+        return Err(builtin_error(format!(
+            "`{}` expects a function and at least one list.",
+            name
+        )));
+    }
+    let mut lists: Vec<Vec<parser::SExpr>> = Vec::new();
+    for arg in &args[1..] {
+        lists.push(expect_list(name, arg)?);
+    }
+    let len = lists.iter().map(|list| list.len()).min().unwrap();
+    for i in 0..len {
+        let call_args: Vec<parser::SExpr> = lists.iter().map(|list| list[i].clone()).collect();
+        consume(evaluator::apply(&args[0], call_args, ctx)?);
+    }
+    Ok(())
+}
+
+// (map func list+), or (map func generator) giving lazy generator
+fn builtin_map(
+    args: &[parser::SExpr],
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    if let [func, source] = args {
+        if let Some(source) = as_generator(source) {
+            return Ok(generators::map_generator(source, func.clone(), ctx));
+        }
+    }
+    let mut result: Vec<parser::SExpr> = Vec::new();
+    map_lists("map", args, ctx, |elem| result.push(elem))?;
+    ctx.charge_list_memory(result.len())?;
+    Ok(parser::SExpr::List(result))
+}
+
+// (for-each func list+) or (for-each func generator)
+fn builtin_for_each(
+    args: &[parser::SExpr],
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    if let [func, source] = args {
+        if let Some(source) = as_generator(source) {
+            while !generators::is_done(&source, ctx)? {
+                let elem = generators::next(&source, ctx)?;
+                evaluator::apply(func, vec![elem], ctx)?;
+            }
+            return Ok(parser::SExpr::List(vec![]));
+        }
+    }
+    map_lists("for-each", args, ctx, |_| {})?;
+    Ok(parser::SExpr::List(vec![]))
+}
+
+fn builtin_filter(
+    args: &[parser::SExpr],
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("filter", args, 2)?;
+    if let Some(source) = as_generator(&args[1]) {
+        return Ok(generators::filter_generator(source, args[0].clone(), ctx));
+    }
+    let mut result: Vec<parser::SExpr> = Vec::new();
+    for elem in expect_list("filter", &args[1])? {
+        if evaluator::value_is_true(&evaluator::apply(&args[0], vec![elem.clone()], ctx)?) {
+            result.push(elem);
+        }
+    }
+    ctx.charge_list_memory(result.len())?;
+    Ok(parser::SExpr::List(result))
+}
+
+fn fold_left(
+    func: &parser::SExpr,
+    init: parser::SExpr,
+    list: Vec<parser::SExpr>,
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    let mut acc = init;
+    for elem in list {
+        acc = evaluator::apply(func, vec![acc, elem], ctx)?;
+    }
+    Ok(acc)
+}
+
+// (reduce func list) or (reduce func init list)
+fn builtin_reduce(
+    args: &[parser::SExpr],
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    match args {
+        [func, list] => {
+            let mut list = expect_list("reduce", list)?;
+            if list.is_empty() {
+                return Err(builtin_error(String::from(
+                    "`reduce` of empty list without initial value.",
+                )));
+            }
+            let init = list.remove(0);
+            fold_left(func, init, list, ctx)
+        }
+        [func, init, list] => fold_left(func, init.clone(), expect_list("reduce", list)?, ctx),
+        _ => Err(builtin_error(format!(
+            "`reduce` expects 2 or 3 argument(s), got {}.",
+            args.len()
+        ))),
+    }
+}
+
+// (fold-left func init list): (func (func init x1) x2) ...
+fn builtin_fold_left(
+    args: &[parser::SExpr],
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("fold-left", args, 3)?;
+    fold_left(
+        &args[0],
+        args[1].clone(),
+        expect_list("fold-left", &args[2])?,
+        ctx,
+    )
+}
+
+// (fold-right func init list): (func x1 (func x2 ... init))
+fn builtin_fold_right(
+    args: &[parser::SExpr],
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("fold-right", args, 3)?;
+    let mut acc = args[1].clone();
+    for elem in expect_list("fold-right", &args[2])?.into_iter().rev() {
+        acc = evaluator::apply(&args[0], vec![elem, acc], ctx)?;
+    }
+    Ok(acc)
+}
+
+// (apply func arg* list): elements of the last list are spread as arguments
+fn builtin_apply(
+    args: &[parser::SExpr],
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    if args.len() < 2 {
+        return Err(builtin_error(String::from(
+            "`apply` expects a function and a list of arguments.",
+        )));
+    }
+    let mut call_args: Vec<parser::SExpr> = args[1..args.len() - 1].to_vec();
+    call_args.extend(expect_list("apply", &args[args.len() - 1])?);
+    evaluator::apply(&args[0], call_args, ctx)
+}
+
+fn builtin_any(
+    args: &[parser::SExpr],
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("any", args, 2)?;
+    for elem in expect_list("any", &args[1])? {
+        if evaluator::value_is_true(&evaluator::apply(&args[0], vec![elem], ctx)?) {
+            return Ok(bool_value(true));
+        }
+    }
+    Ok(bool_value(false))
+}
+
+fn builtin_every(
+    args: &[parser::SExpr],
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("every", args, 2)?;
+    for elem in expect_list("every", &args[1])? {
+        if !evaluator::value_is_true(&evaluator::apply(&args[0], vec![elem], ctx)?) {
+            return Ok(bool_value(false));
+        }
+    }
+    Ok(bool_value(true))
+}
+
+// (sort list less-than): stable merge sort, as comparator can fail
+fn builtin_sort(
+    args: &[parser::SExpr],
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("sort", args, 2)?;
+    let list = expect_list("sort", &args[0])?;
+    let len = list.len();
+    let result = merge_sort(list, &args[1], ctx)?;
+    ctx.charge_list_memory(len)?;
+    Ok(parser::SExpr::List(result))
+}
+
+fn merge_sort(
+    mut list: Vec<parser::SExpr>,
+    less_than: &parser::SExpr,
+    ctx: &mut evaluator::EvalContext,
+) -> Result<Vec<parser::SExpr>, Box<dyn std::error::Error + Send + Sync>> {
+    if list.len() <= 1 {
+        return Ok(list);
+    }
+    let right_half = list.split_off(list.len() / 2);
+    let left = merge_sort(list, less_than, ctx)?;
+    let right = merge_sort(right_half, less_than, ctx)?;
+
+    let mut result: Vec<parser::SExpr> = Vec::with_capacity(left.len() + right.len());
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+    while let (Some(left_elem), Some(right_elem)) = (left.peek(), right.peek()) {
+        // Element from the right half goes first only if it is strictly less
+        let right_first = evaluator::value_is_true(&evaluator::apply(
+            less_than,
+            vec![right_elem.clone(), left_elem.clone()],
+            ctx,
+        )?);
+        if right_first {
+            result.push(right.next().unwrap());
+        } else {
+            result.push(left.next().unwrap());
+        }
+    }
+    result.extend(left);
+    result.extend(right);
+    Ok(result)
+}
+
+// (range end), (range start end) or (range start end step); `end` is exclusive
+fn builtin_range(
+    args: &[parser::SExpr],
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    let (start, end, step) = match args {
+        [end] => (0.0, expect_number("range", end)?, 1.0),
+        [start, end] => (
+            expect_number("range", start)?,
+            expect_number("range", end)?,
+            1.0,
+        ),
+        [start, end, step] => (
+            expect_number("range", start)?,
+            expect_number("range", end)?,
+            expect_number("range", step)?,
+        ),
+        _ => {
+            return Err(builtin_error(format!(
+                "`range` expects 1 to 3 argument(s), got {}.",
+                args.len()
+            )))
+        }
+    };
+    if step == 0.0 {
+        return Err(builtin_error(String::from("`range` step must not be 0.")));
+    }
+    // Range running away from `end` is empty; NaN is left for `expect_new_len` to reject
+    let count = ((end - start) / step).ceil();
+    let len = expect_new_len("range", if count < 0.0 { 0.0 } else { count })?;
+    ctx.charge_list_memory(len)?;
+    Ok(parser::SExpr::List(
+        (0..len)
+            .map(|i| parser::SExpr::Atom(parser::Atom::Number(start + step * i as f64)))
+            .collect(),
+    ))
+}
+
+type HashTable = Arc<Mutex<HashMap<parser::HashKey, parser::SExpr>>>;
+
+fn expect_hash(
+    name: &str,
+    value: &parser::SExpr,
+) -> Result<HashTable, Box<dyn std::error::Error + Send + Sync>> {
+    match evaluator::resolve_reference(value) {
+        parser::SExpr::Hash(table) => Ok(table),
+        _ => Err(builtin_error(format!("`{}` expects a hash table.", name))),
+    }
+}
+
+/// Converts number, string (list of char codes) or symbol to hash table key
+pub fn to_hash_key(
+    value: &parser::SExpr,
+) -> Result<parser::HashKey, Box<dyn std::error::Error + Send + Sync>> {
+    match evaluator::resolve_reference(value) {
+        // -0.0 and 0.0 are the same key
+        parser::SExpr::Atom(parser::Atom::Number(num)) => {
+            Ok(parser::HashKey::Number((num + 0.0).to_bits()))
+        }
+        parser::SExpr::Atom(parser::Atom::Symbol(sym)) => Ok(parser::HashKey::Symbol(sym)),
+        parser::SExpr::List(list) => {
+            let mut bytes: Vec<u8> = Vec::new();
+            for elem in &list {
+                match evaluator::resolve_reference(elem) {
+                    parser::SExpr::Atom(parser::Atom::Number(ch))
+                        if (0.0..=255.0).contains(&ch) && ch.fract() == 0.0 =>
+                    {
+                        bytes.push(ch as u8)
+                    }
+                    _ => {
+                        return Err(builtin_error(String::from(
+                            "Hash table key must be a number, string or symbol.",
+                        )))
+                    }
+                }
+            }
+            Ok(parser::HashKey::String(bytes))
+        }
+        _ => Err(builtin_error(String::from(
+            "Hash table key must be a number, string or symbol.",
+        ))),
+    }
+}
+
+pub fn from_hash_key(key: &parser::HashKey) -> parser::SExpr {
+    match key {
+        parser::HashKey::Number(bits) => {
+            parser::SExpr::Atom(parser::Atom::Number(f64::from_bits(*bits)))
+        }
+        parser::HashKey::String(bytes) => parser::SExpr::List(
+            bytes
+                .iter()
+                .map(|b| parser::SExpr::Atom(parser::Atom::Number(*b as f64)))
+                .collect(),
+        ),
+        parser::HashKey::Symbol(sym) => parser::SExpr::Atom(parser::Atom::Symbol(sym.clone())),
+    }
+}
+
+// (make-hash k v ...)
+fn builtin_make_hash(
+    args: &[parser::SExpr],
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    if !args.len().is_multiple_of(2) {
+        return Err(builtin_error(String::from(
+            "`make-hash` expects pairs of keys and values.",
+        )));
+    }
+    let mut table: HashMap<parser::HashKey, parser::SExpr> = HashMap::new();
+    for pair in args.chunks(2) {
+        let key = to_hash_key(&pair[0])?;
+        ctx.charge_memory(evaluator::hash_entry_size(&key, &pair[1]))?;
+        table.insert(key, pair[1].clone());
+    }
+    let table = Arc::new(Mutex::new(table));
+    ctx.register_object(gc::Object::Hash(table.clone()));
+    Ok(parser::SExpr::Hash(table))
+}
+
+// (hash-get table key [default]); missing key without default gives ()
+fn builtin_hash_get(
+    args: &[parser::SExpr],
+    _ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    if args.len() != 2 && args.len() != 3 {
+        return Err(builtin_error(format!(
+            "`hash-get` expects 2 or 3 argument(s), got {}.",
+            args.len()
+        )));
+    }
+    let table = expect_hash("hash-get", &args[0])?;
+    let key = to_hash_key(&args[1])?;
+    let value = table.lock().unwrap().get(&key).cloned();
+    Ok(match value {
+        Some(value) => value,
+        None => args.get(2).cloned().unwrap_or(parser::SExpr::List(vec![])),
+    })
+}
+
+fn builtin_hash_set(
+    args: &[parser::SExpr],
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("hash-set!", args, 3)?;
+    let table = expect_hash("hash-set!", &args[0])?;
+    let key = to_hash_key(&args[1])?;
+    ctx.charge_memory(evaluator::hash_entry_size(&key, &args[2]))?;
+    table.lock().unwrap().insert(key, args[2].clone());
+    Ok(args[2].clone())
+}
+
+// Returns removed value, or () if key was not present
+fn builtin_hash_remove(
+    args: &[parser::SExpr],
+    _ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("hash-remove!", args, 2)?;
+    let table = expect_hash("hash-remove!", &args[0])?;
+    let key = to_hash_key(&args[1])?;
+    let removed = table.lock().unwrap().remove(&key);
+    Ok(removed.unwrap_or(parser::SExpr::List(vec![])))
+}
+
+fn builtin_hash_keys(
+    args: &[parser::SExpr],
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("hash-keys", args, 1)?;
+    let table = expect_hash("hash-keys", &args[0])?;
+    let keys: Vec<parser::SExpr> = table.lock().unwrap().keys().map(from_hash_key).collect();
+    ctx.charge_list_memory(keys.len())?;
+    Ok(parser::SExpr::List(keys))
+}
+
+fn builtin_hash_values(
+    args: &[parser::SExpr],
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("hash-values", args, 1)?;
+    let table = expect_hash("hash-values", &args[0])?;
+    let values: Vec<parser::SExpr> = table.lock().unwrap().values().cloned().collect();
+    ctx.charge_list_memory(values.len())?;
+    Ok(parser::SExpr::List(values))
+}
+
+fn builtin_hash_contains(
+    args: &[parser::SExpr],
+    _ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("hash-contains?", args, 2)?;
+    let table = expect_hash("hash-contains?", &args[0])?;
+    let key = to_hash_key(&args[1])?;
+    let contains = table.lock().unwrap().contains_key(&key);
+    Ok(bool_value(contains))
+}
+
+fn builtin_hash_count(
+    args: &[parser::SExpr],
+    _ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("hash-count", args, 1)?;
+    let table = expect_hash("hash-count", &args[0])?;
+    let count = table.lock().unwrap().len();
+    Ok(parser::SExpr::Atom(parser::Atom::Number(count as f64)))
+}
+
+type Vector = Arc<Mutex<Vec<parser::SExpr>>>;
+
+fn expect_vector(
+    name: &str,
+    value: &parser::SExpr,
+) -> Result<Vector, Box<dyn std::error::Error + Send + Sync>> {
+    match evaluator::resolve_reference(value) {
+        parser::SExpr::Vector(vector) => Ok(vector),
+        _ => Err(builtin_error(format!("`{}` expects a vector.", name))),
+    }
+}
+
+fn new_vector(
+    elems: Vec<parser::SExpr>,
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    ctx.charge_list_memory(elems.len())?;
+    let vector = Arc::new(Mutex::new(elems));
+    ctx.register_object(gc::Object::Vector(vector.clone()));
+    Ok(parser::SExpr::Vector(vector))
+}
+
+fn builtin_vector(
+    args: &[parser::SExpr],
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    new_vector(args.to_vec(), ctx)
+}
+
+// (make-vector length [fill]); fill defaults to ()
+fn builtin_make_vector(
+    args: &[parser::SExpr],
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    if args.len() != 1 && args.len() != 2 {
+        return Err(builtin_error(format!(
+            "`make-vector` expects 1 or 2 argument(s), got {}.",
+            args.len()
+        )));
+    }
+    let len = expect_new_len("make-vector", expect_number("make-vector", &args[0])?)?;
+    ctx.charge_list_memory(len)?;
+    let fill = args.get(1).cloned().unwrap_or(parser::SExpr::List(vec![]));
+    let vector = Arc::new(Mutex::new(vec![fill; len]));
+    ctx.register_object(gc::Object::Vector(vector.clone()));
+    Ok(parser::SExpr::Vector(vector))
+}
+
+// (vector-ref vector index)
+fn builtin_vector_ref(
+    args: &[parser::SExpr],
+    _ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("vector-ref", args, 2)?;
+    let vector = expect_vector("vector-ref", &args[0])?;
+    let vector = vector.lock().unwrap();
+    let index = expect_index("vector-ref", &args[1], vector.len())?;
+    Ok(vector[index].clone())
+}
+
+// (vector-set! vector index value)
+fn builtin_vector_set(
+    args: &[parser::SExpr],
+    _ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("vector-set!", args, 3)?;
+    let vector = expect_vector("vector-set!", &args[0])?;
+    let mut vector = vector.lock().unwrap();
+    let index = expect_index("vector-set!", &args[1], vector.len())?;
+    vector[index] = args[2].clone();
+    Ok(args[2].clone())
+}
+
+fn builtin_vector_length(
+    args: &[parser::SExpr],
+    _ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("vector-length", args, 1)?;
+    let vector = expect_vector("vector-length", &args[0])?;
+    let len = vector.lock().unwrap().len();
+    Ok(parser::SExpr::Atom(parser::Atom::Number(len as f64)))
+}
+
+// (vector-push! vector value) appends value and returns new length
+fn builtin_vector_push(
+    args: &[parser::SExpr],
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("vector-push!", args, 2)?;
+    let vector = expect_vector("vector-push!", &args[0])?;
+    ctx.charge_memory(std::mem::size_of::<parser::SExpr>())?;
+    let mut vector = vector.lock().unwrap();
+    vector.push(args[1].clone());
+    Ok(parser::SExpr::Atom(parser::Atom::Number(
+        vector.len() as f64
+    )))
+}
+
+fn builtin_vector_to_list(
+    args: &[parser::SExpr],
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("vector->list", args, 1)?;
+    let vector = expect_vector("vector->list", &args[0])?;
+    let list: Vec<parser::SExpr> = vector.lock().unwrap().clone();
+    ctx.charge_list_memory(list.len())?;
+    Ok(parser::SExpr::List(list))
+}
+
+fn builtin_list_to_vector(
+    args: &[parser::SExpr],
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("list->vector", args, 1)?;
+    new_vector(expect_list("list->vector", &args[0])?, ctx)
+}
+
+// Follows chain of references to the last variable cell, returning its address
+fn last_cell(value: &parser::SExpr) -> Option<*const Mutex<parser::SExpr>> {
+    let mut cell: Option<*const Mutex<parser::SExpr>> = None;
+    let mut value_buf: parser::SExpr = value.clone();
+    while let parser::SExpr::Ref(ref_val) = value_buf {
+        cell = Some(Arc::as_ptr(&ref_val));
+        value_buf = ref_val.lock().unwrap().clone();
+    }
+    cell
+}
+
+// Identity comparison; numbers are compared by `numbers_equal`
+fn values_identical(
+    value1: &parser::SExpr,
+    value2: &parser::SExpr,
+    numbers_equal: fn(f64, f64) -> bool,
+) -> bool {
+    if let (Some(cell1), Some(cell2)) = (last_cell(value1), last_cell(value2)) {
+        if cell1 == cell2 {
+            return true;
+        }
+    }
+    match (
+        evaluator::resolve_reference(value1),
+        evaluator::resolve_reference(value2),
+    ) {
+        (
+            parser::SExpr::Atom(parser::Atom::Number(num1)),
+            parser::SExpr::Atom(parser::Atom::Number(num2)),
+        ) => numbers_equal(num1, num2),
+        (
+            parser::SExpr::Atom(parser::Atom::Symbol(sym1)),
+            parser::SExpr::Atom(parser::Atom::Symbol(sym2)),
+        ) => sym1 == sym2,
+        (parser::SExpr::List(list1), parser::SExpr::List(list2)) => {
+            list1.is_empty() && list2.is_empty()
+        }
+        (parser::SExpr::Hash(table1), parser::SExpr::Hash(table2)) => Arc::ptr_eq(&table1, &table2),
+        (parser::SExpr::Vector(vector1), parser::SExpr::Vector(vector2)) => {
+            Arc::ptr_eq(&vector1, &vector2)
+        }
+        (parser::SExpr::RecordType(type1), parser::SExpr::RecordType(type2)) => {
+            Arc::ptr_eq(&type1, &type2)
+        }
+        (parser::SExpr::Record(record1), parser::SExpr::Record(record2)) => {
+            Arc::ptr_eq(&record1, &record2)
+        }
+        (parser::SExpr::Generator(generator1), parser::SExpr::Generator(generator2)) => {
+            Arc::ptr_eq(&generator1, &generator2)
+        }
+        (parser::SExpr::Thread(thread1), parser::SExpr::Thread(thread2)) => {
+            Arc::ptr_eq(&thread1, &thread2)
+        }
+        (parser::SExpr::Channel(channel1), parser::SExpr::Channel(channel2)) => {
+            Arc::ptr_eq(&channel1, &channel2)
+        }
+        (parser::SExpr::Lock(lock1), parser::SExpr::Lock(lock2)) => Arc::ptr_eq(&lock1, &lock2),
+        (parser::SExpr::Atomic(atomic1), parser::SExpr::Atomic(atomic2)) => {
+            Arc::ptr_eq(&atomic1, &atomic2)
+        }
+        (
+            parser::SExpr::Continuation(continuation1),
+            parser::SExpr::Continuation(continuation2),
+        ) => Arc::ptr_eq(&continuation1, &continuation2),
+        _ => false,
+    }
+}
+
+/// Deep structural equality, following references
+pub fn values_equal(value1: &parser::SExpr, value2: &parser::SExpr) -> bool {
+    values_equal_rec(value1, value2, &mut Vec::new())
+}
+
+// `in_progress` holds pairs of shared objects already being compared, so cyclic structures
+// are considered equal when no difference is found elsewhere.
+fn values_equal_rec(
+    value1: &parser::SExpr,
+    value2: &parser::SExpr,
+    in_progress: &mut Vec<(usize, usize)>,
+) -> bool {
+    let shared_pair: Option<(usize, usize)> = match (value1, value2) {
+        (parser::SExpr::Ref(ref1), parser::SExpr::Ref(ref2)) => {
+            Some((Arc::as_ptr(ref1) as usize, Arc::as_ptr(ref2) as usize))
+        }
+        (parser::SExpr::Hash(table1), parser::SExpr::Hash(table2)) => {
+            Some((Arc::as_ptr(table1) as usize, Arc::as_ptr(table2) as usize))
+        }
+        (parser::SExpr::Vector(vector1), parser::SExpr::Vector(vector2)) => {
+            Some((Arc::as_ptr(vector1) as usize, Arc::as_ptr(vector2) as usize))
+        }
+        (parser::SExpr::Record(record1), parser::SExpr::Record(record2)) => {
+            Some((Arc::as_ptr(record1) as usize, Arc::as_ptr(record2) as usize))
+        }
+        _ => None,
+    };
+    if let Some(shared_pair) = shared_pair {
+        if shared_pair.0 == shared_pair.1 || in_progress.contains(&shared_pair) {
+            return true;
+        }
+        in_progress.push(shared_pair);
+    }
+
+    let result = match (value1, value2) {
+        (parser::SExpr::Ref(ref1), _) => {
+            let value1 = ref1.lock().unwrap().clone();
+            values_equal_rec(&value1, value2, in_progress)
+        }
+        (_, parser::SExpr::Ref(ref2)) => {
+            let value2 = ref2.lock().unwrap().clone();
+            values_equal_rec(value1, &value2, in_progress)
+        }
+        (
+            parser::SExpr::Atom(parser::Atom::Number(num1)),
+            parser::SExpr::Atom(parser::Atom::Number(num2)),
+        ) => num1 == num2,
+        (
+            parser::SExpr::Atom(parser::Atom::Symbol(sym1)),
+            parser::SExpr::Atom(parser::Atom::Symbol(sym2)),
+        ) => sym1 == sym2,
+        (parser::SExpr::List(list1), parser::SExpr::List(list2)) => {
+            list1.len() == list2.len()
+                && list1
+                    .iter()
+                    .zip(list2)
+                    .all(|(elem1, elem2)| values_equal_rec(elem1, elem2, in_progress))
+        }
+        (parser::SExpr::Vector(vector1), parser::SExpr::Vector(vector2)) => {
+            let vector1 = vector1.lock().unwrap().clone();
+            let vector2 = vector2.lock().unwrap().clone();
+            vector1.len() == vector2.len()
+                && vector1
+                    .iter()
+                    .zip(&vector2)
+                    .all(|(elem1, elem2)| values_equal_rec(elem1, elem2, in_progress))
+        }
+        (parser::SExpr::Hash(table1), parser::SExpr::Hash(table2)) => {
+            let table1 = table1.lock().unwrap().clone();
+            let table2 = table2.lock().unwrap().clone();
+            table1.len() == table2.len()
+                && table1.iter().all(|(key, value1)| match table2.get(key) {
+                    Some(value2) => values_equal_rec(value1, value2, in_progress),
+                    None => false,
+                })
+        }
+        (parser::SExpr::RecordType(type1), parser::SExpr::RecordType(type2)) => {
+            Arc::ptr_eq(type1, type2)
+        }
+        (parser::SExpr::Generator(generator1), parser::SExpr::Generator(generator2)) => {
+            Arc::ptr_eq(generator1, generator2)
+        }
+        (parser::SExpr::Thread(thread1), parser::SExpr::Thread(thread2)) => {
+            Arc::ptr_eq(thread1, thread2)
+        }
+        (parser::SExpr::Channel(channel1), parser::SExpr::Channel(channel2)) => {
+            Arc::ptr_eq(channel1, channel2)
+        }
+        (parser::SExpr::Lock(lock1), parser::SExpr::Lock(lock2)) => Arc::ptr_eq(lock1, lock2),
+        (parser::SExpr::Atomic(atomic1), parser::SExpr::Atomic(atomic2)) => {
+            Arc::ptr_eq(atomic1, atomic2)
+        }
+        (
+            parser::SExpr::Continuation(continuation1),
+            parser::SExpr::Continuation(continuation2),
+        ) => Arc::ptr_eq(continuation1, continuation2),
+        (parser::SExpr::Record(record1), parser::SExpr::Record(record2)) => {
+            let record1 = record1.lock().unwrap().clone();
+            let record2 = record2.lock().unwrap().clone();
+            Arc::ptr_eq(&record1.record_type, &record2.record_type)
+                && record1
+                    .fields
+                    .iter()
+                    .zip(&record2.fields)
+                    .all(|(elem1, elem2)| values_equal_rec(elem1, elem2, in_progress))
+        }
+        _ => false,
+    };
+
+    if shared_pair.is_some() {
+        in_progress.pop();
+    }
+    result
+}
+
+fn builtin_equal(
+    args: &[parser::SExpr],
+    _ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("equal?", args, 2)?;
+    Ok(bool_value(values_equal(&args[0], &args[1])))
+}
+
+// Like `eq?`, but numbers are compared by value
+fn builtin_eqv(
+    args: &[parser::SExpr],
+    _ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("eqv?", args, 2)?;
+    Ok(bool_value(values_identical(
+        &args[0],
+        &args[1],
+        |num1, num2| num1 == num2,
+    )))
+}
+
+// Same variable, hash table, vector or record, same symbol, both empty lists, or numbers with the
+// same representation
+fn builtin_eq(
+    args: &[parser::SExpr],
+    _ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("eq?", args, 2)?;
+    Ok(bool_value(values_identical(
+        &args[0],
+        &args[1],
+        |num1, num2| num1.to_bits() == num2.to_bits(),
+    )))
+}
+
+fn builtin_is_symbol(
+    args: &[parser::SExpr],
+    _ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("symbol?", args, 1)?;
+    Ok(bool_value(matches!(
+        evaluator::resolve_reference(&args[0]),
+        parser::SExpr::Atom(parser::Atom::Symbol(_))
+    )))
+}
+
+fn builtin_symbol_to_string(
+    args: &[parser::SExpr],
+    _ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("symbol->string", args, 1)?;
+    match evaluator::resolve_reference(&args[0]) {
+        parser::SExpr::Atom(parser::Atom::Symbol(sym)) => Ok(conditions::string_value(&sym)),
+        _ => Err(builtin_error(String::from(
+            "`symbol->string` expects a symbol.",
+        ))),
+    }
+}
+
+// (string->symbol s): interned symbol, the same as one written in source code
+fn builtin_string_to_symbol(
+    args: &[parser::SExpr],
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("string->symbol", args, 1)?;
+    let not_string = || builtin_error(String::from("`string->symbol` expects a string."));
+    let list = match evaluator::resolve_reference(&args[0]) {
+        parser::SExpr::List(list) => list,
+        _ => return Err(not_string()),
+    };
+    let mut bytes: Vec<u8> = Vec::new();
+    for elem in &list {
+        match evaluator::resolve_reference(elem) {
+            parser::SExpr::Atom(parser::Atom::Number(ch))
+                if (0.0..=255.0).contains(&ch) && ch.fract() == 0.0 =>
+            {
+                bytes.push(ch as u8)
+            }
+            _ => return Err(not_string()),
+        }
+    }
+    let name = String::from_utf8(bytes).map_err(|_| not_string())?;
+    ctx.charge_symbol_memory(&name)?;
+    Ok(parser::SExpr::Atom(parser::Atom::Symbol(
+        symbols::Symbol::intern(&name),
+    )))
+}
+
+fn builtin_is_number(
+    args: &[parser::SExpr],
+    _ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("number?", args, 1)?;
+    Ok(bool_value(matches!(
+        evaluator::resolve_reference(&args[0]),
+        parser::SExpr::Atom(parser::Atom::Number(_))
+    )))
+}
+
+// Strings are lists of char codes; the empty list is also the empty string
+fn builtin_is_string(
+    args: &[parser::SExpr],
+    _ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("string?", args, 1)?;
+    let is_string = match evaluator::resolve_reference(&args[0]) {
+        parser::SExpr::List(list) => list.iter().all(|elem| {
+            matches!(
+                evaluator::resolve_reference(elem),
+                parser::SExpr::Atom(parser::Atom::Number(ch)) if (0.0..=255.0).contains(&ch) && ch.fract() == 0.0
+            )
+        }),
+        _ => false,
+    };
+    Ok(bool_value(is_string))
+}
+
+fn builtin_is_procedure(
+    args: &[parser::SExpr],
+    _ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("procedure?", args, 1)?;
+    let value = evaluator::resolve_reference(&args[0]);
+    Ok(bool_value(
+        value_is_lambda(&value)
+            || as_builtin(&value).is_some()
+            || matches!(value, parser::SExpr::Continuation(_)),
+    ))
+}
+
+fn value_is_lambda(value: &parser::SExpr) -> bool {
+    matches!(
+        value,
+        parser::SExpr::List(list) if matches!(
+            list.first(),
+            Some(parser::SExpr::Atom(parser::Atom::Symbol(tag))) if SpecialForm::LambdaCaptured.is(tag)
+        )
+    )
+}
+
+type Record = Arc<Mutex<parser::Record>>;
+
+fn expect_record_type(
+    value: &parser::SExpr,
+) -> Result<Arc<parser::RecordType>, Box<dyn std::error::Error + Send + Sync>> {
+    match evaluator::resolve_reference(value) {
+        parser::SExpr::RecordType(record_type) => Ok(record_type),
+        _ => Err(builtin_error(String::from(
+            "Record builtins expect a record type as 1st argument.",
+        ))),
+    }
+}
+
+// Record of type `record_type`; `name` is the accessor reported in errors
+fn expect_record_of_type(
+    name: &str,
+    record_type: &Arc<parser::RecordType>,
+    value: &parser::SExpr,
+) -> Result<Record, Box<dyn std::error::Error + Send + Sync>> {
+    match evaluator::resolve_reference(value) {
+        parser::SExpr::Record(record)
+            if Arc::ptr_eq(&record.lock().unwrap().record_type, record_type) =>
+        {
+            Ok(record)
+        }
+        _ => Err(builtin_error(format!(
+            "`{}` expects a record of type `{}`.",
+            name, record_type.name
+        ))),
+    }
+}
+
+// `#<name field: value ...>`; nested records are shown as `#<name ...>`
+fn display_record(record: &Record) -> String {
+    let record = record.lock().unwrap().clone();
+    let mut buf = format!("#<{}", record.record_type.name);
+    for (field, value) in record.record_type.fields.iter().zip(&record.fields) {
+        let value = match evaluator::resolve_reference(value) {
+            parser::SExpr::Atom(parser::Atom::Number(num)) => num.to_string(),
+            parser::SExpr::Atom(parser::Atom::Symbol(sym)) => sym.to_string(),
+            parser::SExpr::List(list) if list.is_empty() => String::from("()"),
+            parser::SExpr::List(_) => String::from("(...)"),
+            parser::SExpr::Hash(_) => String::from("{...}"),
+            parser::SExpr::Vector(_) => String::from("[...]"),
+            parser::SExpr::RecordType(record_type) => format!("#<type {}>", record_type.name),
+            parser::SExpr::Generator(_) => String::from("#<generator>"),
+            parser::SExpr::Thread(_) => String::from("#<thread>"),
+            parser::SExpr::Channel(_) => String::from("#<channel>"),
+            parser::SExpr::Lock(_) => String::from("#<mutex>"),
+            parser::SExpr::Atomic(atomic) => format!("#<atomic {}>", atomic.get()),
+            parser::SExpr::Continuation(_) => String::from("#<continuation>"),
+            parser::SExpr::Record(record) => match record.try_lock() {
+                Ok(record) => format!("#<{} ...>", record.record_type.name),
+                Err(_) => String::from("#<...>"),
+            },
+            parser::SExpr::Ref(_) => unreachable!(),
+        };
+        buf.push_str(&format!(" {}: {}", field, value));
+    }
+    buf.push('>');
+    buf
+}
+
+// (record-construct type field-value*), used by `make-<name>` procedures of `defstruct`
+fn builtin_record_construct(
+    args: &[parser::SExpr],
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    if args.is_empty() {
+        return Err(builtin_error(String::from(
+            "`record-construct` expects a record type and field values.",
+        )));
+    }
+    let record_type = expect_record_type(&args[0])?;
+    if args.len() - 1 != record_type.fields.len() {
+        return Err(builtin_error(format!(
+            "`make-{}` expects {} argument(s), got {}.",
+            record_type.name,
+            record_type.fields.len(),
+            args.len() - 1
+        )));
+    }
+    ctx.charge_list_memory(record_type.fields.len())?;
+    let record = Arc::new(Mutex::new(parser::Record {
+        record_type,
+        fields: args[1..].to_vec(),
+    }));
+    ctx.register_object(gc::Object::Record(record.clone()));
+    Ok(parser::SExpr::Record(record))
+}
+
+// (record-of-type? type value)
+fn builtin_record_of_type(
+    args: &[parser::SExpr],
+    _ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("record-of-type?", args, 2)?;
+    let record_type = expect_record_type(&args[0])?;
+    let is_of_type = match evaluator::resolve_reference(&args[1]) {
+        parser::SExpr::Record(record) => {
+            Arc::ptr_eq(&record.lock().unwrap().record_type, &record_type)
+        }
+        _ => false,
+    };
+    Ok(bool_value(is_of_type))
+}
+
+// (record-field type record index)
+fn builtin_record_field(
+    args: &[parser::SExpr],
+    _ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("record-field", args, 3)?;
+    let record_type = expect_record_type(&args[0])?;
+    let index = expect_index("record-field", &args[2], record_type.fields.len())?;
+    let name = format!("{}-{}", record_type.name, record_type.fields[index]);
+    let record = expect_record_of_type(&name, &record_type, &args[1])?;
+    let value = record.lock().unwrap().fields[index].clone();
+    Ok(value)
+}
+
+// (record-set-field! type record value index)
+fn builtin_record_set_field(
+    args: &[parser::SExpr],
+    _ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("record-set-field!", args, 4)?;
+    let record_type = expect_record_type(&args[0])?;
+    let index = expect_index("record-set-field!", &args[3], record_type.fields.len())?;
+    let name = format!("set-{}-{}!", record_type.name, record_type.fields[index]);
+    let record = expect_record_of_type(&name, &record_type, &args[1])?;
+    record.lock().unwrap().fields[index] = args[2].clone();
+    Ok(args[2].clone())
+}
+
+// (macroexpand-1 form): expands macro call `form` once; other forms are returned unchanged
+fn builtin_macroexpand_1(
+    args: &[parser::SExpr],
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("macroexpand-1", args, 1)?;
+    let form = evaluator::resolve_reference(&args[0]);
+    Ok(evaluator::macroexpand_1(&form, ctx)?.unwrap_or(form))
+}
+
+// (macroexpand form): expands `form` until it is no longer a macro call
+fn builtin_macroexpand(
+    args: &[parser::SExpr],
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("macroexpand", args, 1)?;
+    evaluator::macroexpand(&evaluator::resolve_reference(&args[0]), ctx)
+}
+
+// (gensym [prefix]): new uninterned symbol, which is not equal to any other symbol
+fn builtin_gensym(
+    args: &[parser::SExpr],
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    let prefix = match args {
+        [] => symbols::Symbol::intern("g"),
+        [prefix] => match evaluator::resolve_reference(prefix) {
+            parser::SExpr::Atom(parser::Atom::Symbol(prefix)) => prefix,
+            _ => {
+                return Err(builtin_error(String::from(
+                    "`gensym` expects a symbol prefix.",
+                )))
+            }
+        },
+        _ => {
+            return Err(builtin_error(format!(
+                "`gensym` expects at most 1 argument(s), got {}.",
+                args.len()
+            )))
+        }
+    };
+    Ok(parser::SExpr::Atom(parser::Atom::Symbol(
+        ctx.gensym(&prefix)?,
+    )))
+}
+
+fn expect_thunk(
+    name: &str,
+    value: &parser::SExpr,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    let thunk = evaluator::resolve_reference(value);
+    if value_is_lambda(&thunk) {
+        Ok(thunk)
+    } else {
+        Err(builtin_error(format!(
+            "`{}` expects a lambda without parameters.",
+            name
+        )))
+    }
+}
+
+// (spawn thunk): runs body of lambda `thunk` on a new thread, returns its handle
+fn builtin_spawn(
+    args: &[parser::SExpr],
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("spawn", args, 1)?;
+    concurrency::spawn(expect_thunk("spawn", &args[0])?, ctx)
+}
+
+// (join thread): waits for thread, returns value of its thunk or raises its error
+fn builtin_join(
+    args: &[parser::SExpr],
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("join", args, 1)?;
+    match evaluator::resolve_reference(&args[0]) {
+        parser::SExpr::Thread(thread) => concurrency::join(&thread, ctx),
+        _ => Err(builtin_error(String::from("`join` expects a thread."))),
+    }
+}
+
+fn builtin_channel(
+    args: &[parser::SExpr],
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("channel", args, 0)?;
+    Ok(concurrency::new_channel(ctx))
+}
+
+fn expect_channel(
+    name: &str,
+    value: &parser::SExpr,
+) -> Result<Arc<concurrency::Channel>, Box<dyn std::error::Error + Send + Sync>> {
+    match evaluator::resolve_reference(value) {
+        parser::SExpr::Channel(channel) => Ok(channel),
+        _ => Err(builtin_error(format!("`{}` expects a channel.", name))),
+    }
+}
+
+// (send channel value): returns `value`
+fn builtin_send(
+    args: &[parser::SExpr],
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("send", args, 2)?;
+    let channel = expect_channel("send", &args[0])?;
+    concurrency::send(&channel, args[1].clone(), ctx)?;
+    Ok(evaluator::resolve_reference(&args[1]))
+}
+
+// (recv channel): waits for value if channel is empty
+fn builtin_recv(
+    args: &[parser::SExpr],
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("recv", args, 1)?;
+    let channel = expect_channel("recv", &args[0])?;
+    concurrency::recv(&channel, ctx)
+}
+
+fn builtin_make_mutex(
+    args: &[parser::SExpr],
+    _ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("make-mutex", args, 0)?;
+    Ok(parser::SExpr::Lock(Arc::new(concurrency::Lock::default())))
+}
+
+// (with-lock mutex thunk): calls `thunk` holding `mutex`
+fn builtin_with_lock(
+    args: &[parser::SExpr],
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("with-lock", args, 2)?;
+    let lock = match evaluator::resolve_reference(&args[0]) {
+        parser::SExpr::Lock(lock) => lock,
+        _ => return Err(builtin_error(String::from("`with-lock` expects a mutex."))),
+    };
+    concurrency::with_lock(&lock, &expect_thunk("with-lock", &args[1])?, ctx)
+}
+
+// (make-atomic [value]); value defaults to 0
+fn builtin_make_atomic(
+    args: &[parser::SExpr],
+    _ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    let value = match args {
+        [] => 0.0,
+        [value] => expect_number("make-atomic", value)?,
+        _ => {
+            return Err(builtin_error(format!(
+                "`make-atomic` expects at most 1 argument(s), got {}.",
+                args.len()
+            )))
+        }
+    };
+    Ok(parser::SExpr::Atomic(Arc::new(concurrency::Atomic::new(
+        value,
+    ))))
+}
+
+fn expect_atomic(
+    name: &str,
+    value: &parser::SExpr,
+) -> Result<Arc<concurrency::Atomic>, Box<dyn std::error::Error + Send + Sync>> {
+    match evaluator::resolve_reference(value) {
+        parser::SExpr::Atomic(atomic) => Ok(atomic),
+        _ => Err(builtin_error(format!("`{}` expects an atomic.", name))),
+    }
+}
+
+fn builtin_atomic_get(
+    args: &[parser::SExpr],
+    _ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("atomic-get", args, 1)?;
+    Ok(parser::SExpr::Atom(parser::Atom::Number(
+        expect_atomic("atomic-get", &args[0])?.get(),
+    )))
+}
+
+// (atomic-set! atomic value): returns `value`
+fn builtin_atomic_set(
+    args: &[parser::SExpr],
+    _ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("atomic-set!", args, 2)?;
+    let value = expect_number("atomic-set!", &args[1])?;
+    expect_atomic("atomic-set!", &args[0])?.set(value);
+    Ok(parser::SExpr::Atom(parser::Atom::Number(value)))
+}
+
+// (atomic-add! atomic delta): returns the new value
+fn builtin_atomic_add(
+    args: &[parser::SExpr],
+    _ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("atomic-add!", args, 2)?;
+    let delta = expect_number("atomic-add!", &args[1])?;
+    Ok(parser::SExpr::Atom(parser::Atom::Number(
+        expect_atomic("atomic-add!", &args[0])?.add(delta),
+    )))
+}
+
+// (atomic-compare-and-set! atomic expected new): true if value was `expected` and is now `new`
+fn builtin_atomic_compare_and_set(
+    args: &[parser::SExpr],
+    _ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("atomic-compare-and-set!", args, 3)?;
+    let expected = expect_number("atomic-compare-and-set!", &args[1])?;
+    let new = expect_number("atomic-compare-and-set!", &args[2])?;
+    Ok(bool_value(
+        expect_atomic("atomic-compare-and-set!", &args[0])?.compare_and_set(expected, new),
+    ))
+}
+
+// (gc): frees values reachable only through reference cycles, returns their number
+fn builtin_gc(
+    args: &[parser::SExpr],
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("gc", args, 0)?;
+    Ok(parser::SExpr::Atom(parser::Atom::Number(
+        ctx.collect_garbage() as f64,
+    )))
+}
+
+// (heap-stats): hash with keys `objects`, `collections` and `reclaimed`
+fn builtin_heap_stats(
+    args: &[parser::SExpr],
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("heap-stats", args, 0)?;
+    let stats = ctx.heap_stats();
+    let table: HashMap<parser::HashKey, parser::SExpr> = [
+        ("objects", stats.objects as f64),
+        ("collections", stats.collections as f64),
+        ("reclaimed", stats.reclaimed as f64),
+    ]
+    .into_iter()
+    .map(|(key, value)| {
+        (
+            parser::HashKey::Symbol(symbols::Symbol::intern(key)),
+            parser::SExpr::Atom(parser::Atom::Number(value)),
+        )
+    })
+    .collect();
+    let table = Arc::new(Mutex::new(table));
+    ctx.register_object(gc::Object::Hash(table.clone()));
+    Ok(parser::SExpr::Hash(table))
+}
+
+fn builtin_raise(
+    args: &[parser::SExpr],
+    _ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("raise", args, 1)?;
+    Err(Box::new(conditions::Raised {
+        value: evaluator::resolve_reference(&args[0]),
+    }))
+}
+
+// (error message) or (error kind message): raises condition object, by default of kind `error`
+fn builtin_error_raise(
+    args: &[parser::SExpr],
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    let (kind, message): (String, parser::SExpr) = match args {
+        [message] => (String::from("error"), evaluator::resolve_reference(message)),
+        [kind, message] => match evaluator::resolve_reference(kind) {
+            parser::SExpr::Atom(parser::Atom::Symbol(kind)) => {
+                (kind.to_string(), evaluator::resolve_reference(message))
+            }
+            _ => {
+                return Err(builtin_error(String::from(
+                    "`error` expects a symbol kind.",
+                )))
+            }
+        },
+        _ => {
+            return Err(builtin_error(format!(
+                "`error` expects 1 or 2 argument(s), got {}.",
+                args.len()
+            )))
+        }
+    };
+    Err(Box::new(conditions::Raised {
+        value: conditions::make_condition(ctx, &kind, message),
+    }))
+}
+
+fn builtin_is_condition(
+    args: &[parser::SExpr],
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("condition?", args, 1)?;
+    Ok(bool_value(conditions::is_condition(ctx, &args[0])))
+}
+
+fn expect_condition_field(
+    name: &str,
+    args: &[parser::SExpr],
+    ctx: &evaluator::EvalContext,
+    index: usize,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count(name, args, 1)?;
+    match evaluator::resolve_reference(&args[0]) {
+        parser::SExpr::Record(record) if conditions::is_condition(ctx, &args[0]) => {
+            Ok(record.lock().unwrap().fields[index].clone())
+        }
+        _ => Err(builtin_error(format!("`{}` expects a condition.", name))),
+    }
+}
+
+fn builtin_condition_kind(
+    args: &[parser::SExpr],
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_condition_field("condition-kind", args, ctx, 0)
+}
+
+fn builtin_condition_message(
+    args: &[parser::SExpr],
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_condition_field("condition-message", args, ctx, 1)
+}
+
+fn builtin_call_cc(
+    args: &[parser::SExpr],
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("call/cc", args, 1)?;
+    continuations::call_with_current_continuation(&args[0], ctx)
+}
+
+fn as_generator(value: &parser::SExpr) -> Option<Arc<Mutex<generators::Generator>>> {
+    match evaluator::resolve_reference(value) {
+        parser::SExpr::Generator(generator) => Some(generator),
+        _ => None,
+    }
+}
+
+fn expect_generator(
+    name: &str,
+    value: &parser::SExpr,
+) -> Result<Arc<Mutex<generators::Generator>>, Box<dyn std::error::Error + Send + Sync>> {
+    as_generator(value).ok_or_else(|| builtin_error(format!("`{}` expects a generator.", name)))
+}
+
+// (generator thunk): body of lambda `thunk` runs lazily, up to the `yield` of each requested value
+fn builtin_generator(
+    args: &[parser::SExpr],
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("generator", args, 1)?;
+    Ok(generators::new_generator(
+        expect_thunk("generator", &args[0])?,
+        ctx,
+    ))
+}
+
+fn builtin_yield(
+    args: &[parser::SExpr],
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("yield", args, 1)?;
+    generators::yield_value(evaluator::resolve_reference(&args[0]), ctx)?;
+    Ok(parser::SExpr::List(vec![]))
+}
+
+fn builtin_next(
+    args: &[parser::SExpr],
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("next", args, 1)?;
+    generators::next(&expect_generator("next", &args[0])?, ctx)
+}
+
+fn builtin_is_done(
+    args: &[parser::SExpr],
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("done?", args, 1)?;
+    Ok(bool_value(generators::is_done(
+        &expect_generator("done?", &args[0])?,
+        ctx,
+    )?))
+}
+
+fn builtin_is_generator(
+    args: &[parser::SExpr],
+    _ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    expect_arg_count("generator?", args, 1)?;
+    Ok(bool_value(as_generator(&args[0]).is_some()))
+}
+
+// (generator->list generator [limit]): collects remaining values, at most `limit` of them
+fn builtin_generator_to_list(
+    args: &[parser::SExpr],
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    let (generator, limit): (_, Option<f64>) = match args {
+        [generator] => (expect_generator("generator->list", generator)?, None),
+        [generator, limit] => (
+            expect_generator("generator->list", generator)?,
+            Some(expect_number("generator->list", limit)?),
+        ),
+        _ => {
+            return Err(builtin_error(format!(
+                "`generator->list` expects 1 or 2 argument(s), got {}.",
+                args.len()
+            )))
+        }
+    };
+    let mut result: Vec<parser::SExpr> = Vec::new();
+    while limit.is_none_or(|limit| (result.len() as f64) < limit)
+        && !generators::is_done(&generator, ctx)?
+    {
+        result.push(generators::next(&generator, ctx)?);
+    }
+    ctx.charge_list_memory(result.len())?;
+    Ok(parser::SExpr::List(result))
+}
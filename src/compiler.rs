@@ -0,0 +1,426 @@
+use crate::builtins;
+use crate::evaluator;
+use crate::parser;
+use crate::symbols::{self, SpecialForm};
+
+use std::sync::{Arc, Mutex};
+
+/// Instruction of the stack VM. Operands are popped from the top of the value stack.
+pub(crate) enum Op {
+    Const(parser::SExpr),
+    // Pushes reference to variable in slot `n` of the current context
+    LoadSlot(usize),
+    // Variable looked up by name when it runs, as variables may be defined at run time
+    LoadName(symbols::Symbol),
+    // Stores the top value (leaving it on the stack) into variable
+    StoreSlot(usize),
+    StoreName(symbols::Symbol),
+    Pop,
+    // Replaces reference on the top of the stack by the value it refers to
+    Resolve,
+    // Starts and ends scope of `let` variables
+    EnterScope,
+    DefineVar(symbols::Symbol),
+    ExitScope,
+    Jump(usize),
+    JumpIfFalse(usize),
+    // Pops values of captured variables and creates lambda
+    MakeLambda(Arc<LambdaSite>),
+    CallBuiltin(builtins::BuiltinFn, usize),
+    // Pops arguments and value to call; `name` and `line` of the call form are used in
+    // traceback
+    Call {
+        argc: usize,
+        name: Arc<str>,
+        line: Option<usize>,
+        tail: bool,
+    },
+    // Form evaluated by the tree-walking evaluator, e.g. `try` or malformed special form
+    Fallback(parser::SExpr),
+    Return,
+}
+
+/// `lambda` expression in compiled code
+pub(crate) struct LambdaSite {
+    captured: Vec<symbols::Symbol>,
+    params: parser::SExpr,
+    // Body shared by all lambdas created here, which makes it possible to find its bytecode
+    body: Arc<Mutex<parser::SExpr>>,
+    // Estimated size of body, accounted against memory limit for every created lambda
+    body_size: usize,
+    // Line of the `lambda` form, kept by created lambdas for tracebacks
+    line: Option<usize>,
+}
+
+impl LambdaSite {
+    // Lambda `(lambda-captured ((name value)*) params body)` with body kept in a cell
+    pub(crate) fn make_lambda(&self, captured_values: Vec<parser::SExpr>) -> parser::SExpr {
+        let captured_vars: Vec<parser::SExpr> = self
+            .captured
+            .iter()
+            .zip(captured_values)
+            .map(|(name, value)| {
+                parser::SExpr::List(vec![
+                    parser::SExpr::Atom(parser::Atom::Symbol(name.clone())),
+                    value,
+                ])
+            })
+            .collect();
+        parser::SExpr::List(vec![
+            parser::SExpr::Atom(parser::Atom::Symbol(
+                SpecialForm::LambdaCaptured.symbol().at_line(self.line),
+            )),
+            parser::SExpr::List(captured_vars),
+            self.params.clone(),
+            parser::SExpr::Ref(self.body.clone()),
+        ])
+    }
+
+    // Size of lambda as if its body was stored in it directly
+    pub(crate) fn lambda_size(&self, lambda: &parser::SExpr) -> usize {
+        evaluator::value_size(lambda) - std::mem::size_of::<parser::SExpr>() + self.body_size
+    }
+
+    pub(crate) fn captured_count(&self) -> usize {
+        self.captured.len()
+    }
+}
+
+/// Compiled top-level expression or lambda body
+pub(crate) struct Chunk {
+    pub(crate) ops: Vec<Op>,
+}
+
+struct Compiler<'a> {
+    ops: Vec<Op>,
+    // Names of variables of the context, in the same order. `None` if the expression may define
+    // variables at run time (`defstruct`, macro calls), so variables must be looked up by name.
+    scope: Option<Vec<symbols::Symbol>>,
+    ctx: &'a evaluator::EvalContext,
+}
+
+/// Compiles `sexpr` to be run in context with variables named `var_names`
+pub(crate) fn compile(
+    sexpr: &parser::SExpr,
+    var_names: Vec<symbols::Symbol>,
+    ctx: &evaluator::EvalContext,
+) -> Chunk {
+    let mut compiler = Compiler {
+        ops: Vec::new(),
+        scope: (!defines_variables(sexpr, ctx)).then_some(var_names),
+        ctx,
+    };
+    compiler.compile_expr(sexpr, true);
+    compiler.ops.push(Op::Return);
+    Chunk { ops: compiler.ops }
+}
+
+fn as_symbol(sexpr: &parser::SExpr) -> Option<symbols::Symbol> {
+    match sexpr {
+        parser::SExpr::Atom(parser::Atom::Symbol(sym)) => Some(sym.clone()),
+        _ => None,
+    }
+}
+
+// Whether evaluation of `sexpr` may add variables to its context, outside of `let`
+fn defines_variables(sexpr: &parser::SExpr, ctx: &evaluator::EvalContext) -> bool {
+    let list = match sexpr {
+        parser::SExpr::List(list) => list,
+        // Referenced value is evaluated as code
+        parser::SExpr::Ref(_) => return true,
+        _ => return false,
+    };
+    let any_defines =
+        |elems: &[parser::SExpr]| elems.iter().any(|elem| defines_variables(elem, ctx));
+    // Subexpressions of `let` variable definitions, `cond` and `try` clauses, skipping names
+    let any_clause_defines = |clauses: &[parser::SExpr], skip: usize| {
+        clauses.iter().any(|clause| match clause {
+            parser::SExpr::List(clause) => any_defines(clause.get(skip..).unwrap_or(&[])),
+            _ => false,
+        })
+    };
+    match list.first() {
+        Some(parser::SExpr::Atom(parser::Atom::Symbol(sym))) => match SpecialForm::of(sym) {
+            // Lambda body has its own context
+            Some(
+                SpecialForm::Quote
+                | SpecialForm::Lambda
+                | SpecialForm::LambdaCaptured
+                | SpecialForm::Builtin
+                | SpecialForm::Defmacro
+                | SpecialForm::DefineSyntax,
+            ) => false,
+            Some(SpecialForm::Defstruct | SpecialForm::Quasiquote) => true,
+            Some(SpecialForm::Let) => {
+                list.len() >= 2
+                    && (any_clause_defines(&list[1..list.len() - 1], 1)
+                        || defines_variables(&list[list.len() - 1], ctx))
+            }
+            Some(SpecialForm::Cond) => any_clause_defines(&list[1..], 0),
+            Some(SpecialForm::Try) => {
+                list.len() >= 2
+                    && (defines_variables(&list[1], ctx) || any_clause_defines(&list[2..], 1))
+            }
+            Some(_) => any_defines(&list[1..]),
+            None if !ctx.is_macro(sym) && builtins::lookup(sym).is_some() => {
+                any_defines(&list[1..])
+            }
+            // Macro call, possibly of macro defined at run time
+            _ => true,
+        },
+        Some(parser::SExpr::List(_)) => any_defines(list),
+        _ => false,
+    }
+}
+
+impl Compiler<'_> {
+    fn emit(&mut self, op: Op) -> usize {
+        self.ops.push(op);
+        self.ops.len() - 1
+    }
+
+    fn patch_jump(&mut self, at: usize) {
+        let target = self.ops.len();
+        match &mut self.ops[at] {
+            Op::Jump(to) | Op::JumpIfFalse(to) => *to = target,
+            _ => unreachable!(),
+        }
+    }
+
+    fn fallback(&mut self, sexpr: &parser::SExpr) {
+        self.emit(Op::Fallback(sexpr.clone()));
+    }
+
+    fn compile_expr(&mut self, sexpr: &parser::SExpr, tail: bool) {
+        match sexpr {
+            parser::SExpr::Atom(parser::Atom::Symbol(sym)) => self.compile_symbol(sym.clone()),
+            parser::SExpr::List(list) => match list.first() {
+                None => {
+                    self.emit(Op::Const(parser::SExpr::List(vec![])));
+                }
+                Some(parser::SExpr::List(_)) => self.compile_block(list, tail),
+                Some(parser::SExpr::Atom(parser::Atom::Symbol(sym))) => {
+                    self.compile_form(sym.clone(), sexpr, list, tail)
+                }
+                // Errors are reported by the evaluator when the expression runs
+                Some(_) => self.fallback(sexpr),
+            },
+            parser::SExpr::Ref(_) => self.fallback(sexpr),
+            _ => {
+                self.emit(Op::Const(sexpr.clone()));
+            }
+        }
+    }
+
+    fn compile_symbol(&mut self, sym: symbols::Symbol) {
+        if sym.starts_with(':') {
+            self.emit(Op::Const(parser::SExpr::Atom(parser::Atom::Symbol(sym))));
+            return;
+        }
+        match &self.scope {
+            Some(scope) => match scope.iter().rposition(|name| *name == sym) {
+                Some(slot) => self.emit(Op::LoadSlot(slot)),
+                None if builtins::lookup(&sym).is_some() => {
+                    self.emit(Op::Const(builtins::builtin_value(&sym)))
+                }
+                None => self.emit(Op::LoadName(sym)),
+            },
+            None => self.emit(Op::LoadName(sym)),
+        };
+    }
+
+    // List of expressions, the last of which gives the value
+    fn compile_block(&mut self, list: &[parser::SExpr], tail: bool) {
+        for elem in &list[..list.len() - 1] {
+            self.compile_expr(elem, false);
+            self.emit(Op::Pop);
+        }
+        self.compile_expr(&list[list.len() - 1], tail);
+    }
+
+    // Only well-formed special forms are compiled, others are left to the evaluator, so that
+    // they fail with the same error at the same point of evaluation
+    fn compile_form(
+        &mut self,
+        sym: symbols::Symbol,
+        sexpr: &parser::SExpr,
+        list: &[parser::SExpr],
+        tail: bool,
+    ) {
+        match SpecialForm::of(&sym) {
+            Some(SpecialForm::Let) if is_well_formed_let(list) => self.compile_let(list, tail),
+            Some(SpecialForm::Set) if list.len() == 3 && as_symbol(&list[1]).is_some() => {
+                self.compile_set(as_symbol(&list[1]).unwrap(), &list[2])
+            }
+            Some(SpecialForm::If) if list.len() == 3 || list.len() == 4 => {
+                self.compile_if(list, tail)
+            }
+            Some(SpecialForm::Cond) if is_well_formed_cond(list) => {
+                self.compile_cond(&list[1..], tail)
+            }
+            Some(SpecialForm::While) if list.len() == 3 => self.compile_while(&list[1], &list[2]),
+            Some(SpecialForm::Lambda) if is_well_formed_lambda(list) => self.compile_lambda(list),
+            Some(SpecialForm::Call) if list.len() >= 2 => self.compile_call(list, tail),
+            Some(SpecialForm::Quote) if list.len() == 2 => {
+                self.emit(Op::Const(list[1].clone()));
+            }
+            Some(SpecialForm::LambdaCaptured | SpecialForm::Builtin) => {
+                self.emit(Op::Const(sexpr.clone()));
+            }
+            Some(_) => self.fallback(sexpr),
+            None if self.ctx.is_macro(&sym) => self.fallback(sexpr),
+            None => match builtins::lookup(&sym) {
+                Some(builtin) => {
+                    for arg in &list[1..] {
+                        self.compile_expr(arg, false);
+                    }
+                    self.emit(Op::CallBuiltin(builtin, list.len() - 1));
+                }
+                // Possibly macro defined at run time
+                None => self.fallback(sexpr),
+            },
+        }
+    }
+
+    fn compile_let(&mut self, list: &[parser::SExpr], tail: bool) {
+        self.emit(Op::EnterScope);
+        let scope_len = self.scope.as_ref().map(|scope| scope.len());
+        for var_def in &list[1..list.len() - 1] {
+            if let parser::SExpr::List(var_def) = var_def {
+                let name = as_symbol(&var_def[0]).unwrap();
+                self.compile_expr(&var_def[1], false);
+                self.emit(Op::DefineVar(name.clone()));
+                if let Some(scope) = &mut self.scope {
+                    scope.push(name);
+                }
+            }
+        }
+        self.compile_expr(&list[list.len() - 1], tail);
+        self.emit(Op::ExitScope);
+        if let (Some(scope), Some(scope_len)) = (&mut self.scope, scope_len) {
+            scope.truncate(scope_len);
+        }
+    }
+
+    fn compile_set(&mut self, name: symbols::Symbol, value: &parser::SExpr) {
+        self.compile_expr(value, false);
+        let slot = self
+            .scope
+            .as_ref()
+            .and_then(|scope| scope.iter().rposition(|var| *var == name));
+        match slot {
+            Some(slot) => self.emit(Op::StoreSlot(slot)),
+            None => self.emit(Op::StoreName(name)),
+        };
+    }
+
+    fn compile_if(&mut self, list: &[parser::SExpr], tail: bool) {
+        self.compile_expr(&list[1], false);
+        let to_else = self.emit(Op::JumpIfFalse(0));
+        self.compile_expr(&list[2], tail);
+        let to_end = self.emit(Op::Jump(0));
+        self.patch_jump(to_else);
+        match list.get(3) {
+            Some(else_branch) => self.compile_expr(else_branch, tail),
+            None => {
+                self.emit(Op::Const(parser::SExpr::List(vec![])));
+            }
+        }
+        self.patch_jump(to_end);
+    }
+
+    fn compile_cond(&mut self, clauses: &[parser::SExpr], tail: bool) {
+        let mut to_end: Vec<usize> = Vec::new();
+        let mut has_else = false;
+        for clause in clauses {
+            let clause = match clause {
+                parser::SExpr::List(clause) => clause,
+                _ => unreachable!(),
+            };
+            if as_symbol(&clause[0]).is_some_and(|sym| sym == "else") {
+                // Following clauses are never reached
+                self.compile_expr(&clause[1], tail);
+                has_else = true;
+                break;
+            }
+            self.compile_expr(&clause[0], false);
+            let to_next = self.emit(Op::JumpIfFalse(0));
+            self.compile_expr(&clause[1], tail);
+            to_end.push(self.emit(Op::Jump(0)));
+            self.patch_jump(to_next);
+        }
+        if !has_else {
+            self.emit(Op::Const(parser::SExpr::List(vec![])));
+        }
+        for at in to_end {
+            self.patch_jump(at);
+        }
+    }
+
+    // Value of `while` is the value of its last iteration, or `()`
+    fn compile_while(&mut self, cond: &parser::SExpr, body: &parser::SExpr) {
+        self.emit(Op::Const(parser::SExpr::List(vec![])));
+        let start = self.ops.len();
+        self.compile_expr(cond, false);
+        let to_end = self.emit(Op::JumpIfFalse(0));
+        self.emit(Op::Pop);
+        self.compile_expr(body, false);
+        self.emit(Op::Jump(start));
+        self.patch_jump(to_end);
+    }
+
+    fn compile_lambda(&mut self, list: &[parser::SExpr]) {
+        let captured: Vec<symbols::Symbol> = match &list[1] {
+            parser::SExpr::List(captured) => captured
+                .iter()
+                .map(|name| as_symbol(name).unwrap())
+                .collect(),
+            _ => unreachable!(),
+        };
+        for name in &captured {
+            self.compile_symbol(name.clone());
+        }
+        self.emit(Op::MakeLambda(Arc::new(LambdaSite {
+            captured,
+            params: list[2].clone(),
+            body: Arc::new(Mutex::new(list[3].clone())),
+            body_size: evaluator::value_size(&list[3]),
+            line: evaluator::symbol_line(&list[0]),
+        })));
+    }
+
+    fn compile_call(&mut self, list: &[parser::SExpr], tail: bool) {
+        self.compile_expr(&list[1], false);
+        // Value to call is taken before arguments are evaluated
+        self.emit(Op::Resolve);
+        for arg in &list[2..] {
+            self.compile_expr(arg, false);
+        }
+        self.emit(Op::Call {
+            argc: list.len() - 2,
+            name: Arc::from(as_symbol(&list[1]).as_deref().unwrap_or("<lambda>")),
+            line: evaluator::symbol_line(&list[0]),
+            tail,
+        });
+    }
+}
+
+fn is_well_formed_let(list: &[parser::SExpr]) -> bool {
+    list.len() >= 3
+        && list[1..list.len() - 1].iter().all(|var_def| {
+            matches!(var_def, parser::SExpr::List(var_def)
+                if var_def.len() == 2 && as_symbol(&var_def[0]).is_some())
+        })
+}
+
+fn is_well_formed_cond(list: &[parser::SExpr]) -> bool {
+    list[1..]
+        .iter()
+        .all(|clause| matches!(clause, parser::SExpr::List(clause) if clause.len() == 2))
+}
+
+fn is_well_formed_lambda(list: &[parser::SExpr]) -> bool {
+    list.len() == 4
+        && matches!(&list[1], parser::SExpr::List(captured)
+            if captured.iter().all(|name| as_symbol(name).is_some()))
+}
@@ -0,0 +1,275 @@
+use crate::conditions;
+use crate::evaluator;
+use crate::limits::{self, LimitError};
+use crate::parser;
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Stack size of threads started by `spawn`, the same as of generator threads. The stack is
+/// accounted against the memory limit while the thread is running.
+pub(crate) const THREAD_STACK_SIZE: usize = 8 * 1024 * 1024;
+
+// Blocked `join`, `recv` and `with-lock` wake up this often to check whether evaluation was
+// cancelled or timed out
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// Result of thunk run by thread, kept so that it can be joined more than once
+type Outcome = Result<parser::SExpr, Arc<dyn std::error::Error + Send + Sync>>;
+
+enum ThreadState {
+    Running(mpsc::Receiver<Outcome>),
+    Finished(Outcome),
+}
+
+/// Handle of thread started by `spawn`
+pub struct Thread {
+    state: Mutex<ThreadState>,
+}
+
+/// Unbounded channel created by `channel`; any thread holding it can send and receive.
+/// Values waiting in it are accounted against the memory limit.
+pub struct Channel {
+    // Values with their estimated sizes
+    sender: mpsc::Sender<(parser::SExpr, usize)>,
+    receiver: Mutex<mpsc::Receiver<(parser::SExpr, usize)>>,
+    // Bytes waiting in all channels of the interpreter which created this one
+    buffered: Arc<AtomicUsize>,
+}
+
+/// Mutex created by `make-mutex`. It isn't reentrant: locking it again in the thread holding it
+/// fails instead of deadlocking.
+#[derive(Default)]
+pub struct Lock {
+    owner: Mutex<Option<thread::ThreadId>>,
+    released: Condvar,
+}
+
+/// Number created by `make-atomic`, updated atomically by `atomic-add!` and
+/// `atomic-compare-and-set!`
+pub struct Atomic {
+    bits: AtomicU64,
+}
+
+impl std::fmt::Debug for Thread {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Thread")
+    }
+}
+
+impl std::fmt::Debug for Channel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Channel")
+    }
+}
+
+impl std::fmt::Debug for Lock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Lock")
+    }
+}
+
+impl std::fmt::Debug for Atomic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Atomic({})", self.get())
+    }
+}
+
+fn concurrency_error(message: String) -> Box<dyn std::error::Error + Send + Sync> {
+    Box::new(std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        message,
+    ))
+}
+
+/// Runs body of lambda `thunk` on a new thread, sharing variables it captures and the
+/// interpreter's limits with the calling thread. The thread is stopped when the evaluation
+/// which started it ends.
+pub fn spawn(
+    thunk: parser::SExpr,
+    ctx: &evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    let live = ctx.start_thread()?;
+    let (sender, receiver) = mpsc::channel();
+    let mut thread_ctx = ctx.new_thread_context();
+    let thread_live = live.clone();
+    let thread = thread::Builder::new()
+        .stack_size(THREAD_STACK_SIZE)
+        .spawn(move || {
+            limits::set_stack_limit(THREAD_STACK_SIZE);
+            let outcome: Outcome = evaluator::apply(&thunk, vec![], &mut thread_ctx)
+                .map(|value| evaluator::resolve_reference(&value))
+                .map_err(Arc::from);
+            // Released before the outcome is sent, so that thread which joined this one can
+            // start another right away
+            thread_live.fetch_sub(1, Ordering::SeqCst);
+            let _ = sender.send(outcome);
+        })
+        .inspect_err(|_| {
+            live.fetch_sub(1, Ordering::SeqCst);
+        })?;
+    ctx.register_thread(thread);
+    Ok(parser::SExpr::Thread(Arc::new(Thread {
+        state: Mutex::new(ThreadState::Running(receiver)),
+    })))
+}
+
+/// Waits for thread to finish and returns value of its thunk, or raises its error again.
+/// Joining finished thread again gives the same result.
+pub fn join(
+    thread: &Thread,
+    ctx: &evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    let mut state = thread.state.lock().unwrap();
+    if let ThreadState::Running(receiver) = &*state {
+        let outcome = loop {
+            match receiver.recv_timeout(POLL_INTERVAL) {
+                Ok(outcome) => break outcome,
+                Err(mpsc::RecvTimeoutError::Timeout) => ctx.check_interrupted()?,
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    break Err(Arc::from(concurrency_error(String::from(
+                        "thread panicked",
+                    ))))
+                }
+            }
+        };
+        *state = ThreadState::Finished(outcome);
+    }
+    match &*state {
+        ThreadState::Finished(Ok(value)) => Ok(value.clone()),
+        ThreadState::Finished(Err(err)) => match err.downcast_ref::<conditions::Raised>() {
+            Some(raised) => Err(Box::new(conditions::Raised {
+                value: raised.value.clone(),
+            })),
+            // Limits apply to the whole interpreter, so their errors aren't turned into
+            // catchable ones
+            None => match err.downcast_ref::<LimitError>() {
+                Some(limit_error) => Err(Box::new(limit_error.clone())),
+                None => Err(concurrency_error(format!("Joined thread failed: {}", err))),
+            },
+        },
+        ThreadState::Running(_) => unreachable!(),
+    }
+}
+
+pub fn new_channel(ctx: &evaluator::EvalContext) -> parser::SExpr {
+    let (sender, receiver) = mpsc::channel();
+    parser::SExpr::Channel(Arc::new(Channel {
+        sender,
+        receiver: Mutex::new(receiver),
+        buffered: ctx.channel_bytes(),
+    }))
+}
+
+/// Adds value to channel, accounting it against the memory limit until it is received
+pub fn send(
+    channel: &Channel,
+    value: parser::SExpr,
+    ctx: &evaluator::EvalContext,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let value = evaluator::resolve_reference(&value);
+    let size = evaluator::value_size(&value);
+    ctx.charge_memory(size)?;
+    channel.buffered.fetch_add(size, Ordering::SeqCst);
+    // Channel holds its own receiver, so it can't be disconnected
+    let _ = channel.sender.send((value, size));
+    Ok(())
+}
+
+/// Oldest value sent to channel, waiting for one if it's empty
+pub fn recv(
+    channel: &Channel,
+    ctx: &evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    let receiver = channel.receiver.lock().unwrap();
+    loop {
+        match receiver.recv_timeout(POLL_INTERVAL) {
+            Ok((value, size)) => {
+                channel.buffered.fetch_sub(size, Ordering::SeqCst);
+                return Ok(value);
+            }
+            Err(_) => ctx.check_interrupted()?,
+        }
+    }
+}
+
+/// Calls lambda `thunk` holding `lock`, which is released however the call ends
+pub fn with_lock(
+    lock: &Lock,
+    thunk: &parser::SExpr,
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    let current = thread::current().id();
+    {
+        let mut owner = lock.owner.lock().unwrap();
+        loop {
+            match *owner {
+                None => break,
+                Some(thread) if thread == current => {
+                    return Err(concurrency_error(String::from(
+                        "`with-lock`: mutex is already held by this thread.",
+                    )))
+                }
+                Some(_) => {
+                    owner = lock.released.wait_timeout(owner, POLL_INTERVAL).unwrap().0;
+                    ctx.check_interrupted()?;
+                }
+            }
+        }
+        *owner = Some(current);
+    }
+    let result = evaluator::apply(thunk, vec![], ctx);
+    *lock.owner.lock().unwrap() = None;
+    lock.released.notify_one();
+    result
+}
+
+impl Drop for Channel {
+    // Values never received stop counting against the memory limit
+    fn drop(&mut self) {
+        if let Ok(receiver) = self.receiver.get_mut() {
+            for (_, size) in receiver.try_iter() {
+                self.buffered.fetch_sub(size, Ordering::SeqCst);
+            }
+        }
+    }
+}
+
+impl Atomic {
+    pub fn new(value: f64) -> Atomic {
+        Atomic {
+            bits: AtomicU64::new(value.to_bits()),
+        }
+    }
+
+    pub fn get(&self) -> f64 {
+        f64::from_bits(self.bits.load(Ordering::SeqCst))
+    }
+
+    pub fn set(&self, value: f64) {
+        self.bits.store(value.to_bits(), Ordering::SeqCst);
+    }
+
+    /// Adds `delta` and returns the new value
+    pub fn add(&self, delta: f64) -> f64 {
+        let old = self
+            .bits
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |bits| {
+                Some((f64::from_bits(bits) + delta).to_bits())
+            })
+            .unwrap();
+        f64::from_bits(old) + delta
+    }
+
+    /// Sets value to `new` if it equals `expected`, returning whether it did
+    pub fn compare_and_set(&self, expected: f64, new: f64) -> bool {
+        self.bits
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |bits| {
+                (f64::from_bits(bits) == expected).then_some(new.to_bits())
+            })
+            .is_ok()
+    }
+}
@@ -0,0 +1,165 @@
+use crate::continuations;
+use crate::evaluator;
+use crate::gc;
+use crate::generators;
+use crate::limits;
+use crate::parser;
+use crate::symbols;
+
+use std::sync::{Arc, Mutex};
+
+/// Value thrown by `raise` or `error`. It propagates like other evaluation errors until caught
+/// by `try`; if uncaught, the embedder can recognise it with `err.downcast_ref::<Raised>()`.
+#[derive(Debug, Clone)]
+pub struct Raised {
+    pub value: parser::SExpr,
+}
+
+impl std::fmt::Display for Raised {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match condition_fields(&self.value) {
+            Some((kind, message)) => {
+                write!(f, "{}: {}", describe_value(&kind), describe_value(&message))
+            }
+            None => write!(f, "Uncaught raised value: {}", describe_value(&self.value)),
+        }
+    }
+}
+
+impl std::error::Error for Raised {}
+
+/// Record type of condition objects, with fields `kind` (symbol) and `message` (string)
+pub(crate) fn new_condition_type() -> Arc<parser::RecordType> {
+    Arc::new(parser::RecordType {
+        name: String::from("condition"),
+        fields: vec![String::from("kind"), String::from("message")],
+    })
+}
+
+pub(crate) fn string_value(s: &str) -> parser::SExpr {
+    parser::SExpr::List(
+        s.bytes()
+            .map(|b| parser::SExpr::Atom(parser::Atom::Number(b as f64)))
+            .collect(),
+    )
+}
+
+pub(crate) fn make_condition(
+    ctx: &evaluator::EvalContext,
+    kind: &str,
+    message: parser::SExpr,
+) -> parser::SExpr {
+    let record = Arc::new(Mutex::new(parser::Record {
+        record_type: ctx.condition_type(),
+        fields: vec![
+            parser::SExpr::Atom(parser::Atom::Symbol(symbols::Symbol::intern(kind))),
+            message,
+        ],
+    }));
+    ctx.register_object(gc::Object::Record(record.clone()));
+    parser::SExpr::Record(record)
+}
+
+// Kind and message of condition object, or `None` if `value` is not a condition
+fn condition_fields(value: &parser::SExpr) -> Option<(parser::SExpr, parser::SExpr)> {
+    match evaluator::resolve_reference(value) {
+        parser::SExpr::Record(record) => {
+            let record = record.lock().unwrap();
+            if record.record_type.name == "condition" && record.fields.len() == 2 {
+                Some((record.fields[0].clone(), record.fields[1].clone()))
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+pub(crate) fn is_condition(ctx: &evaluator::EvalContext, value: &parser::SExpr) -> bool {
+    match evaluator::resolve_reference(value) {
+        parser::SExpr::Record(record) => {
+            Arc::ptr_eq(&record.lock().unwrap().record_type, &ctx.condition_type())
+        }
+        _ => false,
+    }
+}
+
+// Text of string value: non-empty list of printable char codes
+pub(crate) fn string_text(value: &parser::SExpr) -> Option<String> {
+    match evaluator::resolve_reference(value) {
+        parser::SExpr::List(list) if !list.is_empty() => list
+            .iter()
+            .map(|elem| match evaluator::resolve_reference(elem) {
+                parser::SExpr::Atom(parser::Atom::Number(ch))
+                    if (0.0..=255.0).contains(&ch) && ch.fract() == 0.0 =>
+                {
+                    let ch = ch as u8 as char;
+                    (!ch.is_control() || ch.is_whitespace()).then_some(ch)
+                }
+                _ => None,
+            })
+            .collect(),
+        _ => None,
+    }
+}
+
+// Strings are shown as text, other non-empty lists as `(...)`
+pub(crate) fn describe_value(value: &parser::SExpr) -> String {
+    if let Some(text) = string_text(value) {
+        return text;
+    }
+    match evaluator::resolve_reference(value) {
+        parser::SExpr::Atom(parser::Atom::Number(num)) => num.to_string(),
+        parser::SExpr::Atom(parser::Atom::Symbol(sym)) => sym.to_string(),
+        parser::SExpr::List(list) if list.is_empty() => String::from("()"),
+        parser::SExpr::List(_) => String::from("(...)"),
+        parser::SExpr::Hash(_) => String::from("{...}"),
+        parser::SExpr::Vector(_) => String::from("[...]"),
+        parser::SExpr::RecordType(record_type) => format!("#<type {}>", record_type.name),
+        parser::SExpr::Generator(_) => String::from("#<generator>"),
+        parser::SExpr::Thread(_) => String::from("#<thread>"),
+        parser::SExpr::Channel(_) => String::from("#<channel>"),
+        parser::SExpr::Lock(_) => String::from("#<mutex>"),
+        parser::SExpr::Atomic(atomic) => format!("#<atomic {}>", atomic.get()),
+        parser::SExpr::Continuation(_) => String::from("#<continuation>"),
+        parser::SExpr::Record(record) => {
+            format!("#<{} ...>", record.lock().unwrap().record_type.name)
+        }
+        parser::SExpr::Ref(_) => unreachable!(),
+    }
+}
+
+/// Value bound to the variable of `catch` clause when evaluation fails with `err`:
+/// the raised value itself, or condition object describing interpreter error.
+/// Exhausted fuel, timeout, cancellation and exceeded memory limit can't be caught,
+/// so that scripts can't escape limits set by the embedder; neither can escape to continuation
+/// or closing of generator.
+pub(crate) fn catch(
+    ctx: &evaluator::EvalContext,
+    err: &(dyn std::error::Error + 'static),
+) -> Option<parser::SExpr> {
+    if let Some(raised) = err.downcast_ref::<Raised>() {
+        return Some(raised.value.clone());
+    }
+    if err.is::<continuations::ContinuationInvoked>() || err.is::<generators::GeneratorClosed>() {
+        return None;
+    }
+    let kind: &str = if let Some(limit_error) = err.downcast_ref::<limits::LimitError>() {
+        match limit_error {
+            limits::LimitError::RecursionDepthExceeded { .. } => "recursion-depth-exceeded",
+            _ => return None,
+        }
+    } else if let Some(io_error) = err.downcast_ref::<std::io::Error>() {
+        match io_error.kind() {
+            std::io::ErrorKind::NotFound => "undefined-variable",
+            std::io::ErrorKind::InvalidInput => "invalid-input",
+            std::io::ErrorKind::InvalidData => "invalid-data",
+            _ => "io",
+        }
+    } else if err.is::<std::num::ParseFloatError>() {
+        "parse-error"
+    } else {
+        "error"
+    };
+    Some(make_condition(ctx, kind, string_value(&err.to_string())))
+}
@@ -0,0 +1,77 @@
+use crate::evaluator;
+use crate::parser;
+
+use std::sync::Arc;
+
+/// Escaping continuation created by `call/cc`. Scripts can't forge one: the `call/cc` it
+/// belongs to recognises it by identity, not by its number.
+#[derive(Debug)]
+pub struct Continuation {
+    id: u64,
+}
+
+/// Unwinds evaluation from invoked continuation up to its `call/cc`.
+/// It is not caught by `try`, but cleanups of `finally` and `unwind-protect` run.
+#[derive(Debug, Clone)]
+pub struct ContinuationInvoked {
+    pub continuation: Arc<Continuation>,
+    pub value: parser::SExpr,
+}
+
+impl std::fmt::Display for ContinuationInvoked {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Continuation {} invoked after its `call/cc` returned.",
+            self.continuation.id
+        )
+    }
+}
+
+impl std::error::Error for ContinuationInvoked {}
+
+/// Invokes `continuation` with optional value `args`, unwinding to its `call/cc`
+pub(crate) fn invoke(
+    continuation: &Arc<Continuation>,
+    args: &[parser::SExpr],
+) -> Box<dyn std::error::Error + Send + Sync> {
+    match args {
+        [] | [_] => Box::new(ContinuationInvoked {
+            continuation: continuation.clone(),
+            value: args
+                .first()
+                .map_or(parser::SExpr::List(vec![]), evaluator::resolve_reference),
+        }),
+        _ => Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!(
+                "Continuation expects 0 or 1 argument(s), got {}.",
+                args.len()
+            ),
+        )),
+    }
+}
+
+/// Calls `func` with escaping continuation of this call. Invoking the continuation while
+/// `func` runs makes this call return the value passed to it; continuations can't be
+/// resumed after the call returned.
+pub fn call_with_current_continuation(
+    func: &parser::SExpr,
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    let continuation = Arc::new(Continuation {
+        id: ctx.next_continuation_id(),
+    });
+    let value = parser::SExpr::Continuation(continuation.clone());
+    match evaluator::apply(func, vec![value], ctx) {
+        Err(err) => match err.downcast::<ContinuationInvoked>() {
+            Ok(invoked) if Arc::ptr_eq(&invoked.continuation, &continuation) => {
+                ctx.take_traceback();
+                Ok(invoked.value)
+            }
+            Ok(invoked) => Err(invoked),
+            Err(err) => Err(err),
+        },
+        result => result,
+    }
+}
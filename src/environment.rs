@@ -0,0 +1,97 @@
+use crate::parser;
+use crate::symbols;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone)]
+pub(crate) struct Variable {
+    pub(crate) name: symbols::Symbol,
+    pub(crate) value: Arc<Mutex<parser::SExpr>>,
+}
+
+// Environments with fewer variables are searched linearly, which is faster than hashing
+const INDEX_THRESHOLD: usize = 16;
+
+/// Variables visible in evaluation context, innermost last. Scopes are entered by pushing
+/// variables and left by truncating back, so nothing is copied.
+///
+/// The bytecode compiler resolves variables to positions here, but the tree-walker looks
+/// them up by name every time: it evaluates source forms as they are, without a pass to
+/// resolve them, which keeps it simple enough to serve as reference for the compiler.
+/// Lookup by name scans the few variables of small environments and uses the name index
+/// in larger ones.
+#[derive(Clone, Default)]
+pub(crate) struct Environment {
+    vars: Vec<Variable>,
+    // Built once the environment grows past `INDEX_THRESHOLD` variables
+    index: Option<NameIndex>,
+}
+
+#[derive(Clone, Default)]
+struct NameIndex {
+    // Position of the innermost variable of every name
+    innermost: HashMap<symbols::Symbol, usize>,
+    // For every variable, position of the variable of the same name it shadows
+    shadowed: Vec<Option<usize>>,
+}
+
+impl NameIndex {
+    fn push(&mut self, name: symbols::Symbol, pos: usize) {
+        self.shadowed.push(self.innermost.insert(name, pos));
+    }
+}
+
+impl Environment {
+    pub(crate) fn len(&self) -> usize {
+        self.vars.len()
+    }
+
+    /// Cell of variable at position `pos`, as resolved by the compiler
+    pub(crate) fn cell(&self, pos: usize) -> &Arc<Mutex<parser::SExpr>> {
+        &self.vars[pos].value
+    }
+
+    pub(crate) fn names(&self) -> Vec<symbols::Symbol> {
+        self.vars.iter().map(|var| var.name.clone()).collect()
+    }
+
+    pub(crate) fn lookup(&self, name: &symbols::Symbol) -> Option<&Arc<Mutex<parser::SExpr>>> {
+        let pos = match &self.index {
+            Some(index) => *index.innermost.get(name)?,
+            None => self.vars.iter().rposition(|var| var.name == *name)?,
+        };
+        Some(&self.vars[pos].value)
+    }
+
+    pub(crate) fn push(&mut self, name: symbols::Symbol, value: Arc<Mutex<parser::SExpr>>) {
+        match &mut self.index {
+            Some(index) => index.push(name.clone(), self.vars.len()),
+            None if self.vars.len() + 1 >= INDEX_THRESHOLD => {
+                let mut index = NameIndex::default();
+                for (pos, var) in self.vars.iter().enumerate() {
+                    index.push(var.name.clone(), pos);
+                }
+                index.push(name.clone(), self.vars.len());
+                self.index = Some(index);
+            }
+            None => {}
+        }
+        self.vars.push(Variable { name, value });
+    }
+
+    /// Drops variables defined after the first `len` ones
+    pub(crate) fn truncate(&mut self, len: usize) {
+        if let Some(index) = &mut self.index {
+            for pos in (len..self.vars.len()).rev() {
+                let name = self.vars[pos].name.clone();
+                match index.shadowed[pos] {
+                    Some(shadowed) => index.innermost.insert(name, shadowed),
+                    None => index.innermost.remove(&name),
+                };
+            }
+            index.shadowed.truncate(len);
+        }
+        self.vars.truncate(len);
+    }
+}
@@ -1,45 +1,698 @@
+use crate::builtins;
+use crate::compiler;
+use crate::concurrency;
+use crate::conditions;
+use crate::continuations;
+use crate::environment::{Environment, Variable};
+use crate::gc;
+use crate::generators;
+use crate::limits;
 use crate::parser;
+use crate::symbols::{self, SpecialForm};
+use crate::syntax_rules;
+use crate::traceback;
+use crate::vm;
 
-use std::rc::Rc;
-use std::sync::Mutex;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::Instant;
+
+pub const DEFAULT_MAX_RECURSION_DEPTH: usize = 1000;
+
+/// Default number of generator bodies which may be running or suspended at once
+pub const DEFAULT_MAX_GENERATORS: usize = 256;
+
+/// Default number of threads started by `spawn` which may be running at once
+pub const DEFAULT_MAX_THREADS: usize = 64;
+
+// State shared by all contexts of one interpreter
+struct EvalState {
+    max_depth: usize,
+    // Remaining evaluation steps, unlimited if `None`
+    fuel: Option<u64>,
+    deadline: Option<Instant>,
+    cancel_handle: limits::CancelHandle,
+    steps: u64,
+    memory_limit: Option<usize>,
+    // Estimated memory usage in bytes: size of live variables at the last measurement,
+    // plus everything allocated since then
+    memory_used: usize,
+    // All shared values created by the interpreter, used to measure live memory and to
+    // collect cycles
+    heap: gc::Heap,
+    macros: HashMap<symbols::Symbol, Macro>,
+    // Number of symbols generated by `gensym`
+    gensym_counter: u64,
+    // Number of continuations created by `call/cc`
+    continuation_counter: u64,
+    // Generators whose bodies were started, closed when the evaluation ends, and their
+    // number after the last removal of freed ones
+    generators: Vec<Weak<Mutex<generators::Generator>>>,
+    generators_pruned_len: usize,
+    // Number of generator bodies running or suspended, each holding a thread
+    live_generators: Arc<AtomicUsize>,
+    max_generators: usize,
+    // Threads started by `spawn`, stopped and joined when the evaluation ends, and their
+    // number after the last removal of finished ones
+    threads: Vec<thread::JoinHandle<()>>,
+    threads_pruned_len: usize,
+    // Number of threads started by `spawn` still running
+    live_threads: Arc<AtomicUsize>,
+    max_threads: usize,
+    // Set while threads left running by the ended evaluation are being stopped
+    stopping_threads: bool,
+    // Estimated size of values sent to channels and not received yet
+    channel_bytes: Arc<AtomicUsize>,
+    // Record type of condition objects caught by `try`
+    condition_type: Arc<parser::RecordType>,
+    // Bytecode of lambda bodies compiled so far, by address of the cell holding the body
+    lambda_chunks: HashMap<usize, (Weak<Mutex<parser::SExpr>>, Arc<compiler::Chunk>)>,
+}
+
+// Call stack of the main evaluation or of one generator, which runs on its own stack
+#[derive(Default)]
+struct CallStack {
+    // Lambdas being called, innermost last
+    frames: Vec<traceback::Frame>,
+    // Call stack at the point where the last error was raised, and address of that error
+    traceback: Vec<traceback::Frame>,
+    traceback_error: Option<usize>,
+    // Channels of generator running on this stack, used by `yield`
+    coroutine: Option<Arc<generators::CoroutineLink>>,
+}
 
 #[derive(Clone)]
-struct Variable {
-    name: String,
-    value: Rc<Mutex<parser::SExpr>>,
+enum Macro {
+    // Expander lambda defined by `defmacro`
+    Procedure(parser::SExpr),
+    SyntaxRules(Arc<syntax_rules::SyntaxRules>),
+}
+
+// Deadline is checked only every this many steps, as reading clock is relatively slow
+const DEADLINE_CHECK_INTERVAL: u64 = 256;
+
+// Estimated size of variable cell, not including its value
+const CELL_SIZE: usize =
+    std::mem::size_of::<Variable>() + std::mem::size_of::<Mutex<parser::SExpr>>() + 16;
+
+impl EvalState {
+    fn measure_memory(&mut self) {
+        let stacks = self.live_generators.load(Ordering::SeqCst) * generators::GENERATOR_STACK_SIZE
+            + self.live_threads.load(Ordering::SeqCst) * concurrency::THREAD_STACK_SIZE;
+        self.memory_used = self
+            .heap
+            .live_cells()
+            .iter()
+            .map(|cell| {
+                // Cell being modified right now is skipped
+                CELL_SIZE + cell.try_lock().map_or(0, |value| value_size(&value))
+            })
+            .sum::<usize>()
+            + stacks
+            + self.channel_bytes.load(Ordering::SeqCst);
+    }
+}
+
+// Estimated size of hash table entry in bytes
+pub(crate) fn hash_entry_size(key: &parser::HashKey, value: &parser::SExpr) -> usize {
+    std::mem::size_of::<parser::HashKey>()
+        + match key {
+            parser::HashKey::Number(_) => 0,
+            parser::HashKey::String(bytes) => bytes.len(),
+            parser::HashKey::Symbol(sym) => sym.len(),
+        }
+        + value_size(value)
+}
+
+// Estimated size of value in bytes. Referenced cells are accounted separately.
+pub(crate) fn value_size(value: &parser::SExpr) -> usize {
+    std::mem::size_of::<parser::SExpr>()
+        + match value {
+            parser::SExpr::Atom(parser::Atom::Symbol(sym)) => sym.len(),
+            parser::SExpr::List(list) => list.iter().map(value_size).sum(),
+            // Table already locked higher in the recursion contains itself and is skipped
+            parser::SExpr::Hash(table) => table.try_lock().map_or(0, |table| {
+                table
+                    .iter()
+                    .map(|(key, value)| hash_entry_size(key, value))
+                    .sum()
+            }),
+            parser::SExpr::Vector(vector) => vector
+                .try_lock()
+                .map_or(0, |vector| vector.iter().map(value_size).sum()),
+            parser::SExpr::Record(record) => record
+                .try_lock()
+                .map_or(0, |record| record.fields.iter().map(value_size).sum()),
+            _ => 0,
+        }
 }
 
 #[derive(Clone)]
 pub struct EvalContext {
-    vars: Vec<Variable>,
+    pub(crate) vars: Environment,
+    state: Arc<Mutex<EvalState>>,
+    stack: Arc<Mutex<CallStack>>,
+}
+
+impl Default for EvalContext {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl EvalContext {
     pub fn new() -> EvalContext {
-        EvalContext { vars: Vec::new() }
+        EvalContext {
+            vars: Environment::default(),
+            state: Arc::new(Mutex::new(EvalState {
+                max_depth: DEFAULT_MAX_RECURSION_DEPTH,
+                fuel: None,
+                deadline: None,
+                cancel_handle: limits::CancelHandle::new(),
+                steps: 0,
+                memory_limit: None,
+                memory_used: 0,
+                heap: gc::Heap::default(),
+                macros: HashMap::new(),
+                gensym_counter: 0,
+                continuation_counter: 0,
+                generators: Vec::new(),
+                generators_pruned_len: 0,
+                live_generators: Arc::new(AtomicUsize::new(0)),
+                max_generators: DEFAULT_MAX_GENERATORS,
+                threads: Vec::new(),
+                threads_pruned_len: 0,
+                live_threads: Arc::new(AtomicUsize::new(0)),
+                max_threads: DEFAULT_MAX_THREADS,
+                stopping_threads: false,
+                channel_bytes: Arc::new(AtomicUsize::new(0)),
+                condition_type: conditions::new_condition_type(),
+                lambda_chunks: HashMap::new(),
+            })),
+            stack: Arc::new(Mutex::new(CallStack::default())),
+        }
+    }
+
+    // Context of lambda body: no variables, but shared interpreter state
+    fn new_call_context(&self) -> EvalContext {
+        EvalContext {
+            vars: Environment::default(),
+            state: self.state.clone(),
+            stack: self.stack.clone(),
+        }
+    }
+
+    // Context of generator body, with its own call stack
+    pub(crate) fn new_coroutine_context(&self, link: generators::CoroutineLink) -> EvalContext {
+        EvalContext {
+            vars: Environment::default(),
+            state: self.state.clone(),
+            stack: Arc::new(Mutex::new(CallStack {
+                coroutine: Some(Arc::new(link)),
+                ..CallStack::default()
+            })),
+        }
+    }
+
+    // Context of thread started by `spawn`, with its own call stack
+    pub(crate) fn new_thread_context(&self) -> EvalContext {
+        EvalContext {
+            vars: Environment::default(),
+            state: self.state.clone(),
+            stack: Arc::new(Mutex::new(CallStack::default())),
+        }
+    }
+
+    // Channels of generator whose body is being evaluated in this context
+    pub(crate) fn coroutine_link(&self) -> Option<Arc<generators::CoroutineLink>> {
+        self.stack.lock().unwrap().coroutine.clone()
+    }
+
+    pub fn max_recursion_depth(&self) -> usize {
+        self.state.lock().unwrap().max_depth
+    }
+
+    pub fn set_max_recursion_depth(&mut self, max_depth: usize) {
+        self.state.lock().unwrap().max_depth = max_depth;
+    }
+
+    pub fn recursion_depth(&self) -> usize {
+        self.stack.lock().unwrap().frames.len()
+    }
+
+    /// Takes call stack at the point where the last evaluation error was raised
+    pub fn take_traceback(&mut self) -> Vec<traceback::Frame> {
+        let mut stack = self.stack.lock().unwrap();
+        stack.traceback_error = None;
+        std::mem::take(&mut stack.traceback)
+    }
+
+    // Called on every level `err` passes through, so only the first (innermost) call stack
+    // is remembered. `failed_call` is the call which failed before its frame was pushed.
+    pub(crate) fn record_traceback(
+        &self,
+        err: &dyn std::error::Error,
+        failed_call: Option<traceback::Frame>,
+    ) {
+        let err_address = err as *const dyn std::error::Error as *const () as usize;
+        let mut stack = self.stack.lock().unwrap();
+        if stack.traceback_error != Some(err_address) {
+            stack.traceback_error = Some(err_address);
+            stack.traceback = stack.frames.clone();
+            stack.traceback.extend(failed_call);
+        }
+    }
+
+    // Pushes frame of non-tail lambda call, failing if it would exceed maximum recursion depth
+    pub(crate) fn push_call_frame(
+        &self,
+        frame: traceback::Frame,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let max_depth = self.max_recursion_depth();
+        let mut stack = self.stack.lock().unwrap();
+        if stack.frames.len() >= max_depth {
+            let mut call_chain: Vec<String> = stack
+                .frames
+                .iter()
+                .map(|frame| frame.name.clone())
+                .collect();
+            call_chain.push(frame.name);
+            return Err(Box::new(limits::LimitError::RecursionDepthExceeded {
+                limit: max_depth,
+                call_chain,
+            }));
+        }
+        stack.frames.push(frame);
+        Ok(())
+    }
+
+    // Tail call replaces frame of the current call
+    pub(crate) fn replace_call_frame(&self, frame: traceback::Frame) {
+        *self.stack.lock().unwrap().frames.last_mut().unwrap() = frame;
+    }
+
+    pub(crate) fn pop_call_frame(&self) {
+        self.stack.lock().unwrap().frames.pop();
+    }
+
+    pub(crate) fn var_names(&self) -> Vec<symbols::Symbol> {
+        self.vars.names()
+    }
+
+    // Bytecode of lambda body held by `body_cell`, compiled on its first call
+    pub(crate) fn lambda_chunk(
+        &self,
+        body_cell: &Arc<Mutex<parser::SExpr>>,
+        compile: impl FnOnce() -> compiler::Chunk,
+    ) -> Arc<compiler::Chunk> {
+        let key = Arc::as_ptr(body_cell) as usize;
+        if let Some((cell, chunk)) = self.state.lock().unwrap().lambda_chunks.get(&key) {
+            // Address of dropped cell may have been reused
+            if cell
+                .upgrade()
+                .is_some_and(|cell| Arc::ptr_eq(&cell, body_cell))
+            {
+                return chunk.clone();
+            }
+        }
+        let chunk = Arc::new(compile());
+        let mut state = self.state.lock().unwrap();
+        if state.lambda_chunks.len() >= 1024 && state.lambda_chunks.len().is_power_of_two() {
+            state
+                .lambda_chunks
+                .retain(|_, (cell, _)| cell.strong_count() > 0);
+        }
+        state
+            .lambda_chunks
+            .insert(key, (Arc::downgrade(body_cell), chunk.clone()));
+        chunk
+    }
+
+    pub fn fuel(&self) -> Option<u64> {
+        self.state.lock().unwrap().fuel
+    }
+
+    /// Limits number of evaluation steps; `None` removes the limit. Every call of
+    /// `check_limits` is a step: the evaluator makes it for every expression,
+    /// the bytecode VM only for top-level expressions, lambda calls and loop iterations.
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.state.lock().unwrap().fuel = fuel;
+    }
+
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.state.lock().unwrap().deadline = deadline;
+    }
+
+    pub fn cancel_handle(&self) -> limits::CancelHandle {
+        self.state.lock().unwrap().cancel_handle.clone()
+    }
+
+    /// Limits estimated memory used by values of variables and by newly allocated lists;
+    /// `None` removes the limit
+    pub fn set_memory_limit(&mut self, memory_limit: Option<usize>) {
+        self.state.lock().unwrap().memory_limit = memory_limit;
+    }
+
+    /// Measures memory used by values of all live variables
+    pub fn memory_usage(&self) -> usize {
+        let mut state = self.state.lock().unwrap();
+        state.measure_memory();
+        state.memory_used
+    }
+
+    // Accounts allocation of `bytes` against the memory limit
+    pub(crate) fn charge_memory(
+        &self,
+        bytes: usize,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut state = self.state.lock().unwrap();
+        state.memory_used = state.memory_used.saturating_add(bytes);
+        if let Some(memory_limit) = state.memory_limit {
+            if state.memory_used > memory_limit {
+                // Part of the estimate may have been freed since the last measurement
+                state.measure_memory();
+                state.memory_used = state.memory_used.saturating_add(bytes);
+                if state.memory_used > memory_limit {
+                    return Err(Box::new(limits::LimitError::MemoryLimitExceeded {
+                        limit: memory_limit,
+                        used: state.memory_used,
+                    }));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Accounts allocation of new list with `len` elements against the memory limit
+    pub fn charge_list_memory(
+        &self,
+        len: usize,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.charge_memory(
+            std::mem::size_of::<parser::SExpr>().saturating_mul(len.saturating_add(1)),
+        )
+    }
+
+    // Accounts symbol named `name` created by script against the memory limit
+    pub(crate) fn charge_symbol_memory(
+        &self,
+        name: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.charge_memory(std::mem::size_of::<parser::SExpr>().saturating_add(name.len()))
+    }
+
+    // Creates variable cell holding `value`
+    pub(crate) fn new_cell(
+        &self,
+        value: parser::SExpr,
+    ) -> Result<Arc<Mutex<parser::SExpr>>, Box<dyn std::error::Error + Send + Sync>> {
+        self.charge_memory(CELL_SIZE + value_size(&value))?;
+        let cell = Arc::new(Mutex::new(value));
+        self.register_object(gc::Object::Cell(cell.clone()));
+        Ok(cell)
+    }
+
+    // Tracks shared value, so that it can be collected when it becomes part of garbage cycle
+    pub(crate) fn register_object(&self, object: gc::Object) {
+        self.state.lock().unwrap().heap.register(&object);
+    }
+
+    /// Frees values reachable only through reference cycles, returning their number
+    pub fn collect_garbage(&self) -> usize {
+        let objects = self.state.lock().unwrap().heap.live_objects();
+        let reclaimed = gc::collect(objects);
+        self.state.lock().unwrap().heap.finish_collection(reclaimed);
+        reclaimed
+    }
+
+    pub fn heap_stats(&self) -> gc::HeapStats {
+        self.state.lock().unwrap().heap.stats()
+    }
+
+    // Called once per evaluation step
+    pub(crate) fn check_limits(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if limits::stack_exhausted() {
+            let call_chain: Vec<String> = self
+                .stack
+                .lock()
+                .unwrap()
+                .frames
+                .iter()
+                .map(|frame| frame.name.clone())
+                .collect();
+            return Err(Box::new(limits::LimitError::StackExhausted { call_chain }));
+        }
+        let mut state = self.state.lock().unwrap();
+        if state.cancel_handle.is_cancelled() || state.stopping_threads {
+            return Err(Box::new(limits::LimitError::Cancelled));
+        }
+        if let Some(fuel) = state.fuel {
+            if fuel == 0 {
+                return Err(Box::new(limits::LimitError::FuelExhausted));
+            }
+            state.fuel = Some(fuel - 1);
+        }
+        state.steps += 1;
+        if let Some(deadline) = state.deadline {
+            if state.steps.is_multiple_of(DEADLINE_CHECK_INTERVAL) && Instant::now() >= deadline {
+                return Err(Box::new(limits::LimitError::Timeout));
+            }
+        }
+        let collect = state.heap.take_collection_pending();
+        drop(state);
+        if collect {
+            self.collect_garbage();
+        }
+        Ok(())
+    }
+
+    // Fails if evaluation was cancelled or timed out; checked while waiting for other threads
+    pub(crate) fn check_interrupted(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let state = self.state.lock().unwrap();
+        if state.cancel_handle.is_cancelled() || state.stopping_threads {
+            return Err(Box::new(limits::LimitError::Cancelled));
+        }
+        if state
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            return Err(Box::new(limits::LimitError::Timeout));
+        }
+        Ok(())
+    }
+
+    pub fn is_macro(&self, name: &symbols::Symbol) -> bool {
+        self.state.lock().unwrap().macros.contains_key(name)
+    }
+
+    /// Returns new uninterned symbol named `prefix#N`, different from every other symbol.
+    /// Its name is accounted against the memory limit.
+    pub fn gensym(
+        &self,
+        prefix: &str,
+    ) -> Result<symbols::Symbol, Box<dyn std::error::Error + Send + Sync>> {
+        let name = {
+            let mut state = self.state.lock().unwrap();
+            state.gensym_counter += 1;
+            format!("{}#{}", prefix, state.gensym_counter)
+        };
+        self.charge_symbol_memory(&name)?;
+        Ok(symbols::Symbol::uninterned(name))
+    }
+
+    pub fn max_generators(&self) -> usize {
+        self.state.lock().unwrap().max_generators
+    }
+
+    pub fn set_max_generators(&mut self, max_generators: usize) {
+        self.state.lock().unwrap().max_generators = max_generators;
+    }
+
+    // Reserves place for body of `generator` about to be started, accounting its stack against
+    // the memory limit. Returns counter of running bodies, which the body decrements when its
+    // thread ends.
+    pub(crate) fn start_generator(
+        &self,
+        generator: &Arc<Mutex<generators::Generator>>,
+    ) -> Result<Arc<AtomicUsize>, Box<dyn std::error::Error + Send + Sync>> {
+        self.charge_memory(generators::GENERATOR_STACK_SIZE)?;
+        let mut state = self.state.lock().unwrap();
+        if state.live_generators.load(Ordering::SeqCst) >= state.max_generators {
+            return Err(Box::new(limits::LimitError::GeneratorLimitExceeded {
+                limit: state.max_generators,
+            }));
+        }
+        state.live_generators.fetch_add(1, Ordering::SeqCst);
+        if state.generators.len() >= 2 * state.generators_pruned_len.max(16) {
+            state
+                .generators
+                .retain(|generator| generator.strong_count() > 0);
+            state.generators_pruned_len = state.generators.len();
+        }
+        state.generators.push(Arc::downgrade(generator));
+        Ok(state.live_generators.clone())
+    }
+
+    /// Closes generators whose bodies are suspended in `yield`, running their cleanups, so
+    /// that their threads end with the evaluation which started them. `next` finds them
+    /// exhausted afterwards.
+    pub(crate) fn close_generators(&self) {
+        loop {
+            // Cleanups may start new generators
+            let started = std::mem::take(&mut self.state.lock().unwrap().generators);
+            if started.is_empty() {
+                break;
+            }
+            for generator in started.iter().filter_map(Weak::upgrade) {
+                generators::close(&generator);
+            }
+        }
+    }
+
+    pub fn max_threads(&self) -> usize {
+        self.state.lock().unwrap().max_threads
+    }
+
+    pub fn set_max_threads(&mut self, max_threads: usize) {
+        self.state.lock().unwrap().max_threads = max_threads;
+    }
+
+    // Reserves place for thread about to be started by `spawn`, accounting its stack against
+    // the memory limit. Returns counter of running threads, which the thread decrements when
+    // it ends.
+    pub(crate) fn start_thread(
+        &self,
+    ) -> Result<Arc<AtomicUsize>, Box<dyn std::error::Error + Send + Sync>> {
+        self.charge_memory(concurrency::THREAD_STACK_SIZE)?;
+        let state = self.state.lock().unwrap();
+        if state.stopping_threads {
+            return Err(Box::new(limits::LimitError::Cancelled));
+        }
+        if state.live_threads.load(Ordering::SeqCst) >= state.max_threads {
+            return Err(Box::new(limits::LimitError::ThreadLimitExceeded {
+                limit: state.max_threads,
+            }));
+        }
+        state.live_threads.fetch_add(1, Ordering::SeqCst);
+        Ok(state.live_threads.clone())
     }
+
+    // Remembers thread started by `spawn`, so that it is stopped when the evaluation ends
+    pub(crate) fn register_thread(&self, thread: thread::JoinHandle<()>) {
+        let mut state = self.state.lock().unwrap();
+        if state.threads.len() >= 2 * state.threads_pruned_len.max(16) {
+            state.threads.retain(|thread| !thread.is_finished());
+            state.threads_pruned_len = state.threads.len();
+        }
+        state.threads.push(thread);
+    }
+
+    /// Stops threads started by `spawn` which are still running, failing them with
+    /// [`limits::LimitError::Cancelled`] at their next step, and waits for them to end, so
+    /// that no thread outlives the evaluation which started it
+    pub(crate) fn stop_threads(&self) {
+        self.state.lock().unwrap().stopping_threads = true;
+        loop {
+            // Threads may start new ones until they notice they are stopped
+            let started = std::mem::take(&mut self.state.lock().unwrap().threads);
+            if started.is_empty() {
+                break;
+            }
+            for thread in started {
+                let _ = thread.join();
+            }
+        }
+        self.state.lock().unwrap().stopping_threads = false;
+    }
+
+    // Counter of bytes buffered in channels, shared by channels created by the interpreter
+    pub(crate) fn channel_bytes(&self) -> Arc<AtomicUsize> {
+        self.state.lock().unwrap().channel_bytes.clone()
+    }
+
+    pub(crate) fn next_continuation_id(&self) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        state.continuation_counter += 1;
+        state.continuation_counter
+    }
+
+    pub(crate) fn condition_type(&self) -> Arc<parser::RecordType> {
+        self.state.lock().unwrap().condition_type.clone()
+    }
+
+    pub(crate) fn lookup_var(&self, name: &symbols::Symbol) -> Option<Arc<Mutex<parser::SExpr>>> {
+        self.vars.lookup(name).cloned()
+    }
+
+    pub fn get_var(&self, name: &str) -> Option<parser::SExpr> {
+        self.lookup_var(&symbols::Symbol::intern(name))
+            .map(|value| resolve_reference(&parser::SExpr::Ref(value)))
+    }
+
+    pub fn set_var(&mut self, name: &str, value: parser::SExpr) {
+        let name = symbols::Symbol::intern(name);
+        if let Some(var_value) = self.lookup_var(&name) {
+            *var_value.lock().unwrap() = value;
+        } else {
+            // Variables defined by embedder are not subject to the memory limit
+            let value = Arc::new(Mutex::new(value));
+            self.register_object(gc::Object::Cell(value.clone()));
+            self.vars.push(name, value);
+        }
+    }
+}
+
+pub(crate) fn eval_symbol(
+    sym: &symbols::Symbol,
+    ctx: &EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    if sym.starts_with(':') {
+        // Keywords evaluate to themselves
+        return Ok(parser::SExpr::Atom(parser::Atom::Symbol(sym.clone())));
+    }
+    match ctx.lookup_var(sym) {
+        Some(value) => Ok(parser::SExpr::Ref(value)),
+        // Name of builtin function not shadowed by variable evaluates to the function
+        None if builtins::lookup(sym).is_some() => Ok(builtins::builtin_value(sym)),
+        None => Err(undefined_variable(sym)),
+    }
+}
+
+pub(crate) fn undefined_variable(
+    sym: &symbols::Symbol,
+) -> Box<dyn std::error::Error + Send + Sync> {
+    Box::new(std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        format!("Variable {} not defined.", sym),
+    ))
 }
 
-fn value_is_true(value: &parser::SExpr) -> bool {
+pub(crate) fn value_is_true(value: &parser::SExpr) -> bool {
     let value = resolve_reference(value);
     if let parser::SExpr::Atom(parser::Atom::Number(num)) = value {
-        if num == 0.0 {
-            false
-        } else {
-            true
-        }
+        num != 0.0
     } else if let parser::SExpr::List(cond_list) = value {
-        if cond_list.len() == 0 {
-            false
-        } else {
-            true
-        }
+        !cond_list.is_empty()
     } else {
-        false
+        matches!(
+            value,
+            parser::SExpr::Hash(_)
+                | parser::SExpr::Vector(_)
+                | parser::SExpr::RecordType(_)
+                | parser::SExpr::Record(_)
+                | parser::SExpr::Generator(_)
+                | parser::SExpr::Thread(_)
+                | parser::SExpr::Channel(_)
+                | parser::SExpr::Lock(_)
+                | parser::SExpr::Atomic(_)
+                | parser::SExpr::Continuation(_)
+        )
     }
 }
 
-fn resolve_reference(value: &parser::SExpr) -> parser::SExpr {
+pub fn resolve_reference(value: &parser::SExpr) -> parser::SExpr {
     let mut value_buf: parser::SExpr = value.clone();
     while let parser::SExpr::Ref(ref_val) = value_buf {
         value_buf = (*ref_val.lock().unwrap()).clone();
@@ -47,494 +700,503 @@ fn resolve_reference(value: &parser::SExpr) -> parser::SExpr {
     value_buf
 }
 
+fn bad_lambda_captured() -> Box<dyn std::error::Error + Send + Sync> {
+    Box::new(std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        "Bad lambda-captured.",
+    ))
+}
+
+fn arity_error(message: String) -> Box<dyn std::error::Error + Send + Sync> {
+    Box::new(std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        message,
+    ))
+}
+
+fn bind_argument(
+    new_ctx: &mut EvalContext,
+    name: symbols::Symbol,
+    arg_value: parser::SExpr,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let arg_value: Arc<Mutex<parser::SExpr>> = match arg_value {
+        parser::SExpr::Ref(ref_val) => ref_val,
+        arg_other => new_ctx.new_cell(arg_other)?,
+    };
+    new_ctx.vars.push(name, arg_value);
+    Ok(())
+}
+
+// Parameter spec of `&optional` and `&key` parameters: either `name` or `(name default)`.
+fn parse_param_with_default(
+    param: &parser::SExpr,
+) -> Result<(symbols::Symbol, Option<parser::SExpr>), Box<dyn std::error::Error + Send + Sync>> {
+    match param {
+        parser::SExpr::Atom(parser::Atom::Symbol(name)) => Ok((name.clone(), None)),
+        parser::SExpr::List(param_pair) if param_pair.len() == 2 => {
+            if let parser::SExpr::Atom(parser::Atom::Symbol(name)) = &param_pair[0] {
+                Ok((name.clone(), Some(param_pair[1].clone())))
+            } else {
+                Err(bad_lambda_captured())
+            }
+        }
+        _ => Err(bad_lambda_captured()),
+    }
+}
+
+fn bind_param_with_default(
+    new_ctx: &mut EvalContext,
+    name: symbols::Symbol,
+    default: &Option<parser::SExpr>,
+    arg_value: Option<parser::SExpr>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let arg_value: parser::SExpr = match (arg_value, default) {
+        (Some(arg_value), _) => arg_value,
+        // Default expressions can refer to captured variables and earlier parameters
+        (None, Some(default)) => resolve_reference(&eval(default, new_ctx)?),
+        (None, None) => parser::SExpr::List(vec![]),
+    };
+    bind_argument(new_ctx, name, arg_value)
+}
+
+// Binds already evaluated arguments to parameter list of lambda.
+// Parameter list has form: required* [&optional opt*] [&rest name] [&key key*]
+fn bind_arguments(
+    new_ctx: &mut EvalContext,
+    params: &[parser::SExpr],
+    args: Vec<parser::SExpr>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut required: Vec<symbols::Symbol> = Vec::new();
+    let mut optional: Vec<(symbols::Symbol, Option<parser::SExpr>)> = Vec::new();
+    let mut rest: Option<symbols::Symbol> = None;
+    let mut keys: Option<Vec<(symbols::Symbol, Option<parser::SExpr>)>> = None;
+
+    let mut mode: &str = "";
+    for param in params {
+        if let parser::SExpr::Atom(parser::Atom::Symbol(name)) = param {
+            if name.starts_with('&') {
+                mode = match (mode, name.as_str()) {
+                    ("", "&optional") => "&optional",
+                    ("" | "&optional", "&rest") => "&rest",
+                    ("" | "&optional" | "&rest", "&key") => {
+                        keys = Some(Vec::new());
+                        "&key"
+                    }
+                    _ => {
+                        return Err(arity_error(format!(
+                            "Misplaced lambda list keyword `{}`.",
+                            name
+                        )))
+                    }
+                };
+                continue;
+            }
+        }
+        match mode {
+            "" => {
+                if let parser::SExpr::Atom(parser::Atom::Symbol(name)) = param {
+                    required.push(name.clone());
+                } else {
+                    return Err(bad_lambda_captured());
+                }
+            }
+            "&optional" => optional.push(parse_param_with_default(param)?),
+            "&rest" => {
+                if let (None, parser::SExpr::Atom(parser::Atom::Symbol(name))) = (&rest, param) {
+                    rest = Some(name.clone());
+                } else {
+                    return Err(arity_error(String::from(
+                        "`&rest` must be followed by exactly one variable name.",
+                    )));
+                }
+            }
+            _ => keys
+                .as_mut()
+                .unwrap()
+                .push(parse_param_with_default(param)?),
+        }
+    }
+    if mode == "&rest" && rest.is_none() {
+        return Err(arity_error(String::from(
+            "`&rest` must be followed by exactly one variable name.",
+        )));
+    }
+
+    let max_positional = required.len() + optional.len();
+    if args.len() < required.len() {
+        return Err(arity_error(
+            if optional.is_empty() && rest.is_none() && keys.is_none() {
+                format!(
+                    "Lambda expects {} argument(s), got {}.",
+                    required.len(),
+                    args.len()
+                )
+            } else {
+                format!(
+                    "Lambda expects at least {} argument(s), got {}.",
+                    required.len(),
+                    args.len()
+                )
+            },
+        ));
+    }
+    if args.len() > max_positional && rest.is_none() && keys.is_none() {
+        return Err(arity_error(if optional.is_empty() {
+            format!(
+                "Lambda expects {} argument(s), got {}.",
+                required.len(),
+                args.len()
+            )
+        } else {
+            format!(
+                "Lambda expects at most {} argument(s), got {}.",
+                max_positional,
+                args.len()
+            )
+        }));
+    }
+
+    let mut args = args.into_iter();
+    for name in &required {
+        bind_argument(new_ctx, name.clone(), args.next().unwrap())?;
+    }
+    for (name, default) in &optional {
+        bind_param_with_default(new_ctx, name.clone(), default, args.next())?;
+    }
+    let remaining: Vec<parser::SExpr> = args.collect();
+
+    if let Some(keys) = &keys {
+        if !remaining.len().is_multiple_of(2) {
+            return Err(arity_error(String::from(
+                "Keyword arguments must be given as pairs: :name value.",
+            )));
+        }
+        let mut key_values: Vec<Option<parser::SExpr>> = vec![None; keys.len()];
+        for pair in remaining.chunks(2) {
+            let key_name = match resolve_reference(&pair[0]) {
+                parser::SExpr::Atom(parser::Atom::Symbol(key_name))
+                    if key_name.starts_with(':') =>
+                {
+                    key_name
+                }
+                _ => {
+                    return Err(arity_error(String::from(
+                        "Keyword arguments must be given as pairs: :name value.",
+                    )))
+                }
+            };
+            match keys.iter().position(|(name, _)| name == &key_name[1..]) {
+                Some(key_idx) => {
+                    // The leftmost occurrence of a keyword wins
+                    if key_values[key_idx].is_none() {
+                        key_values[key_idx] = Some(pair[1].clone());
+                    }
+                }
+                None => {
+                    return Err(arity_error(format!(
+                        "Unknown keyword argument {}.",
+                        key_name
+                    )));
+                }
+            }
+        }
+        if let Some(rest) = &rest {
+            bind_argument(
+                new_ctx,
+                rest.clone(),
+                parser::SExpr::List(remaining.clone()),
+            )?;
+        }
+        for ((name, default), arg_value) in keys.iter().zip(key_values) {
+            bind_param_with_default(new_ctx, name.clone(), default, arg_value)?;
+        }
+    } else if let Some(rest) = &rest {
+        bind_argument(new_ctx, rest.clone(), parser::SExpr::List(remaining))?;
+    }
+
+    Ok(())
+}
+
+// Creates context of lambda body: captured variables followed by parameters bound to `args`.
+/// Line at which `sexpr` was read, if it's a symbol read from source code
+pub(crate) fn symbol_line(sexpr: &parser::SExpr) -> Option<usize> {
+    match sexpr {
+        parser::SExpr::Atom(parser::Atom::Symbol(sym)) => sym.line(),
+        _ => None,
+    }
+}
+
+/// Line of definition of lambda `func`, if known
+pub(crate) fn lambda_line(func: &parser::SExpr) -> Option<usize> {
+    match func {
+        parser::SExpr::List(list) => list.first().and_then(symbol_line),
+        _ => None,
+    }
+}
+
+pub(crate) fn prepare_lambda_call(
+    ctx: &EvalContext,
+    value_to_call: &parser::SExpr,
+    args: Vec<parser::SExpr>,
+) -> Result<(EvalContext, parser::SExpr), Box<dyn std::error::Error + Send + Sync>> {
+    let value_to_call = match value_to_call {
+        parser::SExpr::List(value_to_call) => value_to_call,
+        parser::SExpr::Continuation(continuation) => {
+            return Err(continuations::invoke(continuation, &args))
+        }
+        _ => {
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "2nd argument of statement list `call` after evaluation must be list starting with lambda-captured."
+            )))
+        }
+    };
+    match value_to_call.first() {
+        Some(parser::SExpr::Atom(parser::Atom::Symbol(value_to_call_type)))
+            if SpecialForm::LambdaCaptured.is(value_to_call_type) => {}
+        Some(parser::SExpr::Atom(parser::Atom::Symbol(_))) => {
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "2nd argument of statement list `call` after evaluation must be list starting with lambda-captured."
+            )))
+        }
+        _ => {
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Bad value-to-call.",
+            )))
+        }
+    }
+    if value_to_call.len() != 4 {
+        return Err(bad_lambda_captured());
+    }
+
+    let mut new_ctx = ctx.new_call_context();
+    if let parser::SExpr::List(captured_vars) = &value_to_call[1] {
+        for captured_var in captured_vars {
+            match captured_var {
+                parser::SExpr::List(captured_var_pair) if captured_var_pair.len() == 2 => {
+                    match (&captured_var_pair[0], &captured_var_pair[1]) {
+                        (
+                            parser::SExpr::Atom(parser::Atom::Symbol(var_name)),
+                            parser::SExpr::Ref(ref_val),
+                        ) => {
+                            new_ctx.vars.push(var_name.clone(), ref_val.clone());
+                        }
+                        _ => return Err(bad_lambda_captured()),
+                    }
+                }
+                _ => return Err(bad_lambda_captured()),
+            }
+        }
+    } else {
+        return Err(bad_lambda_captured());
+    }
+
+    if let parser::SExpr::List(params) = &value_to_call[2] {
+        bind_arguments(&mut new_ctx, params, args)?;
+    } else {
+        return Err(bad_lambda_captured());
+    }
+
+    Ok((new_ctx, value_to_call[3].clone()))
+}
+
+// Result of evaluating one step of an expression.
+enum Step {
+    Done(parser::SExpr),
+    // Expression in tail position, which should be evaluated instead of the original one
+    Tail(parser::SExpr),
+    // Body of `let` in tail position. Variables of the context from the given position on
+    // belong to the `let` and are dropped when the evaluation finishes.
+    Scoped(parser::SExpr, usize),
+    // Lambda body in tail position with its context and frame of the call
+    TailCall(parser::SExpr, EvalContext, traceback::Frame),
+}
+
+/// Expressions in tail position are evaluated in this loop instead of by recursive call,
+/// so tail calls run in constant Rust stack.
 pub fn eval(
     sexpr: &parser::SExpr,
     ctx: &mut EvalContext,
-) -> Result<parser::SExpr, Box<dyn std::error::Error>> {
-    match sexpr {
-        parser::SExpr::Atom(parser::Atom::Number(num)) => {
-            Ok(parser::SExpr::Atom(parser::Atom::Number(*num)))
-        }
-        parser::SExpr::Atom(parser::Atom::Symbol(sym)) => {
-            if ctx.vars.len() > 0 {
-                let mut curr_var: usize = ctx.vars.len() - 1;
-                loop {
-                    if &ctx.vars[curr_var].name == sym {
-                        break Ok(parser::SExpr::Ref(ctx.vars[curr_var].value.clone()));
-                    }
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    let step: Step = eval_step(sexpr, ctx)?;
+    finish_eval(step, ctx)
+}
 
-                    if curr_var == 0 {
-                        break Err(Box::new(std::io::Error::new(
-                            std::io::ErrorKind::NotFound,
-                            format!("Variable {} not defined.", sym),
-                        )));
-                    } else {
-                        curr_var -= 1;
-                    }
+/// Calls lambda or builtin function `func` with already evaluated arguments
+pub fn apply(
+    func: &parser::SExpr,
+    args: Vec<parser::SExpr>,
+    ctx: &mut EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    let func = resolve_reference(func);
+    if let Some(builtin) = builtins::as_builtin(&func) {
+        return builtin(&args, ctx);
+    }
+    let frame = traceback::Frame {
+        name: String::from("<lambda>"),
+        args: args.clone(),
+        line: lambda_line(&func),
+    };
+    let (new_ctx, body) = match prepare_lambda_call(ctx, &func, args) {
+        Ok(call) => call,
+        Err(err) => {
+            ctx.record_traceback(err.as_ref(), Some(frame));
+            return Err(err);
+        }
+    };
+    // Body of lambda created by compiled code is kept in a cell, and runs compiled too
+    if let parser::SExpr::Ref(body_cell) = &body {
+        return vm::call_lambda(body_cell, new_ctx, frame);
+    }
+    finish_eval(Step::TailCall(body, new_ctx, frame), ctx)
+}
+
+fn finish_eval(
+    step: Step,
+    ctx: &mut EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    let mut frame_pushed: bool = false;
+    let mut scope_start: Option<usize> = None;
+    let result = eval_loop(step, ctx, &mut frame_pushed, &mut scope_start);
+    if let Err(err) = &result {
+        ctx.record_traceback(err.as_ref(), None);
+    }
+    if frame_pushed {
+        ctx.pop_call_frame();
+    }
+    if let Some(scope_start) = scope_start {
+        ctx.vars.truncate(scope_start);
+    }
+    result
+}
+
+fn eval_loop(
+    mut step: Step,
+    ctx: &mut EvalContext,
+    frame_pushed: &mut bool,
+    scope_start: &mut Option<usize>,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    // Context of lambda body in tail position; `let`s in it need no cleanup, as it's dropped
+    let mut tail_ctx: Option<EvalContext> = None;
+    loop {
+        let next_sexpr = match step {
+            Step::Done(result) => return Ok(result),
+            Step::Tail(next_sexpr) => next_sexpr,
+            Step::Scoped(next_sexpr, start) => {
+                if tail_ctx.is_none() && scope_start.is_none() {
+                    *scope_start = Some(start);
                 }
-            } else {
-                Err(Box::new(std::io::Error::new(
-                    std::io::ErrorKind::NotFound,
-                    format!("Variable {} not defined.", sym),
-                )))
+                next_sexpr
+            }
+            Step::TailCall(next_sexpr, next_ctx, frame) => {
+                if *frame_pushed {
+                    ctx.replace_call_frame(frame);
+                } else {
+                    ctx.push_call_frame(frame)?;
+                    *frame_pushed = true;
+                }
+                tail_ctx = Some(next_ctx);
+                next_sexpr
             }
+        };
+        let curr_ctx: &mut EvalContext = match &mut tail_ctx {
+            Some(tail_ctx) => tail_ctx,
+            None => ctx,
+        };
+        step = eval_step(&next_sexpr, curr_ctx)?;
+    }
+}
+
+fn eval_step(
+    sexpr: &parser::SExpr,
+    ctx: &mut EvalContext,
+) -> Result<Step, Box<dyn std::error::Error + Send + Sync>> {
+    ctx.check_limits()?;
+    match sexpr {
+        parser::SExpr::Atom(parser::Atom::Number(num)) => {
+            Ok(Step::Done(parser::SExpr::Atom(parser::Atom::Number(*num))))
         }
+        parser::SExpr::Atom(parser::Atom::Symbol(sym)) => Ok(Step::Done(eval_symbol(sym, ctx)?)),
         parser::SExpr::List(list) => {
-            if list.len() > 0 {
+            if !list.is_empty() {
                 if let parser::SExpr::List(_) = list[0] {
                     // This is a list of lists
                     // Evaluate all elements and return the last one
-                    let mut ret_val: Option<parser::SExpr> = None;
-                    for elem in list {
-                        ret_val = Some(eval(elem, ctx)?);
+                    for elem in &list[..list.len() - 1] {
+                        eval(elem, ctx)?;
                     }
-                    Ok(ret_val.unwrap())
+                    Ok(Step::Tail(list[list.len() - 1].clone()))
                 } else {
                     // The first element of list is an atom
                     match &list[0] {
-                        parser::SExpr::Atom(parser::Atom::Symbol(sym)) => match sym.as_str() {
-                            "let" => {
-                                if list.len() >= 3 {
-                                    let mut ctx_new = ctx.clone();
-                                    for i in 0..(list.len() - 2) {
-                                        if let parser::SExpr::List(var_def_list) = &list[i + 1] {
-                                            if let parser::SExpr::Atom(parser::Atom::Symbol(
-                                                var_name,
-                                            )) = var_def_list[0].clone()
-                                            {
-                                                let value_evaluated: parser::SExpr =
-                                                    eval(&var_def_list[1], &mut ctx_new)?;
-                                                ctx_new.vars.push(Variable {
-                                                    name: var_name,
-                                                    value: Rc::new(Mutex::new(value_evaluated)),
-                                                });
-                                            } else {
-                                                return Err(Box::new(std::io::Error::new(
-                                                    std::io::ErrorKind::InvalidInput,
-                                                    "1st element of `let` variable definition must be symbol - variable name.",
-										        )));
-                                            }
-                                        } else {
-                                            return Err(Box::new(std::io::Error::new(
-                                                std::io::ErrorKind::InvalidInput,
-                                                "Arguments (besides first and last) of statement list `let` must be list of variable name and value.",
-                                            )));
-                                        }
-                                    }
-                                    eval(&list[list.len() - 1], &mut ctx_new)
-                                } else {
-                                    Err(Box::new(std::io::Error::new(
-										std::io::ErrorKind::InvalidInput,
-										"Statement list `let` must have at least 3 elements: `let`, (var_name, var_value)+, block.",
-									)))
+                        parser::SExpr::Atom(parser::Atom::Symbol(sym)) => {
+                            match SpecialForm::of(sym) {
+                                Some(SpecialForm::Let) => eval_let(list, ctx),
+                                Some(SpecialForm::Set) => eval_set(list, ctx),
+                                Some(SpecialForm::If) => eval_if(list, ctx),
+                                Some(SpecialForm::Cond) => eval_cond(list, ctx),
+                                Some(SpecialForm::While) => eval_while(list, ctx),
+                                Some(SpecialForm::Lambda) => eval_lambda(list, ctx),
+                                Some(SpecialForm::LambdaCaptured | SpecialForm::Builtin) => {
+                                    Ok(Step::Done(parser::SExpr::List(list.clone())))
                                 }
-                            }
-                            "set" => {
-                                if list.len() == 3 {
-                                    if let parser::SExpr::Atom(parser::Atom::Symbol(var_name)) =
-                                        &list[1]
-                                    {
-                                        let value_evaluated: parser::SExpr = eval(&list[2], ctx)?;
-                                        let mut curr_var: usize = ctx.vars.len() - 1;
-                                        if let Err(e) = loop {
-                                            if &ctx.vars[curr_var].name == var_name {
-                                                let var_value_rc = ctx.vars[curr_var].value.clone();
-                                                let mut var_value = var_value_rc.lock().unwrap();
-                                                *var_value = value_evaluated.clone();
-                                                break Ok(());
-                                            }
-
-                                            if curr_var == 0 {
-                                                break Err(Box::new(std::io::Error::new(
-                                                    std::io::ErrorKind::NotFound,
-                                                    format!("Variable {} not defined.", var_name),
-                                                )));
-                                            } else {
-                                                curr_var -= 1;
-                                            }
-                                        } {
-                                            return Err(e);
-                                        }
-
-                                        Ok(value_evaluated)
+                                Some(SpecialForm::Call) => eval_call(list, ctx),
+                                Some(SpecialForm::Defstruct) => eval_defstruct(list, ctx),
+                                Some(SpecialForm::Defmacro) => eval_defmacro(list, ctx),
+                                Some(SpecialForm::DefineSyntax) => eval_define_syntax(list, ctx),
+                                Some(SpecialForm::Try) => eval_try(list, ctx),
+                                Some(SpecialForm::UnwindProtect) => eval_unwind_protect(list, ctx),
+                                Some(SpecialForm::Quote) => {
+                                    if list.len() == 2 {
+                                        Ok(Step::Done(list[1].clone()))
                                     } else {
                                         Err(Box::new(std::io::Error::new(
-                                                std::io::ErrorKind::InvalidInput,
-                                                "Statement list `set` must have exactly 3 elements: `set`, var_name, var_value.",
-                                            )))
-                                    }
-                                } else {
-                                    Err(Box::new(std::io::Error::new(
 										std::io::ErrorKind::InvalidInput,
-										"Statement list `set` must have exactly 3 elements: `set`, var_name, var_value.",
+										"Statement list `quote` must have exactly 2 elements: `quote`, value.",
 									)))
-                                }
-                            }
-                            "if" => {
-                                let cond_evaluated: parser::SExpr = eval(&list[1], ctx)?;
-                                let cond: bool = value_is_true(&cond_evaluated);
-
-                                if list.len() == 3 {
-                                    if cond {
-                                        Ok(eval(&list[2], ctx)?)
-                                    } else {
-                                        Ok(parser::SExpr::List(vec![]))
                                     }
-                                } else if list.len() == 4 {
-                                    if cond {
-                                        Ok(eval(&list[2], ctx)?)
-                                    } else {
-                                        Ok(eval(&list[3], ctx)?)
-                                    }
-                                } else {
-                                    Err(Box::new(std::io::Error::new(
-                                        std::io::ErrorKind::InvalidInput,
-                                        "Statement list `if` must have 3 or 4 elements: `if`, cond, block1, block2?."
-                                    )))
-                                }
-                            }
-                            "while" => {
-                                if list.len() == 3 {
-                                    let mut result: parser::SExpr = parser::SExpr::List(vec![]);
-                                    while {
-                                        let cond_evaluated: parser::SExpr = eval(&list[1], ctx)?;
-                                        value_is_true(&cond_evaluated)
-                                    } {
-                                        result = eval(&list[2], ctx)?;
-                                    }
-                                    Ok(result)
-                                } else {
-                                    Err(Box::new(std::io::Error::new(
-                                        std::io::ErrorKind::InvalidInput,
-                                        "Statement list `while` must have 3 elements: `while`, cond, block."
-                                    )))
                                 }
-                            }
-                            "lambda" => {
-                                if list.len() == 4 {
-                                    if let parser::SExpr::List(capture_list) = &list[1] {
-                                        let mut captured_vars: Vec<parser::SExpr> = Vec::new();
-                                        for elem in capture_list {
-                                            if let parser::SExpr::Atom(parser::Atom::Symbol(
-                                                var_name,
-                                            )) = elem
-                                            {
-                                                captured_vars.push(parser::SExpr::List(vec![
-                                                    parser::SExpr::Atom(parser::Atom::Symbol(
-                                                        var_name.clone(),
-                                                    )),
-                                                    eval(
-                                                        &parser::SExpr::Atom(parser::Atom::Symbol(
-                                                            var_name.clone(),
-                                                        )),
-                                                        ctx,
-                                                    )?,
-                                                ]));
-                                            } else {
-                                                return Err(Box::new(std::io::Error::new(
-                                                    std::io::ErrorKind::InvalidInput,
-                                                    "2nd element of statement list `lambda` must be list atoms - variable names."
-                                                )));
-                                            }
-                                        }
-                                        Ok(parser::SExpr::List(vec![
-                                            parser::SExpr::Atom(parser::Atom::Symbol(
-                                                String::from("lambda-captured"),
-                                            )),
-                                            parser::SExpr::List(captured_vars),
-                                            list[2].clone(),
-                                            list[3].clone(),
-                                        ]))
+                                Some(SpecialForm::Quasiquote) => {
+                                    if list.len() == 2 {
+                                        Ok(Step::Done(quasiquote(&list[1], 1, ctx)?))
                                     } else {
                                         Err(Box::new(std::io::Error::new(
-                                            std::io::ErrorKind::InvalidInput,
-                                            "2nd element of statement list `lambda` must be list atoms - variable names."
-                                        )))
-                                    }
-                                } else {
-                                    Err(Box::new(std::io::Error::new(
                                         std::io::ErrorKind::InvalidInput,
-                                        "Statement list `lambda` must have 3 elements: `lambda`, capture-list, args, block."
+                                        "Statement list `quasiquote` must have exactly 2 elements: `quasiquote`, template.",
                                     )))
-                                }
-                            }
-                            "lambda-captured" => Ok(parser::SExpr::List(list.clone())),
-                            "call" => {
-                                if list.len() >= 2 {
-                                    if let parser::SExpr::List(value_to_call) =
-                                        resolve_reference(&(eval(&list[1], ctx)?))
-                                    {
-                                        // println!("Value to call:\n{:#?}", value_to_call);
-                                        if let parser::SExpr::Atom(parser::Atom::Symbol(
-                                            value_to_call_type,
-                                        )) = &value_to_call[0]
-                                        {
-                                            if value_to_call_type == "lambda-captured" {
-                                                let mut new_ctx = EvalContext::new();
-                                                if let parser::SExpr::List(captured_vars) =
-                                                    &value_to_call[1]
-                                                {
-                                                    for captured_var in captured_vars {
-                                                        if let parser::SExpr::List(
-                                                            captured_var_pair,
-                                                        ) = &captured_var
-                                                        {
-                                                            let var_name: String =
-                                                                if let parser::SExpr::Atom(
-                                                                    parser::Atom::Symbol(var_name),
-                                                                ) = &captured_var_pair[0]
-                                                                {
-                                                                    var_name.clone()
-                                                                } else {
-                                                                    return Err(Box::new(std::io::Error::new(
-                                                                        std::io::ErrorKind::InvalidInput,
-                                                                        "Bad lambda-captured."
-                                                                    )));
-                                                                };
-                                                            let var_value: Rc<
-                                                                Mutex<parser::SExpr>,
-                                                            > = if let parser::SExpr::Ref(ref_val) =
-                                                                &captured_var_pair[1]
-                                                            {
-                                                                ref_val.clone()
-                                                            } else {
-                                                                return Err(Box::new(std::io::Error::new(
-                                                                    std::io::ErrorKind::InvalidInput,
-                                                                    "Bad lambda-captured."
-                                                                )));
-                                                            };
-                                                            new_ctx.vars.push(Variable {
-                                                                name: var_name,
-                                                                value: var_value,
-                                                            });
-                                                        } else {
-                                                            return Err(Box::new(std::io::Error::new(
-                                                                std::io::ErrorKind::InvalidInput,
-                                                                "Bad lambda-captured."
-                                                            )));
-                                                        }
-                                                    }
-
-                                                    if let parser::SExpr::List(args) =
-                                                        &value_to_call[2]
-                                                    {
-                                                        for (i, arg) in args.iter().enumerate() {
-                                                            if let parser::SExpr::Atom(
-                                                                parser::Atom::Symbol(arg_name),
-                                                            ) = arg
-                                                            {
-                                                                if list.len() > i + 2 {
-                                                                    let arg_value: Rc<
-                                                                        Mutex<parser::SExpr>,
-                                                                    >;
-                                                                    let arg_evaluated: parser::SExpr =
-                                                                        eval(&list[i + 2], ctx)?;
-                                                                    arg_value = match arg_evaluated
-                                                                    {
-                                                                        parser::SExpr::Ref(
-                                                                            ref_val,
-                                                                        ) => ref_val.clone(),
-                                                                        arg_other => Rc::new(
-                                                                            Mutex::new(arg_other),
-                                                                        ),
-                                                                    };
-                                                                    new_ctx.vars.push(Variable {
-                                                                        name: arg_name.clone(),
-                                                                        value: arg_value,
-                                                                    });
-                                                                } else {
-                                                                    new_ctx.vars.push(Variable {
-                                                                        name: arg_name.clone(),
-                                                                        value: Rc::new(Mutex::new(
-                                                                            parser::SExpr::List(
-                                                                                vec![],
-                                                                            ),
-                                                                        )),
-                                                                    })
-                                                                }
-                                                            } else {
-                                                                return Err(Box::new(std::io::Error::new(
-                                                                    std::io::ErrorKind::InvalidInput,
-                                                                    "Bad lambda-captured."
-                                                                )));
-                                                            }
-                                                        }
-                                                    } else {
-                                                        return Err(Box::new(std::io::Error::new(
-                                                            std::io::ErrorKind::InvalidInput,
-                                                            "Bad lambda-captured.",
-                                                        )));
-                                                    }
-
-                                                    eval(&value_to_call[3], &mut new_ctx)
-                                                } else {
-                                                    Err(Box::new(std::io::Error::new(
-                                                        std::io::ErrorKind::InvalidInput,
-                                                        "Bad lambda-captured.",
-                                                    )))
-                                                }
-                                            } else {
-                                                Err(Box::new(std::io::Error::new(
-                                                    std::io::ErrorKind::InvalidInput,
-                                                    "2nd argument of statement list `call` after evaluation must be list starting with lambda-captured."
-                                                )))
-                                            }
-                                        } else {
-                                            Err(Box::new(std::io::Error::new(
-                                                std::io::ErrorKind::InvalidInput,
-                                                "Bad value-to-call.",
-                                            )))
-                                        }
-                                    } else {
-                                        Err(Box::new(std::io::Error::new(
-                                            std::io::ErrorKind::InvalidInput,
-                                            "2nd argument of statement list `call` after evaluation must be list starting with lambda-captured."
-                                        )))
                                     }
-                                } else {
-                                    Err(Box::new(std::io::Error::new(
-                                        std::io::ErrorKind::InvalidInput,
-                                        "Statement list `call` must have at least 2 elements: `call`, value-to-call, args*."
-                                    )))
                                 }
-                            }
-                            op @ (">" | "<" | ">=" | "<=" | "=") => {
-                                if list.len() == 3 {
-                                    let val1 = resolve_reference(&eval(&list[1], ctx)?);
-                                    let val2 = resolve_reference(&eval(&list[2], ctx)?);
-
-                                    if let parser::SExpr::Atom(parser::Atom::Number(val1_num)) =
-                                        val1
-                                    {
-                                        if let parser::SExpr::Atom(parser::Atom::Number(val2_num)) =
-                                            val2
-                                        {
-                                            let result = match op {
-                                                ">" => val1_num > val2_num,
-                                                "<" => val1_num < val2_num,
-                                                ">=" => val1_num >= val2_num,
-                                                "<=" => val1_num <= val2_num,
-                                                "=" => val1_num == val2_num,
-                                                _ => unreachable!(),
-                                            };
-                                            if result {
-                                                Ok(parser::SExpr::Atom(parser::Atom::Number(1.0)))
-                                            } else {
-                                                Ok(parser::SExpr::Atom(parser::Atom::Number(0.0)))
-                                            }
-                                        } else {
-                                            Err(Box::new(std::io::Error::new(
-                                                std::io::ErrorKind::InvalidInput,
-                                                "Comparison statement list must have exactly 3 elements: operator, val1, val2.",
-                                            )))
-                                        }
-                                    } else {
-                                        Err(Box::new(std::io::Error::new(
-                                            std::io::ErrorKind::InvalidInput,
-                                            "Comparison statement list must have exactly 3 elements: operator, val1, val2.",
-                                        )))
-                                    }
-                                } else {
+                                Some(SpecialForm::Unquote | SpecialForm::UnquoteSplicing) => {
                                     Err(Box::new(std::io::Error::new(
                                         std::io::ErrorKind::InvalidInput,
-                                        "Comparison statement list must have exactly 3 elements: operator, val1, val2.",
+                                        format!(
+                                            "Statement list `{}` used outside of quasiquote.",
+                                            sym
+                                        ),
                                     )))
                                 }
-                            }
-                            "quote" => {
-                                if list.len() == 2 {
-                                    Ok(list[1].clone())
-                                } else {
-                                    Err(Box::new(std::io::Error::new(
-										std::io::ErrorKind::InvalidInput,
-										"Statement list `quote` must have exactly 2 elements: `quote`, value.",
-									)))
-                                }
-                            }
-                            "list" => {
-                                let mut result: Vec<parser::SExpr> = Vec::new();
-                                for i in 1..list.len() {
-                                    result.push(eval(&list[i], ctx)?);
-                                }
-                                Ok(parser::SExpr::List(result))
-                            }
-                            "print" => {
-                                if list.len() >= 2 {
-                                    for i in 1..list.len() {
-                                        let result = resolve_reference(&eval(&list[i], ctx)?);
-                                        if let parser::SExpr::Atom(atom) = &result {
-                                            if let parser::Atom::Number(num) = atom {
-                                                print!("{}", num);
-                                            } else {
-                                                return Err(Box::new(std::io::Error::new(
-												    std::io::ErrorKind::InvalidInput,
-												    "2+nd argument to statement list `print` must evaluate to a number atom or list of number atoms (char codes).",
-											    )));
-                                            }
-                                        } else if let parser::SExpr::List(print_list) = &result {
-                                            for elem in print_list {
-                                                if let parser::SExpr::Atom(parser::Atom::Number(
-                                                    ch,
-                                                )) = elem
-                                                {
-                                                    print!("{}", (*ch) as u8 as char);
-                                                } else {
-                                                    return Err(Box::new(std::io::Error::new(
-                                                        std::io::ErrorKind::InvalidInput,
-                                                        "2+nd argument to statement list `print` must evaluate to a number atom or list of number atoms (char codes).",
-                                                    )));
-                                                }
-                                            }
-                                        } else {
-                                            return Err(Box::new(std::io::Error::new(
-											std::io::ErrorKind::InvalidInput,
-											"+2nd argument to statement list `print` must evaluate to a number atom or list of number atoms (char codes).",
-										    )));
-                                        }
-                                    }
-                                    Ok(parser::SExpr::List(vec![]))
-                                } else {
-                                    Err(Box::new(std::io::Error::new(
-										std::io::ErrorKind::InvalidInput,
-										"Statement list `print` must have at least 2 elements: `print`, value+.",
-									)))
+                                // Macro call not expanded before evaluation, e.g. because the macro
+                                // was defined in the same top-level expression
+                                None if ctx.is_macro(sym) => {
+                                    Ok(Step::Tail(macroexpand_1(sexpr, ctx)?.unwrap()))
                                 }
-                            }
-                            "readnum" => {
-                                let mut buf: String = String::new();
-                                std::io::stdin().read_line(&mut buf)?;
-                                let num = buf.trim().parse::<f64>()?;
-                                Ok(parser::SExpr::Atom(parser::Atom::Number(num)))
-                            }
-                            "+" => {
-                                if list.len() > 2 {
-                                    let mut result: f64 = 0.0;
-                                    for i in 1..list.len() {
-                                        let elem_result = resolve_reference(&eval(&list[i], ctx)?);
-                                        if let parser::SExpr::Atom(parser::Atom::Number(num)) =
-                                            elem_result
-                                        {
-                                            result += num;
-                                        } else {
-                                            return Err(Box::new(std::io::Error::new(
-                                                std::io::ErrorKind::InvalidInput,
-                                                "2+nd argument of statement list `+` must evaluate to a number atom.",
-                                            )));
+                                None => match builtins::lookup(sym) {
+                                    Some(builtin) => {
+                                        let mut args: Vec<parser::SExpr> = Vec::new();
+                                        for arg in &list[1..] {
+                                            args.push(eval(arg, ctx)?);
                                         }
+                                        Ok(Step::Done(builtin(&args, ctx)?))
                                     }
-                                    Ok(parser::SExpr::Atom(parser::Atom::Number(result)))
-                                } else {
-                                    Err(Box::new(std::io::Error::new(
-										std::io::ErrorKind::InvalidInput,
-										"Statement list `+` must have more than 2 elements: `+`, values... .",
-									)))
-                                }
+                                    None => Err(Box::new(std::io::Error::new(
+                                        std::io::ErrorKind::InvalidInput,
+                                        format!("Bad statement list `{}`.", sym),
+                                    ))),
+                                },
                             }
-                            statement => Err(Box::new(std::io::Error::new(
-                                std::io::ErrorKind::InvalidInput,
-                                format!("Bad statement list `{}`.", statement),
-                            ))),
-                        },
+                        }
                         parser::SExpr::Atom(parser::Atom::Number(_)) => {
                             Err(Box::new(std::io::Error::new(
                                 std::io::ErrorKind::InvalidInput,
@@ -545,17 +1207,712 @@ pub fn eval(
                             std::io::ErrorKind::InvalidInput,
                             "First value of statement list cannot be a reference.",
                         ))),
+                        parser::SExpr::Hash(_) => Err(Box::new(std::io::Error::new(
+                            std::io::ErrorKind::InvalidInput,
+                            "First value of statement list cannot be a hash table.",
+                        ))),
+                        parser::SExpr::Vector(_) => Err(Box::new(std::io::Error::new(
+                            std::io::ErrorKind::InvalidInput,
+                            "First value of statement list cannot be a vector.",
+                        ))),
+                        parser::SExpr::RecordType(_) | parser::SExpr::Record(_) => {
+                            Err(Box::new(std::io::Error::new(
+                                std::io::ErrorKind::InvalidInput,
+                                "First value of statement list cannot be a record.",
+                            )))
+                        }
+                        parser::SExpr::Generator(_) => Err(Box::new(std::io::Error::new(
+                            std::io::ErrorKind::InvalidInput,
+                            "First value of statement list cannot be a generator.",
+                        ))),
+                        parser::SExpr::Thread(_)
+                        | parser::SExpr::Channel(_)
+                        | parser::SExpr::Lock(_)
+                        | parser::SExpr::Atomic(_) => Err(Box::new(std::io::Error::new(
+                            std::io::ErrorKind::InvalidInput,
+                            "First value of statement list cannot be a concurrency primitive.",
+                        ))),
+                        parser::SExpr::Continuation(_) => Err(Box::new(std::io::Error::new(
+                            std::io::ErrorKind::InvalidInput,
+                            "First value of statement list cannot be a continuation.",
+                        ))),
                         parser::SExpr::List(_) => unreachable!(),
                     }
                 }
             } else {
-                Ok(parser::SExpr::List(vec![]))
+                Ok(Step::Done(parser::SExpr::List(vec![])))
             }
         }
         parser::SExpr::Ref(ref_val) => {
-            let ref_val_clone = ref_val.clone();
-            let ref_val = ref_val_clone.lock().unwrap();
-            eval(&ref_val, ctx)
+            let ref_val = ref_val.lock().unwrap();
+            Ok(Step::Tail(ref_val.clone()))
+        }
+        parser::SExpr::Hash(_)
+        | parser::SExpr::Vector(_)
+        | parser::SExpr::RecordType(_)
+        | parser::SExpr::Record(_)
+        | parser::SExpr::Generator(_)
+        | parser::SExpr::Thread(_)
+        | parser::SExpr::Channel(_)
+        | parser::SExpr::Lock(_)
+        | parser::SExpr::Atomic(_)
+        | parser::SExpr::Continuation(_) => Ok(Step::Done(sexpr.clone())),
+    }
+}
+
+fn eval_let(
+    list: &[parser::SExpr],
+    ctx: &mut EvalContext,
+) -> Result<Step, Box<dyn std::error::Error + Send + Sync>> {
+    if list.len() >= 3 {
+        // Variables are defined in `ctx` itself and dropped when evaluation of the body ends
+        let scope_start = ctx.vars.len();
+        if let Err(err) = define_let_vars(&list[1..list.len() - 1], ctx) {
+            ctx.vars.truncate(scope_start);
+            return Err(err);
+        }
+        Ok(Step::Scoped(list[list.len() - 1].clone(), scope_start))
+    } else {
+        Err(Box::new(std::io::Error::new(
+                                        std::io::ErrorKind::InvalidInput,
+                                        "Statement list `let` must have at least 3 elements: `let`, (var_name, var_value)+, block.",
+                                    )))
+    }
+}
+
+fn define_let_vars(
+    var_defs: &[parser::SExpr],
+    ctx: &mut EvalContext,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    for var_def in var_defs {
+        if let parser::SExpr::List(var_def_list) = var_def {
+            if var_def_list.len() != 2 {
+                return Err(Box::new(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "Variable definition of `let` must have exactly 2 elements: var_name, var_value.",
+                )));
+            }
+            if let parser::SExpr::Atom(parser::Atom::Symbol(var_name)) = var_def_list[0].clone() {
+                let value_evaluated: parser::SExpr = eval(&var_def_list[1], ctx)?;
+                let value = ctx.new_cell(value_evaluated)?;
+                ctx.vars.push(var_name, value);
+            } else {
+                return Err(Box::new(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "1st element of `let` variable definition must be symbol - variable name.",
+                )));
+            }
+        } else {
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Arguments (besides first and last) of statement list `let` must be list of variable name and value.",
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn eval_set(
+    list: &[parser::SExpr],
+    ctx: &mut EvalContext,
+) -> Result<Step, Box<dyn std::error::Error + Send + Sync>> {
+    if list.len() == 3 {
+        if let parser::SExpr::Atom(parser::Atom::Symbol(var_name)) = &list[1] {
+            let value_evaluated: parser::SExpr = eval(&list[2], ctx)?;
+            match ctx.lookup_var(var_name) {
+                Some(var_value_rc) => {
+                    let mut var_value = var_value_rc.lock().unwrap();
+                    *var_value = value_evaluated.clone();
+                }
+                None => return Err(undefined_variable(var_name)),
+            }
+
+            Ok(Step::Done(value_evaluated))
+        } else {
+            Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Statement list `set` must have exactly 3 elements: `set`, var_name, var_value.",
+            )))
+        }
+    } else {
+        Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "Statement list `set` must have exactly 3 elements: `set`, var_name, var_value.",
+        )))
+    }
+}
+
+fn eval_if(
+    list: &[parser::SExpr],
+    ctx: &mut EvalContext,
+) -> Result<Step, Box<dyn std::error::Error + Send + Sync>> {
+    if list.len() != 3 && list.len() != 4 {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "Statement list `if` must have 3 or 4 elements: `if`, cond, block1, block2?.",
+        )));
+    }
+    let cond_evaluated: parser::SExpr = eval(&list[1], ctx)?;
+    let cond: bool = value_is_true(&cond_evaluated);
+
+    if list.len() == 3 {
+        if cond {
+            Ok(Step::Tail(list[2].clone()))
+        } else {
+            Ok(Step::Done(parser::SExpr::List(vec![])))
+        }
+    } else if cond {
+        Ok(Step::Tail(list[2].clone()))
+    } else {
+        Ok(Step::Tail(list[3].clone()))
+    }
+}
+
+fn eval_cond(
+    list: &[parser::SExpr],
+    ctx: &mut EvalContext,
+) -> Result<Step, Box<dyn std::error::Error + Send + Sync>> {
+    for clause in &list[1..] {
+        if let parser::SExpr::List(clause_list) = clause {
+            if clause_list.len() == 2 {
+                let cond: bool = match &clause_list[0] {
+                    parser::SExpr::Atom(parser::Atom::Symbol(sym)) if sym == "else" => true,
+                    cond_expr => value_is_true(&eval(cond_expr, ctx)?),
+                };
+                if cond {
+                    return Ok(Step::Tail(clause_list[1].clone()));
+                }
+                continue;
+            }
+        }
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "Clauses of statement list `cond` must have exactly 2 elements: cond, block.",
+        )));
+    }
+    Ok(Step::Done(parser::SExpr::List(vec![])))
+}
+
+fn eval_while(
+    list: &[parser::SExpr],
+    ctx: &mut EvalContext,
+) -> Result<Step, Box<dyn std::error::Error + Send + Sync>> {
+    if list.len() == 3 {
+        let mut result: parser::SExpr = parser::SExpr::List(vec![]);
+        while {
+            let cond_evaluated: parser::SExpr = eval(&list[1], ctx)?;
+            value_is_true(&cond_evaluated)
+        } {
+            result = eval(&list[2], ctx)?;
+        }
+        Ok(Step::Done(result))
+    } else {
+        Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "Statement list `while` must have 3 elements: `while`, cond, block.",
+        )))
+    }
+}
+
+fn eval_lambda(
+    list: &[parser::SExpr],
+    ctx: &mut EvalContext,
+) -> Result<Step, Box<dyn std::error::Error + Send + Sync>> {
+    if list.len() == 4 {
+        if let parser::SExpr::List(capture_list) = &list[1] {
+            let mut captured_vars: Vec<parser::SExpr> = Vec::new();
+            for elem in capture_list {
+                if let parser::SExpr::Atom(parser::Atom::Symbol(var_name)) = elem {
+                    captured_vars.push(parser::SExpr::List(vec![
+                        parser::SExpr::Atom(parser::Atom::Symbol(var_name.clone())),
+                        eval(
+                            &parser::SExpr::Atom(parser::Atom::Symbol(var_name.clone())),
+                            ctx,
+                        )?,
+                    ]));
+                } else {
+                    return Err(Box::new(std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        "2nd element of statement list `lambda` must be list atoms - variable names."
+                    )));
+                }
+            }
+            // Lambda remembers line of its definition for tracebacks
+            let lambda_captured = parser::SExpr::List(vec![
+                parser::SExpr::Atom(parser::Atom::Symbol(
+                    SpecialForm::LambdaCaptured
+                        .symbol()
+                        .at_line(symbol_line(&list[0])),
+                )),
+                parser::SExpr::List(captured_vars),
+                list[2].clone(),
+                list[3].clone(),
+            ]);
+            ctx.charge_memory(value_size(&lambda_captured))?;
+            Ok(Step::Done(lambda_captured))
+        } else {
+            Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "2nd element of statement list `lambda` must be list atoms - variable names.",
+            )))
         }
+    } else {
+        Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "Statement list `lambda` must have 3 elements: `lambda`, capture-list, args, block.",
+        )))
     }
 }
+
+fn eval_call(
+    list: &[parser::SExpr],
+    ctx: &mut EvalContext,
+) -> Result<Step, Box<dyn std::error::Error + Send + Sync>> {
+    if list.len() >= 2 {
+        let value_to_call = resolve_reference(&(eval(&list[1], ctx)?));
+        let mut args: Vec<parser::SExpr> = Vec::new();
+        for arg in &list[2..] {
+            args.push(eval(arg, ctx)?);
+        }
+        if let Some(builtin) = builtins::as_builtin(&value_to_call) {
+            return Ok(Step::Done(builtin(&args, ctx)?));
+        }
+        let frame = traceback::Frame {
+            name: match &list[1] {
+                parser::SExpr::Atom(parser::Atom::Symbol(name)) => name.to_string(),
+                _ => String::from("<lambda>"),
+            },
+            args: args.clone(),
+            line: symbol_line(&list[0]).or_else(|| lambda_line(&value_to_call)),
+        };
+        let (new_ctx, body) = match prepare_lambda_call(ctx, &value_to_call, args) {
+            Ok(call) => call,
+            Err(err) => {
+                ctx.record_traceback(err.as_ref(), Some(frame));
+                return Err(err);
+            }
+        };
+        Ok(Step::TailCall(body, new_ctx, frame))
+    } else {
+        Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "Statement list `call` must have at least 2 elements: `call`, value-to-call, args*.",
+        )))
+    }
+}
+
+// (try block (catch var handler)? (finally cleanup)?), with at least one of the clauses.
+// Body is not in tail position, as its errors must be caught.
+fn eval_try(
+    list: &[parser::SExpr],
+    ctx: &mut EvalContext,
+) -> Result<Step, Box<dyn std::error::Error + Send + Sync>> {
+    let bad_try = || -> Box<dyn std::error::Error + Send + Sync> {
+        Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "Statement list `try` must have form: `try`, block, (catch var handler)?, (finally cleanup)?.",
+        ))
+    };
+    let mut catch_clause: Option<(symbols::Symbol, &parser::SExpr)> = None;
+    let mut finally_clause: Option<&parser::SExpr> = None;
+    for clause in list.get(2..).ok_or_else(bad_try)? {
+        match clause {
+            parser::SExpr::List(clause) => match clause.as_slice() {
+                [parser::SExpr::Atom(parser::Atom::Symbol(form)), parser::SExpr::Atom(parser::Atom::Symbol(var)), handler]
+                    if form == "catch" && catch_clause.is_none() && finally_clause.is_none() =>
+                {
+                    catch_clause = Some((var.clone(), handler))
+                }
+                [parser::SExpr::Atom(parser::Atom::Symbol(form)), cleanup]
+                    if form == "finally" && finally_clause.is_none() =>
+                {
+                    finally_clause = Some(cleanup)
+                }
+                _ => return Err(bad_try()),
+            },
+            _ => return Err(bad_try()),
+        }
+    }
+    if catch_clause.is_none() && finally_clause.is_none() {
+        return Err(bad_try());
+    }
+
+    let mut result = eval(&list[1], ctx);
+    if let (Err(err), Some((var, handler))) = (&result, catch_clause) {
+        if let Some(caught) = conditions::catch(ctx, err.as_ref()) {
+            ctx.take_traceback();
+            result = eval_catch_handler(var, caught, handler, ctx);
+        }
+    }
+    if let Some(cleanup) = finally_clause {
+        eval(cleanup, ctx)?;
+    }
+    Ok(Step::Done(result?))
+}
+
+// Evaluates handler of `catch` clause with `var` bound to the caught value
+fn eval_catch_handler(
+    var: symbols::Symbol,
+    caught: parser::SExpr,
+    handler: &parser::SExpr,
+    ctx: &mut EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    let scope_start = ctx.vars.len();
+    let value = ctx.new_cell(caught)?;
+    ctx.vars.push(var, value);
+    let result = eval(handler, ctx);
+    ctx.vars.truncate(scope_start);
+    result
+}
+
+// (unwind-protect block cleanup*): cleanup expressions are evaluated even if block fails
+fn eval_unwind_protect(
+    list: &[parser::SExpr],
+    ctx: &mut EvalContext,
+) -> Result<Step, Box<dyn std::error::Error + Send + Sync>> {
+    if list.len() < 2 {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "Statement list `unwind-protect` must have at least 2 elements: `unwind-protect`, block, cleanup*.",
+        )));
+    }
+    let result = eval(&list[1], ctx);
+    for cleanup in &list[2..] {
+        eval(cleanup, ctx)?;
+    }
+    Ok(Step::Done(result?))
+}
+
+// Lambda `(lambda-captured ((#record-type <type>)) (params*) (builtin #record-type params* extra*))`
+fn record_procedure(
+    type_cell: &Arc<Mutex<parser::SExpr>>,
+    params: &[&str],
+    extra_args: &[parser::SExpr],
+    builtin: &str,
+) -> parser::SExpr {
+    let symbol =
+        |name: &str| parser::SExpr::Atom(parser::Atom::Symbol(symbols::Symbol::intern(name)));
+    let mut body: Vec<parser::SExpr> = vec![symbol(builtin), symbol("#record-type")];
+    body.extend(params.iter().map(|param| symbol(param)));
+    body.extend(extra_args.iter().cloned());
+    parser::SExpr::List(vec![
+        symbol("lambda-captured"),
+        parser::SExpr::List(vec![parser::SExpr::List(vec![
+            symbol("#record-type"),
+            parser::SExpr::Ref(type_cell.clone()),
+        ])]),
+        parser::SExpr::List(params.iter().map(|param| symbol(param)).collect()),
+        parser::SExpr::List(body),
+    ])
+}
+
+// (defstruct name field*) defines in the current context:
+// (make-name field*), (name? value), (name-field record) and (set-name-field! record value)
+fn eval_defstruct(
+    list: &[parser::SExpr],
+    ctx: &mut EvalContext,
+) -> Result<Step, Box<dyn std::error::Error + Send + Sync>> {
+    let mut names: Vec<String> = Vec::new();
+    for elem in &list[1..] {
+        if let parser::SExpr::Atom(parser::Atom::Symbol(name)) = elem {
+            names.push(name.to_string());
+        } else {
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Statement list `defstruct` must consist of symbols: `defstruct`, type name, field names*.",
+            )));
+        }
+    }
+    if names.is_empty() {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "Statement list `defstruct` must consist of symbols: `defstruct`, type name, field names*.",
+        )));
+    }
+    let type_name = names.remove(0);
+    let record_type = parser::SExpr::RecordType(Arc::new(parser::RecordType {
+        name: type_name.clone(),
+        fields: names.clone(),
+    }));
+    let type_cell = ctx.new_cell(record_type.clone())?;
+
+    let field_params: Vec<&str> = names.iter().map(|name| name.as_str()).collect();
+    let mut definitions: Vec<(String, parser::SExpr)> = vec![
+        (
+            format!("make-{}", type_name),
+            record_procedure(&type_cell, &field_params, &[], "record-construct"),
+        ),
+        (
+            format!("{}?", type_name),
+            record_procedure(&type_cell, &["value"], &[], "record-of-type?"),
+        ),
+    ];
+    for (i, field) in names.iter().enumerate() {
+        let index = [parser::SExpr::Atom(parser::Atom::Number(i as f64))];
+        definitions.push((
+            format!("{}-{}", type_name, field),
+            record_procedure(&type_cell, &["record"], &index, "record-field"),
+        ));
+        definitions.push((
+            format!("set-{}-{}!", type_name, field),
+            record_procedure(
+                &type_cell,
+                &["record", "value"],
+                &index,
+                "record-set-field!",
+            ),
+        ));
+    }
+    for (name, procedure) in definitions {
+        let value = ctx.new_cell(procedure)?;
+        ctx.vars.push(symbols::Symbol::intern(&name), value);
+    }
+    Ok(Step::Done(record_type))
+}
+
+// Form `(form x)` of quasiquote template, for form `unquote`, `unquote-splicing` or `quasiquote`
+fn template_form(template: &parser::SExpr, form: SpecialForm) -> Option<&parser::SExpr> {
+    match template {
+        parser::SExpr::List(list) if list.len() == 2 => match &list[0] {
+            parser::SExpr::Atom(parser::Atom::Symbol(sym))
+                if SpecialForm::of(sym) == Some(form) =>
+            {
+                Some(&list[1])
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn template_form_list(form: SpecialForm, value: parser::SExpr) -> parser::SExpr {
+    parser::SExpr::List(vec![
+        parser::SExpr::Atom(parser::Atom::Symbol(form.symbol())),
+        value,
+    ])
+}
+
+// Builds value of quasiquote template. `depth` is the number of enclosing quasiquotes;
+// only unquotes at depth 1 are evaluated, deeper ones are kept with their level decreased.
+fn quasiquote(
+    template: &parser::SExpr,
+    depth: usize,
+    ctx: &mut EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    if let Some(inner) = template_form(template, SpecialForm::Unquote) {
+        return if depth == 1 {
+            eval(inner, ctx)
+        } else {
+            Ok(template_form_list(
+                SpecialForm::Unquote,
+                quasiquote(inner, depth - 1, ctx)?,
+            ))
+        };
+    }
+    if let Some(inner) = template_form(template, SpecialForm::Quasiquote) {
+        return Ok(template_form_list(
+            SpecialForm::Quasiquote,
+            quasiquote(inner, depth + 1, ctx)?,
+        ));
+    }
+    if template_form(template, SpecialForm::UnquoteSplicing).is_some() && depth == 1 {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "`unquote-splicing` must be used inside a list of quasiquote template.",
+        )));
+    }
+    let list = match template {
+        parser::SExpr::List(list) => list,
+        _ => return Ok(template.clone()),
+    };
+
+    let mut result: Vec<parser::SExpr> = Vec::new();
+    for elem in list {
+        match template_form(elem, SpecialForm::UnquoteSplicing) {
+            Some(inner) if depth == 1 => match resolve_reference(&eval(inner, ctx)?) {
+                parser::SExpr::List(spliced) => result.extend(spliced),
+                _ => {
+                    return Err(Box::new(std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        "Argument of `unquote-splicing` must evaluate to a list.",
+                    )))
+                }
+            },
+            Some(inner) => result.push(template_form_list(
+                SpecialForm::UnquoteSplicing,
+                quasiquote(inner, depth - 1, ctx)?,
+            )),
+            None => result.push(quasiquote(elem, depth, ctx)?),
+        }
+    }
+    ctx.charge_list_memory(result.len())?;
+    Ok(parser::SExpr::List(result))
+}
+
+// (defmacro name (params) block) or (defmacro name (capture-list) (params) block).
+// Macro's expander is a lambda called with unevaluated arguments of macro call;
+// its result is evaluated instead of the call.
+fn eval_defmacro(
+    list: &[parser::SExpr],
+    ctx: &mut EvalContext,
+) -> Result<Step, Box<dyn std::error::Error + Send + Sync>> {
+    let name: symbols::Symbol = match list.get(1) {
+        Some(parser::SExpr::Atom(parser::Atom::Symbol(name))) if list.len() == 4 || list.len() == 5 => {
+            name.clone()
+        }
+        _ => {
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Statement list `defmacro` must have 4 or 5 elements: `defmacro`, name, capture-list?, args, block.",
+            )))
+        }
+    };
+    let mut lambda_list: Vec<parser::SExpr> = vec![parser::SExpr::Atom(parser::Atom::Symbol(
+        SpecialForm::Lambda.symbol(),
+    ))];
+    if list.len() == 4 {
+        lambda_list.push(parser::SExpr::List(vec![]));
+    }
+    lambda_list.extend(list[2..].iter().cloned());
+    let expander: parser::SExpr = match eval_lambda(&lambda_list, ctx)? {
+        Step::Done(expander) => expander,
+        _ => unreachable!(),
+    };
+    // Replaced macro is dropped only after the lock is released
+    let _replaced = ctx
+        .state
+        .lock()
+        .unwrap()
+        .macros
+        .insert(name.clone(), Macro::Procedure(expander));
+    Ok(Step::Done(parser::SExpr::Atom(parser::Atom::Symbol(name))))
+}
+
+// (define-syntax name (syntax-rules (literal*) (pattern template)+))
+fn eval_define_syntax(
+    list: &[parser::SExpr],
+    ctx: &mut EvalContext,
+) -> Result<Step, Box<dyn std::error::Error + Send + Sync>> {
+    match list {
+        [_, parser::SExpr::Atom(parser::Atom::Symbol(name)), spec] => {
+            let rules = syntax_rules::SyntaxRules::parse(name, spec)?;
+            let _replaced = ctx
+                .state
+                .lock()
+                .unwrap()
+                .macros
+                .insert(name.clone(), Macro::SyntaxRules(Arc::new(rules)));
+            Ok(Step::Done(parser::SExpr::Atom(parser::Atom::Symbol(name.clone()))))
+        }
+        _ => Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "Statement list `define-syntax` must have 3 elements: `define-syntax`, name, syntax-rules.",
+        ))),
+    }
+}
+
+/// Expands `sexpr` once if it is a macro call, returns `None` otherwise
+pub fn macroexpand_1(
+    sexpr: &parser::SExpr,
+    ctx: &mut EvalContext,
+) -> Result<Option<parser::SExpr>, Box<dyn std::error::Error + Send + Sync>> {
+    let list = match sexpr {
+        parser::SExpr::List(list) => list,
+        _ => return Ok(None),
+    };
+    let macro_def: Macro = match list.first() {
+        Some(parser::SExpr::Atom(parser::Atom::Symbol(name))) => {
+            match ctx.state.lock().unwrap().macros.get(name) {
+                Some(expander) => expander.clone(),
+                None => return Ok(None),
+            }
+        }
+        _ => return Ok(None),
+    };
+    match macro_def {
+        Macro::Procedure(expander) => {
+            let expansion = apply(&expander, list[1..].to_vec(), ctx)?;
+            Ok(Some(resolve_code(&expansion)))
+        }
+        Macro::SyntaxRules(rules) => Ok(Some(rules.expand(sexpr, ctx)?)),
+    }
+}
+
+// Replaces references to variables (e.g. inserted by unquote) with their values in the whole
+// expression, so that it can be evaluated as code
+fn resolve_code(value: &parser::SExpr) -> parser::SExpr {
+    match resolve_reference(value) {
+        parser::SExpr::List(list) => parser::SExpr::List(list.iter().map(resolve_code).collect()),
+        other => other,
+    }
+}
+
+/// Expands `sexpr` repeatedly until it is no longer a macro call. Subexpressions are not expanded.
+pub fn macroexpand(
+    sexpr: &parser::SExpr,
+    ctx: &mut EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    let mut sexpr: parser::SExpr = sexpr.clone();
+    while let Some(expansion) = macroexpand_1(&sexpr, ctx)? {
+        sexpr = expansion;
+    }
+    Ok(sexpr)
+}
+
+fn expand_all_macros(
+    list: &[parser::SExpr],
+    ctx: &mut EvalContext,
+) -> Result<Vec<parser::SExpr>, Box<dyn std::error::Error + Send + Sync>> {
+    list.iter().map(|elem| expand_macros(elem, ctx)).collect()
+}
+
+/// Expansion pass run before evaluation: expands all macro calls in `sexpr` and its
+/// subexpressions. Quoted data and variable names are left untouched.
+pub fn expand_macros(
+    sexpr: &parser::SExpr,
+    ctx: &mut EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    let sexpr: parser::SExpr = macroexpand(sexpr, ctx)?;
+    let list = match &sexpr {
+        parser::SExpr::List(list) => list,
+        _ => return Ok(sexpr),
+    };
+    let form = match list.first() {
+        Some(parser::SExpr::Atom(parser::Atom::Symbol(form))) => SpecialForm::of(form),
+        _ => return Ok(parser::SExpr::List(expand_all_macros(list, ctx)?)),
+    };
+    let expanded: Vec<parser::SExpr> = match form {
+        Some(
+            SpecialForm::Quote
+            | SpecialForm::Quasiquote
+            | SpecialForm::LambdaCaptured
+            | SpecialForm::Builtin
+            | SpecialForm::Defstruct
+            | SpecialForm::Defmacro
+            | SpecialForm::DefineSyntax,
+        ) => return Ok(sexpr.clone()),
+        // Only the block of lambda is code
+        Some(SpecialForm::Lambda) if list.len() == 4 => {
+            let mut expanded: Vec<parser::SExpr> = list[..3].to_vec();
+            expanded.push(expand_macros(&list[3], ctx)?);
+            expanded
+        }
+        // Variable definitions (name value) and clauses (cond block) are not calls themselves
+        Some(SpecialForm::Let | SpecialForm::Cond) => {
+            let mut expanded: Vec<parser::SExpr> = vec![list[0].clone()];
+            for (i, elem) in list.iter().enumerate().skip(1) {
+                expanded.push(match elem {
+                    parser::SExpr::List(pair)
+                        if form == Some(SpecialForm::Cond) || i < list.len() - 1 =>
+                    {
+                        parser::SExpr::List(expand_all_macros(pair, ctx)?)
+                    }
+                    _ => expand_macros(elem, ctx)?,
+                });
+            }
+            expanded
+        }
+        _ => expand_all_macros(list, ctx)?,
+    };
+    Ok(parser::SExpr::List(expanded))
+}
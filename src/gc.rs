@@ -0,0 +1,300 @@
+use crate::generators;
+use crate::parser;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, Weak};
+
+// Collection is triggered when the heap grows to twice its size after the last collection,
+// but not below this many objects
+const MIN_COLLECTION_THRESHOLD: usize = 1024;
+
+/// Statistics of interpreter heap
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HeapStats {
+    /// Variable cells, hash tables, vectors, records and generators not freed yet
+    pub objects: usize,
+    /// Number of garbage collections run so far
+    pub collections: u64,
+    /// Number of objects freed by garbage collections so far
+    pub reclaimed: u64,
+}
+
+/// Shared value whose contents can refer to other shared values
+pub(crate) enum Object {
+    Cell(Arc<Mutex<parser::SExpr>>),
+    Hash(Arc<Mutex<HashMap<parser::HashKey, parser::SExpr>>>),
+    Vector(Arc<Mutex<Vec<parser::SExpr>>>),
+    Record(Arc<Mutex<parser::Record>>),
+    Generator(Arc<Mutex<generators::Generator>>),
+}
+
+enum WeakObject {
+    Cell(Weak<Mutex<parser::SExpr>>),
+    Hash(Weak<Mutex<HashMap<parser::HashKey, parser::SExpr>>>),
+    Vector(Weak<Mutex<Vec<parser::SExpr>>>),
+    Record(Weak<Mutex<parser::Record>>),
+    Generator(Weak<Mutex<generators::Generator>>),
+}
+
+impl WeakObject {
+    fn upgrade(&self) -> Option<Object> {
+        match self {
+            WeakObject::Cell(cell) => cell.upgrade().map(Object::Cell),
+            WeakObject::Hash(table) => table.upgrade().map(Object::Hash),
+            WeakObject::Vector(vector) => vector.upgrade().map(Object::Vector),
+            WeakObject::Record(record) => record.upgrade().map(Object::Record),
+            WeakObject::Generator(generator) => generator.upgrade().map(Object::Generator),
+        }
+    }
+
+    fn is_freed(&self) -> bool {
+        match self {
+            WeakObject::Cell(cell) => cell.strong_count() == 0,
+            WeakObject::Hash(table) => table.strong_count() == 0,
+            WeakObject::Vector(vector) => vector.strong_count() == 0,
+            WeakObject::Record(record) => record.strong_count() == 0,
+            WeakObject::Generator(generator) => generator.strong_count() == 0,
+        }
+    }
+}
+
+impl Object {
+    fn downgrade(&self) -> WeakObject {
+        match self {
+            Object::Cell(cell) => WeakObject::Cell(Arc::downgrade(cell)),
+            Object::Hash(table) => WeakObject::Hash(Arc::downgrade(table)),
+            Object::Vector(vector) => WeakObject::Vector(Arc::downgrade(vector)),
+            Object::Record(record) => WeakObject::Record(Arc::downgrade(record)),
+            Object::Generator(generator) => WeakObject::Generator(Arc::downgrade(generator)),
+        }
+    }
+
+    fn address(&self) -> usize {
+        match self {
+            Object::Cell(cell) => Arc::as_ptr(cell) as *const u8 as usize,
+            Object::Hash(table) => Arc::as_ptr(table) as *const u8 as usize,
+            Object::Vector(vector) => Arc::as_ptr(vector) as *const u8 as usize,
+            Object::Record(record) => Arc::as_ptr(record) as *const u8 as usize,
+            Object::Generator(generator) => Arc::as_ptr(generator) as *const u8 as usize,
+        }
+    }
+
+    fn strong_count(&self) -> usize {
+        match self {
+            Object::Cell(cell) => Arc::strong_count(cell),
+            Object::Hash(table) => Arc::strong_count(table),
+            Object::Vector(vector) => Arc::strong_count(vector),
+            Object::Record(record) => Arc::strong_count(record),
+            Object::Generator(generator) => Arc::strong_count(generator),
+        }
+    }
+
+    // Addresses of shared values referred to by contents, or `None` if object is locked
+    fn references(&self) -> Option<Vec<usize>> {
+        let mut addresses = Vec::new();
+        match self {
+            Object::Cell(cell) => references(&*cell.try_lock().ok()?, &mut addresses),
+            Object::Hash(table) => {
+                for value in table.try_lock().ok()?.values() {
+                    references(value, &mut addresses);
+                }
+            }
+            Object::Vector(vector) => {
+                for value in vector.try_lock().ok()?.iter() {
+                    references(value, &mut addresses);
+                }
+            }
+            Object::Record(record) => {
+                for value in record.try_lock().ok()?.fields.iter() {
+                    references(value, &mut addresses);
+                }
+            }
+            // Generator with started body is a root
+            Object::Generator(generator) => {
+                let visible = generator
+                    .try_lock()
+                    .ok()?
+                    .visit_values(|value| references(value, &mut addresses));
+                if !visible {
+                    return None;
+                }
+            }
+        }
+        Some(addresses)
+    }
+
+    // Empties object, moving its contents to `garbage`
+    fn clear(&self, garbage: &mut Vec<parser::SExpr>) {
+        match self {
+            Object::Cell(cell) => {
+                if let Ok(mut value) = cell.try_lock() {
+                    garbage.push(std::mem::replace(&mut value, parser::SExpr::List(vec![])));
+                }
+            }
+            Object::Hash(table) => {
+                if let Ok(mut table) = table.try_lock() {
+                    garbage.extend(table.drain().map(|(_, value)| value));
+                }
+            }
+            Object::Vector(vector) => {
+                if let Ok(mut vector) = vector.try_lock() {
+                    garbage.append(&mut vector);
+                }
+            }
+            Object::Record(record) => {
+                if let Ok(mut record) = record.try_lock() {
+                    garbage.append(&mut record.fields);
+                }
+            }
+            Object::Generator(generator) => {
+                if let Ok(mut generator) = generator.try_lock() {
+                    generator.clear(garbage);
+                }
+            }
+        }
+    }
+}
+
+fn references(value: &parser::SExpr, addresses: &mut Vec<usize>) {
+    match value {
+        parser::SExpr::List(list) => {
+            for elem in list {
+                references(elem, addresses);
+            }
+        }
+        parser::SExpr::Ref(cell) => addresses.push(Arc::as_ptr(cell) as *const u8 as usize),
+        parser::SExpr::Hash(table) => addresses.push(Arc::as_ptr(table) as *const u8 as usize),
+        parser::SExpr::Vector(vector) => addresses.push(Arc::as_ptr(vector) as *const u8 as usize),
+        parser::SExpr::Record(record) => addresses.push(Arc::as_ptr(record) as *const u8 as usize),
+        parser::SExpr::Generator(generator) => {
+            addresses.push(Arc::as_ptr(generator) as *const u8 as usize)
+        }
+        // Channels and threads are not tracked, so values they hold are treated as reachable
+        _ => {}
+    }
+}
+
+/// All shared values allocated by one interpreter
+#[derive(Default)]
+pub(crate) struct Heap {
+    objects: Vec<WeakObject>,
+    // Number of objects after the last collection or pruning of freed ones
+    live_len: usize,
+    collection_pending: bool,
+    collections: u64,
+    reclaimed: u64,
+}
+
+impl Heap {
+    pub(crate) fn register(&mut self, object: &Object) {
+        self.objects.push(object.downgrade());
+        if self.objects.len() >= 2 * self.live_len.max(MIN_COLLECTION_THRESHOLD) {
+            self.prune();
+            self.collection_pending = true;
+        }
+    }
+
+    // Forgets objects already freed by reference counting
+    fn prune(&mut self) {
+        self.objects.retain(|object| !object.is_freed());
+        self.live_len = self.objects.len();
+    }
+
+    /// Whether heap has grown enough to be collected at the next safe point
+    pub(crate) fn take_collection_pending(&mut self) -> bool {
+        std::mem::take(&mut self.collection_pending)
+    }
+
+    pub(crate) fn live_objects(&mut self) -> Vec<Object> {
+        self.prune();
+        self.objects
+            .iter()
+            .filter_map(WeakObject::upgrade)
+            .collect()
+    }
+
+    pub(crate) fn live_cells(&mut self) -> Vec<Arc<Mutex<parser::SExpr>>> {
+        self.prune();
+        self.objects
+            .iter()
+            .filter_map(|object| match object {
+                WeakObject::Cell(cell) => cell.upgrade(),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub(crate) fn finish_collection(&mut self, reclaimed: usize) {
+        self.prune();
+        self.collections += 1;
+        self.reclaimed += reclaimed as u64;
+    }
+
+    pub(crate) fn stats(&mut self) -> HeapStats {
+        self.prune();
+        HeapStats {
+            objects: self.objects.len(),
+            collections: self.collections,
+            reclaimed: self.reclaimed,
+        }
+    }
+}
+
+/// Frees objects reachable only from each other, returning their number. Objects referred to
+/// from outside of `objects` (variables in scope, values being evaluated, started generator
+/// bodies) and objects locked right now are roots.
+///
+/// Must be called without holding any interpreter lock, as freeing values may run code.
+pub(crate) fn collect(objects: Vec<Object>) -> usize {
+    let positions: HashMap<usize, usize> = objects
+        .iter()
+        .enumerate()
+        .map(|(pos, object)| (object.address(), pos))
+        .collect();
+    // References from outside of heap; `objects` itself holds one
+    let mut external: Vec<usize> = objects
+        .iter()
+        .map(|object| object.strong_count() - 1)
+        .collect();
+    let edges: Vec<Option<Vec<usize>>> = objects
+        .iter()
+        .map(|object| {
+            object.references().map(|addresses| {
+                addresses
+                    .iter()
+                    .filter_map(|address| positions.get(address).copied())
+                    .collect()
+            })
+        })
+        .collect();
+    for targets in edges.iter().flatten() {
+        for &target in targets {
+            external[target] = external[target].saturating_sub(1);
+        }
+    }
+
+    let mut reachable = vec![false; objects.len()];
+    let mut pending: Vec<usize> = (0..objects.len())
+        .filter(|&pos| external[pos] > 0 || edges[pos].is_none())
+        .collect();
+    while let Some(pos) = pending.pop() {
+        if std::mem::replace(&mut reachable[pos], true) {
+            continue;
+        }
+        if let Some(targets) = &edges[pos] {
+            pending.extend(targets.iter().filter(|&&target| !reachable[target]));
+        }
+    }
+
+    // Breaking cycles lets reference counting free them once `garbage` is dropped
+    let mut garbage = Vec::new();
+    let mut reclaimed = 0;
+    for (object, reachable) in objects.iter().zip(reachable) {
+        if !reachable {
+            object.clear(&mut garbage);
+            reclaimed += 1;
+        }
+    }
+    drop(garbage);
+    reclaimed
+}
@@ -0,0 +1,380 @@
+use crate::conditions;
+use crate::evaluator;
+use crate::gc;
+use crate::limits;
+use crate::parser;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Generator body runs on its own thread, which is used only as a separate stack: control is
+/// handed over through channels and the thread handing it over blocks until it gets it back.
+/// The stack is accounted against the memory limit while the body is running or suspended.
+pub(crate) const GENERATOR_STACK_SIZE: usize = 8 * 1024 * 1024;
+
+enum ToGenerator {
+    Resume,
+    Close,
+}
+
+enum FromGenerator {
+    Yield(parser::SExpr),
+    Return,
+    Fail(Box<dyn std::error::Error + Send + Sync>),
+}
+
+/// Ends of channels used by `yield` in generator body
+pub(crate) struct CoroutineLink {
+    to_caller: mpsc::Sender<FromGenerator>,
+    // Locked only by the generator thread; the lock makes the link shareable
+    from_caller: Mutex<mpsc::Receiver<ToGenerator>>,
+}
+
+/// Unwinds generator body when generator is dropped before it finished.
+/// It is not caught by `try`, but cleanups of `finally` and `unwind-protect` run.
+#[derive(Debug)]
+pub(crate) struct GeneratorClosed;
+
+impl std::fmt::Display for GeneratorClosed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Generator closed.")
+    }
+}
+
+impl std::error::Error for GeneratorClosed {}
+
+struct Coroutine {
+    to_generator: mpsc::Sender<ToGenerator>,
+    from_generator: mpsc::Receiver<FromGenerator>,
+    thread: Option<thread::JoinHandle<()>>,
+    // Number of running bodies of the interpreter, decremented when the thread is joined
+    live: Arc<AtomicUsize>,
+}
+
+impl Coroutine {
+    fn start(
+        thunk: parser::SExpr,
+        ctx: &evaluator::EvalContext,
+        live: Arc<AtomicUsize>,
+    ) -> Result<Coroutine, Box<dyn std::error::Error + Send + Sync>> {
+        let (to_generator, from_caller) = mpsc::channel::<ToGenerator>();
+        let (to_caller, from_generator) = mpsc::channel::<FromGenerator>();
+        let mut generator_ctx = ctx.new_coroutine_context(CoroutineLink {
+            to_caller: to_caller.clone(),
+            from_caller: Mutex::new(from_caller),
+        });
+        let thread = thread::Builder::new()
+            .stack_size(GENERATOR_STACK_SIZE)
+            .spawn(move || {
+                limits::set_stack_limit(GENERATOR_STACK_SIZE);
+                // Nothing shared is touched before the first `next` hands control over
+                let link = generator_ctx.coroutine_link().unwrap();
+                let started = matches!(
+                    link.from_caller.lock().unwrap().recv(),
+                    Ok(ToGenerator::Resume)
+                );
+                drop(link);
+                let message = if !started {
+                    FromGenerator::Return
+                } else {
+                    match evaluator::apply(&thunk, vec![], &mut generator_ctx) {
+                        Ok(_) => FromGenerator::Return,
+                        Err(err) if err.is::<GeneratorClosed>() => FromGenerator::Return,
+                        Err(err) => FromGenerator::Fail(err),
+                    }
+                };
+                // Values shared with the other thread must be dropped before handing control back
+                drop(thunk);
+                drop(generator_ctx);
+                let _ = to_caller.send(message);
+            });
+        match thread {
+            Ok(thread) => Ok(Coroutine {
+                to_generator,
+                from_generator,
+                thread: Some(thread),
+                live,
+            }),
+            Err(err) => {
+                live.fetch_sub(1, Ordering::SeqCst);
+                Err(Box::new(err))
+            }
+        }
+    }
+
+    // Runs generator body until the next `yield`; `None` when the body finished
+    fn resume(
+        &mut self,
+    ) -> Result<Option<parser::SExpr>, Box<dyn std::error::Error + Send + Sync>> {
+        let _ = self.to_generator.send(ToGenerator::Resume);
+        let message = self.from_generator.recv();
+        match message {
+            Ok(FromGenerator::Yield(value)) => Ok(Some(value)),
+            Ok(FromGenerator::Return) | Err(_) => {
+                self.join();
+                Ok(None)
+            }
+            Ok(FromGenerator::Fail(err)) => {
+                self.join();
+                Err(err)
+            }
+        }
+    }
+
+    fn join(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+            self.live.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+}
+
+impl Drop for Coroutine {
+    // Unwinds body suspended in `yield`, waiting for it, as it may still use shared values
+    fn drop(&mut self) {
+        if self.thread.is_none() {
+            return;
+        }
+        while self.to_generator.send(ToGenerator::Close).is_ok() {
+            match self.from_generator.recv() {
+                Ok(FromGenerator::Yield(_)) => continue,
+                _ => break,
+            }
+        }
+        self.join();
+    }
+}
+
+enum Source {
+    // Lambda without parameters, whose body is started by the first `next`
+    Lambda(parser::SExpr),
+    Coroutine(Coroutine),
+    Map {
+        source: Arc<Mutex<Generator>>,
+        func: parser::SExpr,
+    },
+    Filter {
+        source: Arc<Mutex<Generator>>,
+        pred: parser::SExpr,
+    },
+    Finished,
+}
+
+/// Lazy sequence created by `generator`, or by `map`/`filter` of another generator
+pub struct Generator {
+    source: Source,
+    // Value fetched by `done?` and not yet returned by `next`
+    peeked: Option<parser::SExpr>,
+}
+
+impl Generator {
+    /// Calls `visit` with every value held by generator. Returns `false` without calling it if
+    /// the body was started, as values on the body's own stack can't be seen.
+    pub(crate) fn visit_values(&self, mut visit: impl FnMut(&parser::SExpr)) -> bool {
+        if let Some(value) = &self.peeked {
+            visit(value);
+        }
+        match &self.source {
+            Source::Lambda(thunk) => visit(thunk),
+            Source::Coroutine(_) => return false,
+            Source::Map {
+                source,
+                func: lambda,
+            }
+            | Source::Filter {
+                source,
+                pred: lambda,
+            } => {
+                visit(&parser::SExpr::Generator(source.clone()));
+                visit(lambda);
+            }
+            Source::Finished => {}
+        }
+        true
+    }
+
+    /// Empties generator whose body wasn't started, moving values it held to `garbage`
+    pub(crate) fn clear(&mut self, garbage: &mut Vec<parser::SExpr>) {
+        if matches!(self.source, Source::Coroutine(_)) {
+            return;
+        }
+        garbage.extend(self.peeked.take());
+        match std::mem::replace(&mut self.source, Source::Finished) {
+            Source::Lambda(thunk) => garbage.push(thunk),
+            Source::Map {
+                source,
+                func: lambda,
+            }
+            | Source::Filter {
+                source,
+                pred: lambda,
+            } => {
+                garbage.extend([parser::SExpr::Generator(source), lambda]);
+            }
+            Source::Coroutine(_) | Source::Finished => {}
+        }
+    }
+}
+
+impl std::fmt::Debug for Generator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Generator")
+    }
+}
+
+fn generator_value(source: Source, ctx: &evaluator::EvalContext) -> parser::SExpr {
+    let generator = Arc::new(Mutex::new(Generator {
+        source,
+        peeked: None,
+    }));
+    ctx.register_object(gc::Object::Generator(generator.clone()));
+    parser::SExpr::Generator(generator)
+}
+
+/// Generator yielding values passed to `yield` by body of lambda `thunk`
+pub fn new_generator(thunk: parser::SExpr, ctx: &evaluator::EvalContext) -> parser::SExpr {
+    generator_value(Source::Lambda(thunk), ctx)
+}
+
+/// Generator of `(func x)` for values `x` of generator `source`
+pub fn map_generator(
+    source: Arc<Mutex<Generator>>,
+    func: parser::SExpr,
+    ctx: &evaluator::EvalContext,
+) -> parser::SExpr {
+    generator_value(Source::Map { source, func }, ctx)
+}
+
+/// Generator of values `x` of generator `source` for which `(pred x)` is true
+pub fn filter_generator(
+    source: Arc<Mutex<Generator>>,
+    pred: parser::SExpr,
+    ctx: &evaluator::EvalContext,
+) -> parser::SExpr {
+    generator_value(Source::Filter { source, pred }, ctx)
+}
+
+/// Closes generator whose body is suspended in `yield`, running its cleanups
+pub(crate) fn close(generator: &Arc<Mutex<Generator>>) {
+    let coroutine = match generator.try_lock() {
+        Ok(mut generator) if matches!(generator.source, Source::Coroutine(_)) => {
+            generator.peeked = None;
+            std::mem::replace(&mut generator.source, Source::Finished)
+        }
+        _ => return,
+    };
+    // Cleanups may use the generator itself, so it's unlocked first
+    drop(coroutine);
+}
+
+fn already_running() -> Box<dyn std::error::Error + Send + Sync> {
+    Box::new(std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        "Generator is already running.",
+    ))
+}
+
+// Produces the next value of generator, `None` if it is exhausted
+fn fetch(
+    generator: &Arc<Mutex<Generator>>,
+    ctx: &mut evaluator::EvalContext,
+) -> Result<Option<parser::SExpr>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut generator_ref = generator.try_lock().map_err(|_| already_running())?;
+    if let Some(value) = generator_ref.peeked.take() {
+        return Ok(Some(value));
+    }
+    if let Source::Lambda(thunk) = &generator_ref.source {
+        let live = ctx.start_generator(generator)?;
+        let coroutine = Coroutine::start(thunk.clone(), ctx, live)?;
+        generator_ref.source = Source::Coroutine(coroutine);
+    }
+    let value: Option<parser::SExpr> = match &mut generator_ref.source {
+        Source::Lambda(_) => unreachable!(),
+        Source::Coroutine(coroutine) => coroutine.resume()?,
+        // Lock is released while Lisp functions run, as they may use this generator too
+        Source::Map { source, func } => {
+            let (source, func) = (source.clone(), func.clone());
+            drop(generator_ref);
+            let value = match fetch(&source, ctx)? {
+                Some(value) => Some(evaluator::apply(&func, vec![value], ctx)?),
+                None => None,
+            };
+            generator_ref = generator.try_lock().map_err(|_| already_running())?;
+            value
+        }
+        Source::Filter { source, pred } => {
+            let (source, pred) = (source.clone(), pred.clone());
+            drop(generator_ref);
+            let mut value = None;
+            while let Some(candidate) = fetch(&source, ctx)? {
+                if evaluator::value_is_true(&evaluator::apply(&pred, vec![candidate.clone()], ctx)?)
+                {
+                    value = Some(candidate);
+                    break;
+                }
+            }
+            generator_ref = generator.try_lock().map_err(|_| already_running())?;
+            value
+        }
+        Source::Finished => None,
+    };
+    if value.is_none() {
+        // Dropping coroutine of finished body only joins its thread
+        generator_ref.source = Source::Finished;
+    }
+    Ok(value)
+}
+
+/// Returns the next value of generator, or raises condition of kind `generator-exhausted`
+pub fn next(
+    generator: &Arc<Mutex<Generator>>,
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    match fetch(generator, ctx)? {
+        Some(value) => Ok(value),
+        None => Err(Box::new(conditions::Raised {
+            value: conditions::make_condition(
+                ctx,
+                "generator-exhausted",
+                conditions::string_value("Generator has no more values."),
+            ),
+        })),
+    }
+}
+
+/// Whether generator is exhausted. The body runs up to the next `yield` to find out.
+pub fn is_done(
+    generator: &Arc<Mutex<Generator>>,
+    ctx: &mut evaluator::EvalContext,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    match fetch(generator, ctx)? {
+        Some(value) => {
+            generator.try_lock().map_err(|_| already_running())?.peeked = Some(value);
+            Ok(false)
+        }
+        None => Ok(true),
+    }
+}
+
+/// Passes `value` to the caller of `next` and suspends generator body until it is resumed
+pub(crate) fn yield_value(
+    value: parser::SExpr,
+    ctx: &evaluator::EvalContext,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let link =
+        ctx.coroutine_link()
+            .ok_or_else(|| -> Box<dyn std::error::Error + Send + Sync> {
+                Box::new(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "`yield` used outside of generator.",
+                ))
+            })?;
+    let _ = link.to_caller.send(FromGenerator::Yield(value));
+    let message = link.from_caller.lock().unwrap().recv();
+    match message {
+        Ok(ToGenerator::Resume) => Ok(()),
+        Ok(ToGenerator::Close) | Err(_) => Err(Box::new(GeneratorClosed)),
+    }
+}
@@ -0,0 +1,250 @@
+use crate::evaluator;
+use crate::lexer;
+use crate::limits;
+use crate::parser;
+use crate::traceback;
+use crate::vm;
+
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Embeddable interpreter instance. It can be moved to another thread, and independent
+/// instances can run concurrently.
+///
+/// Global variables set with [`Interpreter::set_global`] (or by top-level `set`)
+/// persist between calls to [`Interpreter::eval_str`] and [`Interpreter::eval_file`].
+#[derive(Default)]
+pub struct Interpreter {
+    ctx: evaluator::EvalContext,
+    traceback: Option<traceback::Traceback>,
+    engine: Engine,
+}
+
+/// Implementation which evaluates expressions. Both give the same results, except for
+/// the number of steps counted against fuel (see [`Interpreter::set_fuel`]).
+///
+/// Lambda values have the same shape, `(lambda-captured captures params body)`, except that
+/// the bytecode engine keeps `body` in an [`SExpr::Ref`](crate::SExpr::Ref) cell shared by all
+/// lambdas of one `lambda` form, through which it finds the body's compiled code. Scripts can't
+/// tell the difference, as references are followed wherever values are used, and lambdas
+/// created by either engine can be called by the other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Engine {
+    /// Reference evaluator walking the expression tree
+    #[default]
+    TreeWalker,
+    /// Expressions are compiled to bytecode with resolved variable slots, run by a stack VM
+    Bytecode,
+}
+
+/// Source code parsed once, which can be evaluated by any number of interpreters, including
+/// ones running on other threads. Cloning shares the parsed code.
+///
+/// Macros are expanded and bytecode is compiled by each interpreter evaluating the module, as
+/// both depend on macros and variables defined in it.
+#[derive(Clone)]
+pub struct Module {
+    // Top-level expressions with positions of their first tokens
+    exprs: Arc<[(parser::SExpr, usize)]>,
+    // Line of every token
+    lines: Arc<[usize]>,
+}
+
+impl Module {
+    pub fn parse(code: &str) -> Result<Module, Box<dyn std::error::Error + Send + Sync>> {
+        let (tokens, lines) = lexer::lex_with_lines(String::from(code))?;
+        let exprs = parser::parse_all_with_positions(&tokens)?;
+        Ok(Module {
+            exprs: exprs.into(),
+            lines: lines.into(),
+        })
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Interpreter {
+        Interpreter {
+            ctx: evaluator::EvalContext::new(),
+            traceback: None,
+            engine: Engine::default(),
+        }
+    }
+
+    /// Evaluates every top-level expression in `code` and returns the value of the last one.
+    /// If evaluation fails, its traceback is available from [`Interpreter::traceback`].
+    ///
+    /// Every call starts a thread with 64 MB stack for the evaluation and waits for it. Stack
+    /// memory is only reserved, not committed, but starting the thread costs tens of
+    /// microseconds, so embedders evaluating many small snippets should batch them into one
+    /// call.
+    pub fn eval_str(
+        &mut self,
+        code: &str,
+    ) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+        let module = Module::parse(code).inspect_err(|_| self.traceback = None)?;
+        self.eval_module(&module)
+    }
+
+    /// Evaluates every top-level expression of `module`, like [`Interpreter::eval_str`], on a
+    /// new thread as well
+    pub fn eval_module(
+        &mut self,
+        module: &Module,
+    ) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+        // Evaluation runs on its own thread with known stack size, so that running out of it
+        // fails with `LimitError::StackExhausted` whatever the stack of the calling thread is
+        thread::scope(|scope| {
+            thread::Builder::new()
+                .stack_size(limits::EVAL_STACK_SIZE)
+                .spawn_scoped(scope, || {
+                    limits::set_stack_limit(limits::EVAL_STACK_SIZE);
+                    let result = self.eval_exprs(module);
+                    self.ctx.stop_threads();
+                    self.ctx.close_generators();
+                    result
+                })?
+                .join()
+                .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+        })
+    }
+
+    fn eval_exprs(
+        &mut self,
+        module: &Module,
+    ) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+        self.traceback = None;
+        let mut result: parser::SExpr = parser::SExpr::List(vec![]);
+        self.ctx.take_traceback();
+        for (sexpr, token_pos) in module.exprs.iter() {
+            // Each expression is expanded just before its evaluation, so it can use macros
+            // defined by the preceding ones
+            result = evaluator::expand_macros(sexpr, &mut self.ctx)
+                .and_then(|sexpr| match self.engine {
+                    Engine::Bytecode => vm::eval(&sexpr, &mut self.ctx),
+                    Engine::TreeWalker => evaluator::eval(&sexpr, &mut self.ctx),
+                })
+                .inspect_err(|_| {
+                    self.traceback = Some(traceback::Traceback {
+                        line: module.lines.get(*token_pos).copied(),
+                        frames: self.ctx.take_traceback(),
+                    });
+                })?;
+        }
+        Ok(evaluator::resolve_reference(&result))
+    }
+
+    pub fn eval_file<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+    ) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+        let code = std::fs::read_to_string(path)?;
+        self.eval_str(&code)
+    }
+
+    pub fn engine(&self) -> Engine {
+        self.engine
+    }
+
+    pub fn set_engine(&mut self, engine: Engine) {
+        self.engine = engine;
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<parser::SExpr> {
+        self.ctx.get_var(name)
+    }
+
+    /// Defines the global variable `name`, or overwrites its value if it already exists.
+    pub fn set_global(&mut self, name: &str, value: parser::SExpr) {
+        self.ctx.set_var(name, value);
+    }
+
+    /// Sets maximum depth of nested (non-tail) lambda calls. Exceeding it fails evaluation
+    /// with [`crate::LimitError::RecursionDepthExceeded`]. Recursion running out of stack
+    /// before reaching the limit, e.g. through builtins such as `map`, fails with
+    /// [`crate::LimitError::StackExhausted`].
+    pub fn set_max_recursion_depth(&mut self, max_depth: usize) {
+        self.ctx.set_max_recursion_depth(max_depth);
+    }
+
+    /// Limits the number of evaluation steps; `None` removes the limit. Engines count steps
+    /// differently: the tree-walker counts every evaluated subexpression, while the bytecode
+    /// engine counts only top-level expressions, lambda calls and loop iterations, so the same
+    /// code uses less fuel on it. Both stop any unbounded loop or recursion. The budget is
+    /// shared by all following evaluations and running out of it fails evaluation with
+    /// [`crate::LimitError::FuelExhausted`].
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.ctx.set_fuel(fuel);
+    }
+
+    pub fn remaining_fuel(&self) -> Option<u64> {
+        self.ctx.fuel()
+    }
+
+    /// Evaluation running past `deadline` fails with [`crate::LimitError::Timeout`].
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.ctx.set_deadline(deadline);
+    }
+
+    /// Sets deadline `timeout` from now.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.ctx.set_deadline(Some(Instant::now() + timeout));
+    }
+
+    /// Returns handle which can be used from another thread to stop evaluation with
+    /// [`crate::LimitError::Cancelled`].
+    pub fn cancel_handle(&self) -> crate::limits::CancelHandle {
+        self.ctx.cancel_handle()
+    }
+
+    /// Limits estimated memory in bytes used by script's lists, strings, symbols and variables;
+    /// `None` removes the limit. Exceeding it fails evaluation with
+    /// [`crate::LimitError::MemoryLimitExceeded`].
+    pub fn set_memory_limit(&mut self, memory_limit: Option<usize>) {
+        self.ctx.set_memory_limit(memory_limit);
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        self.ctx.memory_usage()
+    }
+
+    /// Sets how many generator bodies may be running or suspended at once; default is
+    /// [`crate::evaluator::DEFAULT_MAX_GENERATORS`]. Every body holds a thread, whose 8 MB
+    /// stack also counts against the memory limit. Starting one more fails evaluation with
+    /// [`crate::LimitError::GeneratorLimitExceeded`]. Generators still suspended when
+    /// evaluation ends are closed, running their cleanups, and are exhausted afterwards.
+    pub fn set_max_generators(&mut self, max_generators: usize) {
+        self.ctx.set_max_generators(max_generators);
+    }
+
+    /// Sets how many threads started by `spawn` may be running at once; default is
+    /// [`crate::evaluator::DEFAULT_MAX_THREADS`]. Every thread's 8 MB stack counts against the
+    /// memory limit while it runs, as do values sent to channels until they are received.
+    /// Starting one more fails evaluation with [`crate::LimitError::ThreadLimitExceeded`].
+    /// Threads still running when evaluation ends are stopped with
+    /// [`crate::LimitError::Cancelled`] and waited for.
+    pub fn set_max_threads(&mut self, max_threads: usize) {
+        self.ctx.set_max_threads(max_threads);
+    }
+
+    /// Frees values reachable only through reference cycles, such as closure stored in
+    /// variable it captures, returning their number. Runs automatically whenever the heap
+    /// doubles in size.
+    pub fn collect_garbage(&mut self) -> usize {
+        self.ctx.collect_garbage()
+    }
+
+    pub fn heap_stats(&self) -> crate::gc::HeapStats {
+        self.ctx.heap_stats()
+    }
+
+    /// Call stack of the last failed evaluation, `None` if the last evaluation succeeded
+    pub fn traceback(&self) -> Option<&traceback::Traceback> {
+        self.traceback.as_ref()
+    }
+
+    pub fn context(&mut self) -> &mut evaluator::EvalContext {
+        &mut self.ctx
+    }
+}
@@ -1,99 +1,155 @@
+use crate::symbols;
+
 #[derive(Debug)]
 pub enum Token {
     LeftParen,
     RightParen,
+    LeftBrace,
+    RightBrace,
+    LeftBracket,
+    RightBracket,
+    Quote,
+    Quasiquote,
+    Unquote,
+    UnquoteSplicing,
     Number(f64),
-    Symbol(String),
+    Symbol(symbols::Symbol),
     String(String),
 }
 
-pub fn lex(input: String) -> Result<Vec<Token>, Box<dyn std::error::Error>> {
+fn is_digit_char(ch: char) -> bool {
+    ch.is_ascii_digit()
+}
+
+fn is_symbol_char(ch: char) -> bool {
+    ch.is_ascii_alphanumeric()
+        || ch == '_'
+        || ch == '+'
+        || ch == '-'
+        || ch == '*'
+        || ch == '/'
+        || ch == '>'
+        || ch == '<'
+        || ch == '='
+        || ch == '&'
+        || ch == ':'
+        || ch == '?'
+        || ch == '!'
+        || ch == '.'
+}
+
+pub fn lex(input: String) -> Result<Vec<Token>, Box<dyn std::error::Error + Send + Sync>> {
+    Ok(lex_with_lines(input)?.0)
+}
+
+/// Like [`lex`], but also returns line number (starting from 1) of every token
+pub fn lex_with_lines(
+    input: String,
+) -> Result<(Vec<Token>, Vec<usize>), Box<dyn std::error::Error + Send + Sync>> {
+    let input: Vec<char> = input.chars().collect();
     let mut curr_pos = 0;
 
     let mut tokens: Vec<Token> = Vec::new();
+    let mut lines: Vec<usize> = Vec::new();
+    let mut line: usize = 1;
+    let mut line_counted_pos = 0;
 
     while curr_pos < input.len() {
-        if input.chars().nth(curr_pos).unwrap() == '(' {
+        // Token added in the previous iteration starts on the line counted so far
+        lines.resize(tokens.len(), line);
+        line += input[line_counted_pos..curr_pos]
+            .iter()
+            .filter(|ch| **ch == '\n')
+            .count();
+        line_counted_pos = curr_pos;
+
+        if input[curr_pos] == '(' {
             tokens.push(Token::LeftParen);
             curr_pos += 1;
-        } else if input.chars().nth(curr_pos).unwrap() == ')' {
+        } else if input[curr_pos] == ')' {
             tokens.push(Token::RightParen);
             curr_pos += 1;
-        } else if {
-            let ch = input.chars().nth(curr_pos).unwrap();
-            ch >= '0' && ch <= '9'
-        } {
+        } else if input[curr_pos] == '{' {
+            tokens.push(Token::LeftBrace);
+            curr_pos += 1;
+        } else if input[curr_pos] == '}' {
+            tokens.push(Token::RightBrace);
+            curr_pos += 1;
+        } else if input[curr_pos] == '[' {
+            tokens.push(Token::LeftBracket);
+            curr_pos += 1;
+        } else if input[curr_pos] == ']' {
+            tokens.push(Token::RightBracket);
+            curr_pos += 1;
+        } else if input[curr_pos] == '\'' {
+            tokens.push(Token::Quote);
+            curr_pos += 1;
+        } else if input[curr_pos] == '`' {
+            tokens.push(Token::Quasiquote);
+            curr_pos += 1;
+        } else if input[curr_pos] == ',' {
+            if input.get(curr_pos + 1) == Some(&'@') {
+                tokens.push(Token::UnquoteSplicing);
+                curr_pos += 2;
+            } else {
+                tokens.push(Token::Unquote);
+                curr_pos += 1;
+            }
+        } else if is_digit_char(input[curr_pos]) {
             let mut buf: String = String::new();
-            let mut ch: char;
-            while {
-                ch = input.chars().nth(curr_pos).unwrap();
-                ch >= '0' && ch <= '9'
-            } {
-                buf.push(ch);
+            while curr_pos < input.len() && is_digit_char(input[curr_pos]) {
+                buf.push(input[curr_pos]);
                 curr_pos += 1;
             }
 
             tokens.push(Token::Number(buf.parse::<f64>()?));
-        } else if {
-            let ch = input.chars().nth(curr_pos).unwrap();
-            (ch >= 'a' && ch <= 'z')
-                || (ch >= 'A' && ch <= 'Z')
-                || (ch >= '0' && ch <= '9')
-                || ch == '_'
-                || ch == '+'
-                || ch == '-'
-                || ch == '*'
-                || ch == '/'
-                || ch == '>'
-                || ch == '<'
-                || ch == '='
-        } {
+        } else if is_symbol_char(input[curr_pos]) {
             let mut buf: String = String::new();
-            let mut ch: char;
-            while {
-                ch = input.chars().nth(curr_pos).unwrap();
-                (ch >= 'a' && ch <= 'z')
-                    || (ch >= 'A' && ch <= 'Z')
-                    || (ch >= '0' && ch <= '9')
-                    || ch == '_'
-                    || ch == '+'
-                    || ch == '-'
-                    || ch == '*'
-                    || ch == '/'
-                    || ch == '>'
-                    || ch == '<'
-                    || ch == '='
-            } {
-                buf.push(ch);
+            while curr_pos < input.len() && is_symbol_char(input[curr_pos]) {
+                buf.push(input[curr_pos]);
                 curr_pos += 1;
             }
 
-            tokens.push(Token::Symbol(buf));
-        } else if input.chars().nth(curr_pos).unwrap() == '"' {
+            tokens.push(Token::Symbol(
+                symbols::Symbol::intern(&buf).at_line(Some(line)),
+            ));
+        } else if input[curr_pos] == '"' {
             let mut buf: String = String::new();
             curr_pos += 1;
             'chars_loop: loop {
-                match input.chars().nth(curr_pos).unwrap() {
-                    '\"' => {
+                match input.get(curr_pos) {
+                    Some('\"') => {
                         break 'chars_loop;
                     }
-                    '\\' => {
-                        buf.push(match input.chars().nth(curr_pos + 1).unwrap() {
-                            'n' => '\n',
-                            'r' => '\r',
-                            't' => '\t',
-                            '"' => '\"',
-                            ch => {
+                    Some('\\') => {
+                        buf.push(match input.get(curr_pos + 1) {
+                            Some('n') => '\n',
+                            Some('r') => '\r',
+                            Some('t') => '\t',
+                            Some('"') => '\"',
+                            Some(ch) => {
                                 return Err(Box::new(std::io::Error::new(
                                     std::io::ErrorKind::InvalidData,
                                     format!("Invalid escape character '{}'", ch),
                                 )));
                             }
+                            None => {
+                                return Err(Box::new(std::io::Error::new(
+                                    std::io::ErrorKind::InvalidData,
+                                    "String not closed by double quote",
+                                )));
+                            }
                         });
                         curr_pos += 1;
                     }
-                    ch => {
-                        buf.push(ch);
+                    Some(ch) => {
+                        buf.push(*ch);
+                    }
+                    None => {
+                        return Err(Box::new(std::io::Error::new(
+                            std::io::ErrorKind::InvalidData,
+                            "String not closed by double quote",
+                        )));
                     }
                 }
                 curr_pos += 1;
@@ -105,5 +161,6 @@ pub fn lex(input: String) -> Result<Vec<Token>, Box<dyn std::error::Error>> {
         }
     }
 
-    Ok(tokens)
+    lines.resize(tokens.len(), line);
+    Ok((tokens, lines))
 }
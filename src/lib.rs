@@ -0,0 +1,26 @@
+pub mod builtins;
+pub(crate) mod compiler;
+pub mod concurrency;
+pub mod conditions;
+pub mod continuations;
+pub(crate) mod environment;
+pub mod evaluator;
+pub mod gc;
+pub mod generators;
+pub mod interpreter;
+pub mod lexer;
+pub mod limits;
+pub mod parser;
+pub mod symbols;
+pub mod syntax_rules;
+pub mod traceback;
+pub mod vm;
+
+pub use conditions::Raised;
+pub use evaluator::EvalContext;
+pub use gc::HeapStats;
+pub use interpreter::{Engine, Interpreter, Module};
+pub use limits::{CancelHandle, LimitError};
+pub use parser::{Atom, SExpr};
+pub use symbols::Symbol;
+pub use traceback::{Frame, Traceback};
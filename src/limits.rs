@@ -0,0 +1,143 @@
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Errors raised when a script exceeds limits configured by the embedder.
+/// They are returned boxed like all other evaluation errors and can be recognised with
+/// `err.downcast_ref::<LimitError>()`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LimitError {
+    RecursionDepthExceeded {
+        limit: usize,
+        call_chain: Vec<String>,
+    },
+    /// Native stack of the thread evaluating the script ran low, e.g. because of recursion
+    /// through builtins such as `map`, which isn't bounded by the recursion depth limit
+    StackExhausted { call_chain: Vec<String> },
+    /// Evaluation step budget set with `set_fuel` ran out
+    FuelExhausted,
+    /// Deadline set with `set_deadline` passed
+    Timeout,
+    /// Evaluation was cancelled through `CancelHandle`
+    Cancelled,
+    /// Estimated memory usage in bytes exceeded limit set with `set_memory_limit`
+    MemoryLimitExceeded { limit: usize, used: usize },
+    /// Script started more generator bodies at once than allowed by `set_max_generators`
+    GeneratorLimitExceeded { limit: usize },
+    /// Script started more threads running at once than allowed by `set_max_threads`
+    ThreadLimitExceeded { limit: usize },
+}
+
+// Number of innermost calls shown in the message of `RecursionDepthExceeded`
+const SHOWN_CALL_CHAIN_LEN: usize = 10;
+
+impl std::fmt::Display for LimitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LimitError::RecursionDepthExceeded { limit, call_chain } => {
+                write!(
+                    f,
+                    "Maximum recursion depth exceeded (limit {}). Call chain: ",
+                    limit
+                )?;
+                write_call_chain(f, call_chain)
+            }
+            LimitError::StackExhausted { call_chain } => {
+                write!(f, "Stack space exhausted. Call chain: ")?;
+                write_call_chain(f, call_chain)
+            }
+            LimitError::FuelExhausted => write!(f, "Evaluation step budget exhausted."),
+            LimitError::Timeout => write!(f, "Evaluation timed out."),
+            LimitError::Cancelled => write!(f, "Evaluation cancelled."),
+            LimitError::MemoryLimitExceeded { limit, used } => write!(
+                f,
+                "Memory limit exceeded: {} bytes used, limit is {} bytes.",
+                used, limit
+            ),
+            LimitError::GeneratorLimitExceeded { limit } => {
+                write!(f, "Too many running generators (limit {}).", limit)
+            }
+            LimitError::ThreadLimitExceeded { limit } => {
+                write!(f, "Too many running threads (limit {}).", limit)
+            }
+        }
+    }
+}
+
+fn write_call_chain(f: &mut std::fmt::Formatter<'_>, call_chain: &[String]) -> std::fmt::Result {
+    if call_chain.len() > SHOWN_CALL_CHAIN_LEN {
+        write!(
+            f,
+            "... ({} more) -> ",
+            call_chain.len() - SHOWN_CALL_CHAIN_LEN
+        )?;
+    }
+    let shown = &call_chain[call_chain.len().saturating_sub(SHOWN_CALL_CHAIN_LEN)..];
+    write!(f, "{}", shown.join(" -> "))
+}
+
+impl std::error::Error for LimitError {}
+
+/// Stack size of the thread started by every [`crate::Interpreter`] evaluation. Threads
+/// started by `spawn` and generator bodies have smaller stacks of their own.
+pub(crate) const EVAL_STACK_SIZE: usize = 64 * 1024 * 1024;
+
+// Part of the stack left for frames entered between two checks and for builtins which
+// recurse on their own, such as `equal?` or printing
+const STACK_RESERVE: usize = 2 * 1024 * 1024;
+
+thread_local! {
+    // Address near the start of stack of thread evaluating scripts, and how much of the stack
+    // evaluation may use. Not set on threads which weren't started for evaluation.
+    static STACK_LIMIT: Cell<Option<(usize, usize)>> = const { Cell::new(None) };
+}
+
+// Approximate address of the top of the current thread's stack
+#[inline(never)]
+fn stack_position() -> usize {
+    let marker: u8 = 0;
+    std::hint::black_box(&marker) as *const u8 as usize
+}
+
+/// Marks the current thread, which was started with `stack_size` bytes of stack, as thread
+/// evaluating scripts; it's checked for running out of stack from now on
+pub(crate) fn set_stack_limit(stack_size: usize) {
+    let usable = stack_size.saturating_sub(STACK_RESERVE);
+    STACK_LIMIT.with(|limit| limit.set(Some((stack_position(), usable))));
+}
+
+/// Whether evaluation on the current thread is about to run out of stack
+pub(crate) fn stack_exhausted() -> bool {
+    STACK_LIMIT.with(|limit| match limit.get() {
+        Some((start, usable)) => start.abs_diff(stack_position()) > usable,
+        None => false,
+    })
+}
+
+/// Handle which can cancel evaluation of an interpreter from another thread.
+/// Cancellation is cooperative: it is noticed at the next evaluation step.
+#[derive(Clone, Debug, Default)]
+pub struct CancelHandle {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelHandle {
+    pub fn new() -> CancelHandle {
+        CancelHandle {
+            cancelled: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Allows evaluation again after it was cancelled
+    pub fn reset(&self) {
+        self.cancelled.store(false, Ordering::Relaxed);
+    }
+}
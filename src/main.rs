@@ -1,8 +1,21 @@
-mod evaluator;
-mod lexer;
-mod parser;
+use tk_lisp_test_1::Interpreter;
 
 fn main() {
+    let mut interpreter = Interpreter::new();
+
+    if let Some(path) = std::env::args().nth(1) {
+        match interpreter.eval_file(&path) {
+            Ok(result) => println!("Result:\n{:#?}", result),
+            Err(e) => {
+                eprintln!("Error:\n{}", e);
+                if let Some(traceback) = interpreter.traceback() {
+                    eprint!("{}", traceback);
+                }
+            }
+        }
+        return;
+    }
+
     // let code1: String = String::from("(print (+ 2 5))");
     // let code2: String = String::from("(print (+ (+ 8 2) 5))");
     // let code3: String = String::from(
@@ -113,24 +126,10 @@ fn main() {
         "#,
     );
 
-    match lexer::lex(code7) {
-        Ok(tokens) => {
-            println!("Tokens:\n{:#?}", tokens);
-
-            match parser::parse(&tokens) {
-                Ok(sexpr) => {
-                    println!("Parsed code:\n{:#?}", sexpr);
-
-                    match evaluator::eval(&sexpr, &mut evaluator::EvalContext::new()) {
-                        Ok(result) => {
-                            println!("Result:\n{:#?}", result);
-                        }
-                        Err(e) => eprintln!("Evaluation error:\n{}", e),
-                    }
-                }
-                Err(e) => eprintln!("Parsing error:\n{}", e),
-            }
+    match interpreter.eval_str(&code7) {
+        Ok(result) => {
+            println!("Result:\n{:#?}", result);
         }
-        Err(e) => eprintln!("Lexing error:\n{}", e),
+        Err(e) => eprintln!("Error:\n{}", e),
     }
 }
@@ -1,28 +1,75 @@
+use crate::concurrency;
+use crate::continuations;
+use crate::generators;
 use crate::lexer;
+use crate::symbols;
 
-use std::rc::Rc;
-use std::sync::Mutex;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 
 #[derive(Clone, Debug)]
 pub enum Atom {
     Number(f64),
-    Symbol(String),
+    Symbol(symbols::Symbol),
+}
+
+/// Key of hash table. Strings are stored as their bytes, numbers as bits of their value.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum HashKey {
+    Number(u64),
+    String(Vec<u8>),
+    Symbol(symbols::Symbol),
+}
+
+/// Record type declared by `defstruct`
+#[derive(Debug)]
+pub struct RecordType {
+    pub name: String,
+    pub fields: Vec<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Record {
+    pub record_type: Arc<RecordType>,
+    pub fields: Vec<SExpr>,
 }
 
 #[derive(Clone, Debug)]
 pub enum SExpr {
     Atom(Atom),
     List(Vec<SExpr>),
-    Ref(Rc<Mutex<SExpr>>),
+    Ref(Arc<Mutex<SExpr>>),
+    Hash(Arc<Mutex<HashMap<HashKey, SExpr>>>),
+    Vector(Arc<Mutex<Vec<SExpr>>>),
+    RecordType(Arc<RecordType>),
+    Record(Arc<Mutex<Record>>),
+    Generator(Arc<Mutex<generators::Generator>>),
+    Thread(Arc<concurrency::Thread>),
+    Channel(Arc<concurrency::Channel>),
+    Lock(Arc<concurrency::Lock>),
+    Atomic(Arc<concurrency::Atomic>),
+    Continuation(Arc<continuations::Continuation>),
+}
+
+fn reader_macro_form(token: &lexer::Token) -> Option<&'static str> {
+    match token {
+        lexer::Token::Quote => Some("quote"),
+        lexer::Token::Quasiquote => Some("quasiquote"),
+        lexer::Token::Unquote => Some("unquote"),
+        lexer::Token::UnquoteSplicing => Some("unquote-splicing"),
+        _ => None,
+    }
 }
 
 fn parse_expr(
-    input: &Vec<lexer::Token>,
+    input: &[lexer::Token],
     curr_pos: &mut usize,
-) -> Result<Option<SExpr>, Box<dyn std::error::Error>> {
+) -> Result<Option<SExpr>, Box<dyn std::error::Error + Send + Sync>> {
     // println!("curr_pos={}", *curr_pos);
     let org_pos = *curr_pos;
-    if let lexer::Token::LeftParen = input[*curr_pos] {
+    if *curr_pos >= input.len() {
+        Ok(None)
+    } else if let lexer::Token::LeftParen = input[*curr_pos] {
         *curr_pos += 1;
         let mut list: Vec<SExpr> = Vec::new();
         loop {
@@ -38,7 +85,7 @@ fn parse_expr(
                 }
             }
         }
-        if let lexer::Token::RightParen = input[*curr_pos] {
+        if let Some(lexer::Token::RightParen) = input.get(*curr_pos) {
             *curr_pos += 1;
             Ok(Some(SExpr::List(list)))
         } else {
@@ -48,6 +95,67 @@ fn parse_expr(
                 "List not closed by right parenthesis",
             )))
         }
+    } else if let lexer::Token::LeftBrace = input[*curr_pos] {
+        // Hash table literal {k v ...} is read as (make-hash k v ...)
+        *curr_pos += 1;
+        let mut list: Vec<SExpr> = vec![SExpr::Atom(Atom::Symbol(symbols::Symbol::intern(
+            "make-hash",
+        )))];
+        while let Some(sexpr) = parse_expr(input, curr_pos)? {
+            list.push(sexpr);
+        }
+        if let Some(lexer::Token::RightBrace) = input.get(*curr_pos) {
+            *curr_pos += 1;
+            if list.len() % 2 == 1 {
+                Ok(Some(SExpr::List(list)))
+            } else {
+                *curr_pos = org_pos;
+                Err(Box::new(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "Hash table literal must have even number of elements",
+                )))
+            }
+        } else {
+            *curr_pos = org_pos;
+            Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Hash table literal not closed by right brace",
+            )))
+        }
+    } else if let lexer::Token::LeftBracket = input[*curr_pos] {
+        // Vector literal [a b ...] is read as (vector a b ...)
+        *curr_pos += 1;
+        let mut list: Vec<SExpr> =
+            vec![SExpr::Atom(Atom::Symbol(symbols::Symbol::intern("vector")))];
+        while let Some(sexpr) = parse_expr(input, curr_pos)? {
+            list.push(sexpr);
+        }
+        if let Some(lexer::Token::RightBracket) = input.get(*curr_pos) {
+            *curr_pos += 1;
+            Ok(Some(SExpr::List(list)))
+        } else {
+            *curr_pos = org_pos;
+            Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Vector literal not closed by right bracket",
+            )))
+        }
+    } else if let Some(form) = reader_macro_form(&input[*curr_pos]) {
+        // 'x, `x, ,x and ,@x are read as (quote x), (quasiquote x), (unquote x) and
+        // (unquote-splicing x)
+        *curr_pos += 1;
+        if let Some(sexpr) = parse_expr(input, curr_pos)? {
+            Ok(Some(SExpr::List(vec![
+                SExpr::Atom(Atom::Symbol(symbols::Symbol::intern(form))),
+                sexpr,
+            ])))
+        } else {
+            *curr_pos = org_pos;
+            Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Expected expression after `{}` shorthand", form),
+            )))
+        }
     } else if let lexer::Token::Number(num) = input[*curr_pos] {
         *curr_pos += 1;
         Ok(Some(SExpr::Atom(Atom::Number(num))))
@@ -55,7 +163,7 @@ fn parse_expr(
         *curr_pos += 1;
         Ok(Some(SExpr::Atom(Atom::Symbol(sym.clone()))))
     } else if let lexer::Token::String(s) = &input[*curr_pos] {
-        let mut buf: Vec<SExpr> = vec![SExpr::Atom(Atom::Symbol(String::from("list")))];
+        let mut buf: Vec<SExpr> = vec![SExpr::Atom(Atom::Symbol(symbols::Symbol::intern("list")))];
         for b in s.bytes() {
             buf.push(SExpr::Atom(Atom::Number(b as f64)));
         }
@@ -66,7 +174,7 @@ fn parse_expr(
     }
 }
 
-pub fn parse(input: &Vec<lexer::Token>) -> Result<SExpr, Box<dyn std::error::Error>> {
+pub fn parse(input: &[lexer::Token]) -> Result<SExpr, Box<dyn std::error::Error + Send + Sync>> {
     match parse_expr(input, &mut 0) {
         Ok(Some(sexpr)) => Ok(sexpr),
         Ok(None) => Err(Box::new(std::io::Error::new(
@@ -76,3 +184,35 @@ pub fn parse(input: &Vec<lexer::Token>) -> Result<SExpr, Box<dyn std::error::Err
         Err(e) => Err(e),
     }
 }
+
+pub fn parse_all(
+    input: &[lexer::Token],
+) -> Result<Vec<SExpr>, Box<dyn std::error::Error + Send + Sync>> {
+    Ok(parse_all_with_positions(input)?
+        .into_iter()
+        .map(|(sexpr, _)| sexpr)
+        .collect())
+}
+
+/// Like [`parse_all`], but also returns index of the first token of every expression
+pub fn parse_all_with_positions(
+    input: &[lexer::Token],
+) -> Result<Vec<(SExpr, usize)>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut curr_pos: usize = 0;
+    let mut exprs: Vec<(SExpr, usize)> = Vec::new();
+    loop {
+        let start_pos = curr_pos;
+        match parse_expr(input, &mut curr_pos)? {
+            Some(sexpr) => exprs.push((sexpr, start_pos)),
+            None => break,
+        }
+    }
+    if curr_pos < input.len() {
+        Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "Unexpected right parenthesis",
+        )))
+    } else {
+        Ok(exprs)
+    }
+}
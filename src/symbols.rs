@@ -0,0 +1,207 @@
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, LazyLock, Mutex};
+
+// Interned names of all interpreters. Names used by no symbol any more are dropped when the
+// table has doubled in size since they were last dropped.
+static INTERNED: LazyLock<Mutex<InternTable>> =
+    LazyLock::new(|| Mutex::new(InternTable::default()));
+
+// Size of table below which unused names are not dropped
+const MIN_SWEEP_LEN: usize = 1024;
+
+#[derive(Default)]
+struct InternTable {
+    names: HashSet<Arc<str>>,
+    // Number of names left by the last sweep
+    swept_len: usize,
+}
+
+impl InternTable {
+    // Drops names whose only reference is the one held by the table
+    fn sweep(&mut self) {
+        self.names.retain(|name| Arc::strong_count(name) > 1);
+        self.swept_len = self.names.len();
+    }
+}
+
+/// Handle of symbol name. Symbols read from source code or created by `string->symbol` are
+/// interned, so the same name always gives the same symbol. Symbols created by `gensym` are
+/// uninterned: they are different from every other symbol, even one with the same name.
+///
+/// Names are reference counted and freed when no symbol uses them. Cloning, comparing and
+/// hashing symbol uses only the address of its name, not the name itself.
+///
+/// Symbols read from source code also remember the line they were read at, which tracebacks
+/// show for call forms and lambdas. The line doesn't take part in comparison or hashing.
+#[derive(Clone)]
+pub struct Symbol {
+    name: Arc<str>,
+    line: Option<u32>,
+}
+
+impl Symbol {
+    /// The symbol named `name`
+    pub fn intern(name: &str) -> Symbol {
+        let mut interned = INTERNED.lock().unwrap();
+        if let Some(name) = interned.names.get(name) {
+            return Symbol::from_name(name.clone());
+        }
+        if interned.names.len() >= (2 * interned.swept_len).max(MIN_SWEEP_LEN) {
+            interned.sweep();
+        }
+        let name: Arc<str> = Arc::from(name);
+        interned.names.insert(name.clone());
+        Symbol::from_name(name)
+    }
+
+    /// New symbol named `name`, not equal to any existing one
+    pub fn uninterned(name: String) -> Symbol {
+        Symbol::from_name(Arc::from(name))
+    }
+
+    fn from_name(name: Arc<str>) -> Symbol {
+        Symbol { name, line: None }
+    }
+
+    /// The same symbol, read at line `line` of source code
+    pub(crate) fn at_line(self, line: Option<usize>) -> Symbol {
+        Symbol {
+            line: line.map(|line| u32::try_from(line).unwrap_or(u32::MAX)),
+            ..self
+        }
+    }
+
+    /// Line of source code the symbol was read at, if it was read from source code
+    pub fn line(&self) -> Option<usize> {
+        self.line.map(|line| line as usize)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.name
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Symbol) -> bool {
+        Arc::ptr_eq(&self.name, &other.name)
+    }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Arc::as_ptr(&self.name).cast::<u8>().hash(state);
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        &*self.name == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        &*self.name == *other
+    }
+}
+
+impl std::ops::Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.name
+    }
+}
+
+impl std::fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", &*self.name)
+    }
+}
+
+impl std::fmt::Display for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", &*self.name)
+    }
+}
+
+/// Special form of the language, recognised by comparing symbol with names interned once,
+/// without comparing strings.
+///
+/// [`SpecialForm::Builtin`] tags builtin function values `(builtin name)`. Its symbol is
+/// uninterned, so that only values created by the interpreter are builtins, and a list read
+/// from source code, such as `'(builtin +)`, is not.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum SpecialForm {
+    Let,
+    Set,
+    If,
+    Cond,
+    While,
+    Lambda,
+    LambdaCaptured,
+    Builtin,
+    Call,
+    Defstruct,
+    Defmacro,
+    DefineSyntax,
+    Try,
+    UnwindProtect,
+    Quote,
+    Quasiquote,
+    Unquote,
+    UnquoteSplicing,
+}
+
+// Symbols of special forms, in the order of declaration of `SpecialForm`
+static SPECIAL_FORMS: LazyLock<Vec<(Symbol, SpecialForm)>> = LazyLock::new(|| {
+    [
+        ("let", SpecialForm::Let),
+        ("set", SpecialForm::Set),
+        ("if", SpecialForm::If),
+        ("cond", SpecialForm::Cond),
+        ("while", SpecialForm::While),
+        ("lambda", SpecialForm::Lambda),
+        ("lambda-captured", SpecialForm::LambdaCaptured),
+        ("builtin", SpecialForm::Builtin),
+        ("call", SpecialForm::Call),
+        ("defstruct", SpecialForm::Defstruct),
+        ("defmacro", SpecialForm::Defmacro),
+        ("define-syntax", SpecialForm::DefineSyntax),
+        ("try", SpecialForm::Try),
+        ("unwind-protect", SpecialForm::UnwindProtect),
+        ("quote", SpecialForm::Quote),
+        ("quasiquote", SpecialForm::Quasiquote),
+        ("unquote", SpecialForm::Unquote),
+        ("unquote-splicing", SpecialForm::UnquoteSplicing),
+    ]
+    .into_iter()
+    .map(|(name, form)| match form {
+        SpecialForm::Builtin => (Symbol::uninterned(String::from(name)), form),
+        _ => (Symbol::intern(name), form),
+    })
+    .collect()
+});
+
+impl SpecialForm {
+    /// Special form named `sym`, if any
+    pub(crate) fn of(sym: &Symbol) -> Option<SpecialForm> {
+        SPECIAL_FORMS
+            .iter()
+            .find(|(name, _)| name == sym)
+            .map(|(_, form)| *form)
+    }
+
+    /// Interned symbol naming the form
+    pub(crate) fn symbol(self) -> Symbol {
+        SPECIAL_FORMS[self as usize].0.clone()
+    }
+
+    /// Whether `sym` is the symbol naming the form
+    pub(crate) fn is(self, sym: &Symbol) -> bool {
+        SPECIAL_FORMS[self as usize].0 == *sym
+    }
+}
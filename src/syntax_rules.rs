@@ -0,0 +1,342 @@
+use crate::evaluator;
+use crate::parser;
+use crate::symbols;
+
+use std::collections::HashMap;
+
+const ELLIPSIS: &str = "...";
+
+/// Pattern macro declared by `(define-syntax name (syntax-rules (literal*) (pattern template)+))`
+#[derive(Debug)]
+pub struct SyntaxRules {
+    pub name: String,
+    literals: Vec<symbols::Symbol>,
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug)]
+struct Rule {
+    pattern: parser::SExpr,
+    template: parser::SExpr,
+    // Variables bound by `let` or `lambda` in the template itself (not substituted from the
+    // macro call); they are renamed in every expansion, so they can't capture user's variables
+    introduced_bindings: Vec<symbols::Symbol>,
+}
+
+// Value matched by pattern variable; variables under ellipsis match sequence of values
+#[derive(Clone)]
+enum Binding {
+    One(parser::SExpr),
+    Many(Vec<Binding>),
+}
+
+fn syntax_error(message: String) -> Box<dyn std::error::Error + Send + Sync> {
+    Box::new(std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        message,
+    ))
+}
+
+fn as_symbol(sexpr: &parser::SExpr) -> Option<symbols::Symbol> {
+    match sexpr {
+        parser::SExpr::Atom(parser::Atom::Symbol(sym)) => Some(sym.clone()),
+        _ => None,
+    }
+}
+
+fn is_ellipsis(sexpr: &parser::SExpr) -> bool {
+    as_symbol(sexpr).is_some_and(|sym| sym == ELLIPSIS)
+}
+
+impl SyntaxRules {
+    /// Parses `(syntax-rules (literal*) (pattern template)+)` of macro `name`
+    pub fn parse(
+        name: &str,
+        spec: &parser::SExpr,
+    ) -> Result<SyntaxRules, Box<dyn std::error::Error + Send + Sync>> {
+        let bad_spec = || {
+            syntax_error(format!(
+                "Macro `{}` must be defined as (syntax-rules (literal*) (pattern template)+).",
+                name
+            ))
+        };
+        let spec = match spec {
+            parser::SExpr::List(spec)
+                if spec.len() >= 3
+                    && as_symbol(&spec[0]).is_some_and(|sym| sym == "syntax-rules") =>
+            {
+                spec
+            }
+            _ => return Err(bad_spec()),
+        };
+        let literals: Vec<symbols::Symbol> = match &spec[1] {
+            parser::SExpr::List(literals) => literals
+                .iter()
+                .map(|literal| as_symbol(literal).ok_or_else(bad_spec))
+                .collect::<Result<_, _>>()?,
+            _ => return Err(bad_spec()),
+        };
+
+        let mut rules: Vec<Rule> = Vec::new();
+        for rule in &spec[2..] {
+            match rule {
+                parser::SExpr::List(rule) if rule.len() == 2 => {
+                    if !matches!(&rule[0], parser::SExpr::List(pattern) if !pattern.is_empty()) {
+                        return Err(syntax_error(format!(
+                            "Pattern of macro `{}` must be a non-empty list.",
+                            name
+                        )));
+                    }
+                    let mut pattern_vars: Vec<symbols::Symbol> = Vec::new();
+                    collect_pattern_vars(&rule[0], &literals, &mut pattern_vars);
+                    let mut introduced_bindings: Vec<symbols::Symbol> = Vec::new();
+                    collect_template_bindings(&rule[1], &mut introduced_bindings);
+                    introduced_bindings.retain(|var| !pattern_vars.contains(var));
+                    rules.push(Rule {
+                        pattern: rule[0].clone(),
+                        template: rule[1].clone(),
+                        introduced_bindings,
+                    });
+                }
+                _ => return Err(bad_spec()),
+            }
+        }
+        Ok(SyntaxRules {
+            name: String::from(name),
+            literals,
+            rules,
+        })
+    }
+
+    /// Expands macro call `form` using the first rule whose pattern matches it
+    pub fn expand(
+        &self,
+        form: &parser::SExpr,
+        ctx: &mut evaluator::EvalContext,
+    ) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+        let form = match form {
+            parser::SExpr::List(form) => form,
+            _ => return Err(self.no_match()),
+        };
+        for rule in &self.rules {
+            let pattern = match &rule.pattern {
+                parser::SExpr::List(pattern) => pattern,
+                _ => unreachable!(),
+            };
+            // The first element of pattern stands for the macro keyword and is not matched
+            let mut bindings: HashMap<symbols::Symbol, Binding> = HashMap::new();
+            if self.match_list(&pattern[1..], &form[1..], &mut bindings) {
+                for var in &rule.introduced_bindings {
+                    let renamed = ctx.gensym(var)?;
+                    bindings.insert(
+                        var.clone(),
+                        Binding::One(parser::SExpr::Atom(parser::Atom::Symbol(renamed))),
+                    );
+                }
+                return expand_template(&rule.template, &bindings);
+            }
+        }
+        Err(self.no_match())
+    }
+
+    fn no_match(&self) -> Box<dyn std::error::Error + Send + Sync> {
+        syntax_error(format!(
+            "No pattern of macro `{}` matches its use.",
+            self.name
+        ))
+    }
+
+    fn match_pattern(
+        &self,
+        pattern: &parser::SExpr,
+        form: &parser::SExpr,
+        bindings: &mut HashMap<symbols::Symbol, Binding>,
+    ) -> bool {
+        match pattern {
+            parser::SExpr::Atom(parser::Atom::Symbol(sym)) if sym == "_" => true,
+            parser::SExpr::Atom(parser::Atom::Symbol(sym)) if self.literals.contains(sym) => {
+                as_symbol(form) == Some(sym.clone())
+            }
+            parser::SExpr::Atom(parser::Atom::Symbol(sym)) => {
+                bindings.insert(sym.clone(), Binding::One(form.clone()));
+                true
+            }
+            parser::SExpr::Atom(parser::Atom::Number(num)) => {
+                matches!(form, parser::SExpr::Atom(parser::Atom::Number(form_num)) if form_num == num)
+            }
+            parser::SExpr::List(patterns) => match form {
+                parser::SExpr::List(forms) => self.match_list(patterns, forms, bindings),
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    // Matches list of patterns, of which at most one may be followed by ellipsis
+    fn match_list(
+        &self,
+        patterns: &[parser::SExpr],
+        forms: &[parser::SExpr],
+        bindings: &mut HashMap<symbols::Symbol, Binding>,
+    ) -> bool {
+        let ellipsis_pos = match patterns.iter().position(is_ellipsis) {
+            Some(ellipsis_pos) if ellipsis_pos > 0 => ellipsis_pos,
+            Some(_) => return false,
+            None => {
+                return patterns.len() == forms.len()
+                    && patterns
+                        .iter()
+                        .zip(forms)
+                        .all(|(pattern, form)| self.match_pattern(pattern, form, bindings))
+            }
+        };
+        let before = &patterns[..ellipsis_pos - 1];
+        let repeated = &patterns[ellipsis_pos - 1];
+        let after = &patterns[ellipsis_pos + 1..];
+        if forms.len() < before.len() + after.len() {
+            return false;
+        }
+        let repeated_forms = &forms[before.len()..forms.len() - after.len()];
+
+        let mut matches: Vec<HashMap<symbols::Symbol, Binding>> = Vec::new();
+        for form in repeated_forms {
+            let mut repeated_bindings: HashMap<symbols::Symbol, Binding> = HashMap::new();
+            if !self.match_pattern(repeated, form, &mut repeated_bindings) {
+                return false;
+            }
+            matches.push(repeated_bindings);
+        }
+        let mut repeated_vars: Vec<symbols::Symbol> = Vec::new();
+        collect_pattern_vars(repeated, &self.literals, &mut repeated_vars);
+        for var in repeated_vars {
+            let values: Vec<Binding> = matches.iter().map(|m| m[&var].clone()).collect();
+            bindings.insert(var, Binding::Many(values));
+        }
+
+        self.match_list(before, &forms[..before.len()], bindings)
+            && self.match_list(after, &forms[forms.len() - after.len()..], bindings)
+    }
+}
+
+fn collect_pattern_vars(
+    pattern: &parser::SExpr,
+    literals: &[symbols::Symbol],
+    vars: &mut Vec<symbols::Symbol>,
+) {
+    match pattern {
+        parser::SExpr::Atom(parser::Atom::Symbol(sym))
+            if sym != "_" && sym != ELLIPSIS && !literals.contains(sym) =>
+        {
+            vars.push(sym.clone());
+        }
+        parser::SExpr::List(patterns) => {
+            for pattern in patterns {
+                collect_pattern_vars(pattern, literals, vars);
+            }
+        }
+        _ => {}
+    }
+}
+
+// Collects variable names bound by `let` definitions and `lambda` parameter lists of template
+fn collect_template_bindings(template: &parser::SExpr, bindings: &mut Vec<symbols::Symbol>) {
+    let list = match template {
+        parser::SExpr::List(list) => list,
+        _ => return,
+    };
+    match list.first().and_then(as_symbol).as_deref() {
+        Some("let") if list.len() >= 3 => {
+            for var_def in &list[1..list.len() - 1] {
+                if let parser::SExpr::List(var_def) = var_def {
+                    if let Some(name) = var_def.first().and_then(as_symbol) {
+                        bindings.push(name);
+                    }
+                }
+            }
+        }
+        Some("lambda") if list.len() == 4 => {
+            if let parser::SExpr::List(params) = &list[2] {
+                for param in params {
+                    let name = match param {
+                        parser::SExpr::List(param_pair) => param_pair.first().and_then(as_symbol),
+                        _ => as_symbol(param),
+                    };
+                    match name {
+                        Some(name) if !name.starts_with('&') && name != ELLIPSIS => {
+                            bindings.push(name)
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+        _ => {}
+    }
+    for elem in list {
+        collect_template_bindings(elem, bindings);
+    }
+}
+
+fn expand_template(
+    template: &parser::SExpr,
+    bindings: &HashMap<symbols::Symbol, Binding>,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    let list = match template {
+        parser::SExpr::Atom(parser::Atom::Symbol(sym)) => {
+            return match bindings.get(sym) {
+                Some(Binding::One(value)) => Ok(value.clone()),
+                Some(Binding::Many(_)) => Err(syntax_error(format!(
+                    "Pattern variable `{}` must be followed by ellipsis in template.",
+                    sym
+                ))),
+                None => Ok(template.clone()),
+            }
+        }
+        parser::SExpr::List(list) => list,
+        _ => return Ok(template.clone()),
+    };
+
+    let mut result: Vec<parser::SExpr> = Vec::new();
+    let mut i: usize = 0;
+    while i < list.len() {
+        let elem = &list[i];
+        if !list.get(i + 1).is_some_and(is_ellipsis) {
+            result.push(expand_template(elem, bindings)?);
+            i += 1;
+            continue;
+        }
+
+        // `elem ...` is expanded once for every value of sequence variables it contains
+        let mut vars: Vec<symbols::Symbol> = Vec::new();
+        collect_pattern_vars(elem, &[], &mut vars);
+        let sequences: Vec<(&symbols::Symbol, &Vec<Binding>)> = vars
+            .iter()
+            .filter_map(|var| match bindings.get(var) {
+                Some(Binding::Many(values)) => Some((var, values)),
+                _ => None,
+            })
+            .collect();
+        let len = match sequences.first() {
+            Some((_, values)) => values.len(),
+            None => {
+                return Err(syntax_error(String::from(
+                    "Template followed by ellipsis must contain a pattern variable matched under ellipsis.",
+                )))
+            }
+        };
+        if sequences.iter().any(|(_, values)| values.len() != len) {
+            return Err(syntax_error(String::from(
+                "Pattern variables used under the same ellipsis matched different number of values.",
+            )));
+        }
+        for n in 0..len {
+            let mut elem_bindings = bindings.clone();
+            for (var, values) in &sequences {
+                elem_bindings.insert((*var).clone(), values[n].clone());
+            }
+            result.push(expand_template(elem, &elem_bindings)?);
+        }
+        i += 2;
+    }
+    Ok(parser::SExpr::List(result))
+}
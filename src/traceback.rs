@@ -0,0 +1,49 @@
+use crate::conditions;
+use crate::parser;
+
+/// Lambda call active when evaluation failed
+#[derive(Debug, Clone)]
+pub struct Frame {
+    /// Name of variable through which lambda was called, or `<lambda>`
+    pub name: String,
+    pub args: Vec<parser::SExpr>,
+    /// Line of the `call` form, or of the lambda's definition when it was called by a builtin
+    /// function such as `map`; `None` when neither was read from source code
+    pub line: Option<usize>,
+}
+
+/// Lisp-level call stack at the point where evaluation failed.
+/// Tail calls replace frame of their caller, so they don't appear in it.
+#[derive(Debug, Clone, Default)]
+pub struct Traceback {
+    /// Line of the top-level expression which failed, if known
+    pub line: Option<usize>,
+    /// Outermost call first
+    pub frames: Vec<Frame>,
+}
+
+impl std::fmt::Display for Traceback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Traceback (most recent call last):")?;
+        match self.line {
+            Some(line) => writeln!(f, "  in top-level expression at line {}", line)?,
+            None => writeln!(f, "  in top-level expression")?,
+        }
+        for frame in &self.frames {
+            let args: Vec<String> = frame
+                .args
+                .iter()
+                .map(|arg| match conditions::string_text(arg) {
+                    Some(text) => format!("{:?}", text),
+                    None => conditions::describe_value(arg),
+                })
+                .collect();
+            write!(f, "  in {} ({})", frame.name, args.join(", "))?;
+            match frame.line {
+                Some(line) => writeln!(f, " at line {}", line)?,
+                None => writeln!(f)?,
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,297 @@
+use crate::builtins;
+use crate::compiler::{self, Op};
+use crate::evaluator;
+use crate::parser;
+use crate::traceback;
+
+use std::sync::{Arc, Mutex};
+
+// Activation of compiled top-level expression or lambda body
+struct Frame {
+    chunk: Arc<compiler::Chunk>,
+    pc: usize,
+    ctx: evaluator::EvalContext,
+    // Height of the value stack when the frame was entered
+    stack_base: usize,
+    // Number of variables of `ctx` at the start of every enclosing `let`
+    scopes: Vec<usize>,
+    // Whether frame of lambda call was pushed to the call stack for this frame
+    is_call: bool,
+}
+
+// Lambda calls push frames here instead of recursing, so only the depth limit bounds them
+struct Vm {
+    frames: Vec<Frame>,
+    stack: Vec<parser::SExpr>,
+}
+
+/// Compiles `sexpr` to bytecode and runs it in context `ctx`. Gives the same results as
+/// [`evaluator::eval`].
+pub fn eval(
+    sexpr: &parser::SExpr,
+    ctx: &mut evaluator::EvalContext,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    ctx.check_limits()?;
+    let chunk = Arc::new(compiler::compile(sexpr, ctx.var_names(), ctx));
+    let vars = std::mem::take(&mut ctx.vars);
+    let mut frame_ctx = ctx.clone();
+    frame_ctx.vars = vars;
+    let mut vm = Vm::new(Frame {
+        chunk,
+        pc: 0,
+        ctx: frame_ctx,
+        stack_base: 0,
+        scopes: Vec::new(),
+        is_call: false,
+    });
+    let result = vm.run();
+    // Variables defined by `defstruct` outside of `let` stay in the context
+    let frame = vm.frames.pop().unwrap();
+    ctx.vars = frame.ctx.vars;
+    if let Some(&len) = frame.scopes.first() {
+        ctx.vars.truncate(len);
+    }
+    result
+}
+
+/// Runs body of lambda created by compiled code, when it is called by the evaluator or
+/// by a builtin function. `new_ctx` holds its captured variables and arguments.
+pub(crate) fn call_lambda(
+    body_cell: &Arc<Mutex<parser::SExpr>>,
+    new_ctx: evaluator::EvalContext,
+    call_frame: traceback::Frame,
+) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+    if let Err(err) = new_ctx
+        .check_limits()
+        .and_then(|_| new_ctx.push_call_frame(call_frame))
+    {
+        new_ctx.record_traceback(err.as_ref(), None);
+        return Err(err);
+    }
+    let chunk = lambda_chunk(body_cell, &new_ctx);
+    let mut vm = Vm::new(Frame {
+        chunk,
+        pc: 0,
+        ctx: new_ctx,
+        stack_base: 0,
+        scopes: Vec::new(),
+        is_call: true,
+    });
+    let result = vm.run();
+    vm.frames[0].ctx.pop_call_frame();
+    result
+}
+
+// Bytecode of lambda body, compiled for variables (captured ones and parameters) of `new_ctx`
+fn lambda_chunk(
+    body_cell: &Arc<Mutex<parser::SExpr>>,
+    new_ctx: &evaluator::EvalContext,
+) -> Arc<compiler::Chunk> {
+    new_ctx.lambda_chunk(body_cell, || {
+        compiler::compile(&body_cell.lock().unwrap(), new_ctx.var_names(), new_ctx)
+    })
+}
+
+// Replaces value of variable. The old value is dropped after the cell is unlocked, as
+// dropping it may run code (closing generator) which uses the same variable.
+fn store(cell: &Arc<Mutex<parser::SExpr>>, value: parser::SExpr) {
+    let replaced = std::mem::replace(&mut *cell.lock().unwrap(), value);
+    drop(replaced);
+}
+
+impl Vm {
+    fn new(frame: Frame) -> Vm {
+        Vm {
+            frames: vec![frame],
+            stack: Vec::new(),
+        }
+    }
+
+    // Runs until the outermost frame returns. On error, frames above it are unwound.
+    fn run(&mut self) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+        let result = self.run_frames();
+        if let Err(err) = &result {
+            self.frames[0].ctx.record_traceback(err.as_ref(), None);
+            while self.frames.len() > 1 {
+                let frame = self.frames.pop().unwrap();
+                if frame.is_call {
+                    frame.ctx.pop_call_frame();
+                }
+            }
+        }
+        result
+    }
+
+    fn pop(&mut self) -> parser::SExpr {
+        self.stack.pop().unwrap()
+    }
+
+    fn pop_args(&mut self, argc: usize) -> Vec<parser::SExpr> {
+        self.stack.split_off(self.stack.len() - argc)
+    }
+
+    fn run_frames(&mut self) -> Result<parser::SExpr, Box<dyn std::error::Error + Send + Sync>> {
+        let mut chunk = self.frames.last().unwrap().chunk.clone();
+        loop {
+            let frame = self.frames.last_mut().unwrap();
+            // Chunk is cloned only when control moves to another one, as cloning is atomic
+            if !Arc::ptr_eq(&chunk, &frame.chunk) {
+                chunk = frame.chunk.clone();
+            }
+            let op = &chunk.ops[frame.pc];
+            frame.pc += 1;
+            match op {
+                Op::Const(value) => self.stack.push(value.clone()),
+                Op::LoadSlot(slot) => self
+                    .stack
+                    .push(parser::SExpr::Ref(frame.ctx.vars.cell(*slot).clone())),
+                Op::LoadName(name) => self.stack.push(evaluator::eval_symbol(name, &frame.ctx)?),
+                Op::StoreSlot(slot) => {
+                    let value = self.stack.last().unwrap().clone();
+                    store(frame.ctx.vars.cell(*slot), value);
+                }
+                Op::StoreName(name) => {
+                    let value = self.stack.last().unwrap().clone();
+                    match frame.ctx.lookup_var(name) {
+                        Some(cell) => store(&cell, value),
+                        None => return Err(evaluator::undefined_variable(name)),
+                    }
+                }
+                Op::Pop => {
+                    self.stack.pop();
+                }
+                Op::Resolve => {
+                    let top = self.stack.last_mut().unwrap();
+                    if let parser::SExpr::Ref(_) = top {
+                        *top = evaluator::resolve_reference(top);
+                    }
+                }
+                Op::EnterScope => frame.scopes.push(frame.ctx.vars.len()),
+                Op::DefineVar(name) => {
+                    let value = frame.ctx.new_cell(self.stack.pop().unwrap())?;
+                    frame.ctx.vars.push(name.clone(), value);
+                }
+                Op::ExitScope => {
+                    let len = frame.scopes.pop().unwrap();
+                    frame.ctx.vars.truncate(len);
+                }
+                Op::Jump(target) => {
+                    // Every iteration of loop counts as a step
+                    if *target < frame.pc {
+                        frame.ctx.check_limits()?;
+                    }
+                    frame.pc = *target;
+                }
+                Op::JumpIfFalse(target) => {
+                    if !evaluator::value_is_true(&self.stack.pop().unwrap()) {
+                        frame.pc = *target;
+                    }
+                }
+                Op::MakeLambda(site) => {
+                    let captured = self.pop_args(site.captured_count());
+                    let lambda = site.make_lambda(captured);
+                    self.frames
+                        .last()
+                        .unwrap()
+                        .ctx
+                        .charge_memory(site.lambda_size(&lambda))?;
+                    self.stack.push(lambda);
+                }
+                Op::CallBuiltin(builtin, argc) => {
+                    // Arguments are passed in place, without copying them off the stack
+                    let args_start = self.stack.len() - argc;
+                    let result = builtin(&self.stack[args_start..], &mut frame.ctx)?;
+                    self.stack.truncate(args_start);
+                    self.stack.push(result);
+                }
+                Op::Call {
+                    argc,
+                    name,
+                    line,
+                    tail,
+                } => {
+                    let args = self.pop_args(*argc);
+                    let func = self.pop();
+                    self.call(func, args, name, *line, *tail)?;
+                }
+                Op::Fallback(sexpr) => {
+                    let value = evaluator::eval(sexpr, &mut frame.ctx)?;
+                    self.stack.push(value);
+                }
+                Op::Return => {
+                    let value = self.pop();
+                    if self.frames.len() == 1 {
+                        return Ok(value);
+                    }
+                    let frame = self.frames.pop().unwrap();
+                    if frame.is_call {
+                        frame.ctx.pop_call_frame();
+                    }
+                    self.stack.truncate(frame.stack_base);
+                    self.stack.push(value);
+                }
+            }
+        }
+    }
+
+    fn call(
+        &mut self,
+        func: parser::SExpr,
+        args: Vec<parser::SExpr>,
+        name: &str,
+        line: Option<usize>,
+        tail: bool,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let frame = self.frames.last_mut().unwrap();
+        if let Some(builtin) = builtins::as_builtin(&func) {
+            let result = builtin(&args, &mut frame.ctx)?;
+            self.stack.push(result);
+            return Ok(());
+        }
+        let call_frame = traceback::Frame {
+            name: String::from(name),
+            args: args.clone(),
+            line: line.or_else(|| evaluator::lambda_line(&func)),
+        };
+        let (mut new_ctx, body) = match evaluator::prepare_lambda_call(&frame.ctx, &func, args) {
+            Ok(call) => call,
+            Err(err) => {
+                frame.ctx.record_traceback(err.as_ref(), Some(call_frame));
+                return Err(err);
+            }
+        };
+        new_ctx.check_limits()?;
+
+        let body_cell = match body {
+            parser::SExpr::Ref(body_cell) => body_cell,
+            // Lambda created by the evaluator, e.g. by `defstruct`
+            body => {
+                new_ctx.push_call_frame(call_frame)?;
+                let result = evaluator::eval(&body, &mut new_ctx);
+                new_ctx.pop_call_frame();
+                self.stack.push(result?);
+                return Ok(());
+            }
+        };
+        let chunk = lambda_chunk(&body_cell, &new_ctx);
+        if tail && frame.is_call {
+            new_ctx.replace_call_frame(call_frame);
+            self.stack.truncate(frame.stack_base);
+            frame.chunk = chunk;
+            frame.pc = 0;
+            frame.ctx = new_ctx;
+            frame.scopes.clear();
+        } else {
+            new_ctx.push_call_frame(call_frame)?;
+            self.frames.push(Frame {
+                chunk,
+                pc: 0,
+                ctx: new_ctx,
+                stack_base: self.stack.len(),
+                scopes: Vec::new(),
+                is_call: true,
+            });
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,244 @@
+use std::time::Duration;
+use tk_lisp_test_1::{Atom, Engine, Interpreter, LimitError, Raised, SExpr};
+
+fn eval_number(code: &str, engine: Engine) -> f64 {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_engine(engine);
+    match interpreter.eval_str(code).unwrap() {
+        SExpr::Atom(Atom::Number(num)) => num,
+        other => panic!("Expected number, got {:?}", other),
+    }
+}
+
+#[test]
+fn spawned_threads_are_joined() {
+    let code = r#"
+        (let
+            (a (spawn (lambda () () (+ 1 2))))
+            (b (spawn (lambda () () (reduce + (list 4 5 6)))))
+            (+ (join a) (join b) (join a)))"#;
+    for engine in [Engine::TreeWalker, Engine::Bytecode] {
+        assert_eq!(eval_number(code, engine), 21.0);
+    }
+}
+
+#[test]
+fn join_raises_error_of_thread() {
+    let code = r#"
+        (let (t (spawn (lambda () () (raise 7))))
+            (+ (try (join t) (catch e e)) (try (join t) (catch e e))))"#;
+    assert_eq!(eval_number(code, Engine::Bytecode), 14.0);
+    let err = Interpreter::new()
+        .eval_str("(join (spawn (lambda () () (raise 5))))")
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<Raised>(),
+        Some(Raised {
+            value: SExpr::Atom(Atom::Number(num))
+        }) if *num == 5.0
+    ));
+}
+
+#[test]
+fn channels_pass_values_between_threads() {
+    let code = r#"
+        (let
+            (requests (channel))
+            (replies (channel))
+            (worker (spawn (lambda (requests replies) ()
+                (let (n (recv requests))
+                    (while (> n 0)
+                        ((send replies (+ n n))
+                         (set n (recv requests))))))))
+            (total 0)
+            ((send requests 1)
+             (send requests 2)
+             (send requests 3)
+             (send requests 0)
+             (set total (+ (recv replies) (recv replies) (recv replies)))
+             (join worker)
+             total))"#;
+    for engine in [Engine::TreeWalker, Engine::Bytecode] {
+        assert_eq!(eval_number(code, engine), 12.0);
+    }
+}
+
+#[test]
+fn atomics_and_mutexes_serialize_updates() {
+    let code = r#"
+        (let
+            (hits (make-atomic))
+            (count 0)
+            (lock (make-mutex))
+            (work (lambda (hits count lock) ()
+                (let (i 0)
+                    (while (< i 500)
+                        ((atomic-add! hits 1)
+                         (with-lock lock (lambda (count) () (set count (+ count 1))))
+                         (set i (+ i 1)))))))
+            (threads (list (spawn work) (spawn work) (spawn work) (spawn work)))
+            ((map (lambda () (t) (join t)) threads)
+             (+ (atomic-get hits) count)))"#;
+    for engine in [Engine::TreeWalker, Engine::Bytecode] {
+        assert_eq!(eval_number(code, engine), 4000.0);
+    }
+}
+
+#[test]
+fn atomic_compare_and_set_checks_current_value() {
+    let code = r#"
+        (let (a (make-atomic 3))
+            (list
+                (atomic-compare-and-set! a 4 10)
+                (atomic-compare-and-set! a 3 10)
+                (atomic-get a)
+                (atomic-set! a 1)))"#;
+    let result = Interpreter::new().eval_str(code).unwrap();
+    let values: Vec<f64> = match result {
+        SExpr::List(list) => list
+            .iter()
+            .map(|elem| match elem {
+                SExpr::Atom(Atom::Number(num)) => *num,
+                other => panic!("Expected number, got {:?}", other),
+            })
+            .collect(),
+        other => panic!("Expected list, got {:?}", other),
+    };
+    assert_eq!(values, vec![0.0, 1.0, 10.0, 1.0]);
+}
+
+#[test]
+fn relocking_mutex_fails_instead_of_deadlocking() {
+    let code = r#"
+        (let (lock (make-mutex))
+            (with-lock lock (lambda (lock) () (with-lock lock (lambda () () 1)))))"#;
+    assert!(Interpreter::new().eval_str(code).is_err());
+    let code = r#"
+        (let (lock (make-mutex))
+            ((try (with-lock lock (lambda () () (raise 1))) (catch e e))
+             (with-lock lock (lambda () () 2))))"#;
+    assert_eq!(eval_number(code, Engine::Bytecode), 2.0);
+}
+
+#[test]
+fn blocked_recv_stops_at_deadline() {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_timeout(Duration::from_millis(50));
+    let err = interpreter.eval_str("(recv (channel))").unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<LimitError>(),
+        Some(LimitError::Timeout)
+    ));
+}
+
+#[test]
+fn threads_left_running_are_stopped_when_evaluation_ends() {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_global("ticks", SExpr::List(vec![]));
+    interpreter
+        .eval_str(
+            r#"
+            (set ticks (make-atomic))
+            (spawn (lambda (ticks) () (while 1 (atomic-add! ticks 1))))
+            (spawn (lambda () () (recv (channel))))"#,
+        )
+        .unwrap();
+    let ticks = interpreter.eval_str("(atomic-get ticks)").unwrap();
+    std::thread::sleep(Duration::from_millis(30));
+    assert_eq!(
+        format!("{:?}", interpreter.eval_str("(atomic-get ticks)").unwrap()),
+        format!("{:?}", ticks)
+    );
+    // Interpreter evaluates normally afterwards
+    assert!(interpreter
+        .eval_str("(join (spawn (lambda () () 3)))")
+        .is_ok());
+}
+
+#[test]
+fn number_of_running_threads_is_limited() {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_max_threads(2);
+    let err = interpreter
+        .eval_str(
+            r#"
+            (let (c (channel))
+                (try
+                    (list
+                        (spawn (lambda (c) () (recv c)))
+                        (spawn (lambda (c) () (recv c)))
+                        (spawn (lambda (c) () (recv c))))
+                    (catch e e)))"#,
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<LimitError>(),
+        Some(&LimitError::ThreadLimitExceeded { limit: 2 })
+    );
+    // Finished threads don't count
+    interpreter.set_max_threads(1);
+    let code = r#"
+        (let (i 0) (s 0)
+            ((while (< i 20)
+                ((set s (+ s (join (spawn (lambda (i) () i)))))
+                 (set i (+ i 1))))
+             s))"#;
+    assert!(matches!(
+        interpreter.eval_str(code).unwrap(),
+        SExpr::Atom(Atom::Number(num)) if num == 190.0
+    ));
+}
+
+#[test]
+fn thread_stacks_and_channel_buffers_count_against_memory_limit() {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_memory_limit(Some(4 * 1024 * 1024));
+    let err = interpreter
+        .eval_str("(join (spawn (lambda () () 1)))")
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<LimitError>(),
+        Some(LimitError::MemoryLimitExceeded { .. })
+    ));
+
+    let fill = |receive: bool| {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_memory_limit(Some(1024 * 1024));
+        interpreter.eval_str(&format!(
+            r#"
+            (let (c (channel)) (i 0)
+                (while (< i 2000)
+                    ((send c (range 100))
+                     ({} c)
+                     (set i (+ i 1)))))"#,
+            if receive { "recv" } else { "list" }
+        ))
+    };
+    assert!(fill(true).is_ok());
+    let err = fill(false).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<LimitError>(),
+        Some(LimitError::MemoryLimitExceeded { .. })
+    ));
+}
+
+#[test]
+fn join_raises_limit_errors_of_thread_uncaught() {
+    let code = r#"
+        (let (f ())
+            ((set f (lambda (f) (n) (map f (list n))))
+             (try (join (spawn (lambda (f) () (call f 1)))) (catch e 0))))"#;
+    for engine in [Engine::TreeWalker, Engine::Bytecode] {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_engine(engine);
+        let err = interpreter.eval_str(code).unwrap_err();
+        assert!(
+            matches!(
+                err.downcast_ref::<LimitError>(),
+                Some(LimitError::StackExhausted { .. })
+            ),
+            "{}",
+            err
+        );
+    }
+}
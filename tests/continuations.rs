@@ -0,0 +1,68 @@
+use tk_lisp_test_1::{Atom, Engine, Interpreter, SExpr};
+
+fn number(value: SExpr) -> f64 {
+    match value {
+        SExpr::Atom(Atom::Number(num)) => num,
+        other => panic!("Expected number, got {:?}", other),
+    }
+}
+
+fn eval_number(code: &str) -> f64 {
+    number(Interpreter::new().eval_str(code).unwrap())
+}
+
+#[test]
+fn continuation_exits_early_from_loop() {
+    let result = eval_number(
+        r#"
+        (let
+            (cleanups 0)
+            (found
+                (call/cc (lambda (cleanups) (return)
+                    (unwind-protect
+                        ((for-each (lambda (return) (x) (if (> x 3) (call return x)))
+                            (list 1 2 5 7))
+                         0)
+                        (set cleanups (+ cleanups 1))))))
+            (+ found cleanups)
+        )"#,
+    );
+    assert_eq!(result, 6.0);
+}
+
+#[test]
+fn continuation_fails_after_call_cc_returned() {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_global("saved", SExpr::List(vec![]));
+    interpreter
+        .eval_str("(set saved (call/cc (lambda () (k) k)))")
+        .unwrap();
+    assert!(interpreter.eval_str("(call saved 1)").is_err());
+}
+
+#[test]
+fn continuations_cannot_be_forged() {
+    for engine in [Engine::TreeWalker, Engine::Bytecode] {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_engine(engine);
+        // Resuming continuation is not reachable from scripts
+        assert!(interpreter
+            .eval_str("(call/cc (lambda () (k) (continuation-resume 1 5)))")
+            .is_err());
+        // Lambda with the shape continuations used to have is only a lambda
+        let forged = "(call/cc (lambda () (k)
+            (call (list (quote lambda-captured) () (list (quote value)) (quote value)) 5)))";
+        assert_eq!(number(interpreter.eval_str(forged).unwrap()), 5.0);
+        let code = "(call/cc (lambda () (k)
+            (list (procedure? k) (eq? k k) (call/cc (lambda (k) (j) (eq? k j))) (call k 7))))";
+        assert_eq!(number(interpreter.eval_str(code).unwrap()), 7.0);
+        let code = "(call/cc (lambda () (k) (list (procedure? k) (eq? k k))))";
+        match interpreter.eval_str(code).unwrap() {
+            SExpr::List(flags) => {
+                let flags: Vec<f64> = flags.into_iter().map(number).collect();
+                assert_eq!(flags, [1.0, 1.0]);
+            }
+            other => panic!("Expected list, got {:?}", other),
+        }
+    }
+}
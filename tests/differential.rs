@@ -0,0 +1,215 @@
+use tk_lisp_test_1::{Engine, Interpreter, SExpr};
+
+// Value with references followed, so that results of both engines can be compared
+fn show(value: &SExpr) -> String {
+    match value {
+        SExpr::Atom(atom) => format!("{:?}", atom),
+        SExpr::List(list) => {
+            let elems: Vec<String> = list.iter().map(show).collect();
+            format!("({})", elems.join(" "))
+        }
+        SExpr::Ref(cell) => show(&cell.lock().unwrap()),
+        SExpr::Vector(vector) => {
+            let elems: Vec<String> = vector.lock().unwrap().iter().map(show).collect();
+            format!("[{}]", elems.join(" "))
+        }
+        SExpr::Hash(table) => format!("{{{} entries}}", table.lock().unwrap().len()),
+        SExpr::RecordType(record_type) => format!("#<type {}>", record_type.name),
+        SExpr::Record(record) => {
+            let record = record.lock().unwrap();
+            let fields: Vec<String> = record.fields.iter().map(show).collect();
+            format!("#<{} {}>", record.record_type.name, fields.join(" "))
+        }
+        SExpr::Generator(_) => String::from("#<generator>"),
+        SExpr::Thread(_) => String::from("#<thread>"),
+        SExpr::Channel(_) => String::from("#<channel>"),
+        SExpr::Lock(_) => String::from("#<mutex>"),
+        SExpr::Atomic(atomic) => format!("#<atomic {}>", atomic.get()),
+        SExpr::Continuation(_) => String::from("#<continuation>"),
+    }
+}
+
+// Result or error of `code`, with names of lambdas in traceback of the error
+fn run(code: &str, engine: Engine) -> String {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_engine(engine);
+    interpreter.set_max_recursion_depth(200);
+    match interpreter.eval_str(code) {
+        Ok(value) => show(&value),
+        Err(err) => {
+            let traceback = interpreter.traceback().unwrap();
+            let frames: Vec<&str> = traceback
+                .frames
+                .iter()
+                .map(|frame| frame.name.as_str())
+                .collect();
+            format!("error: {} at {:?} in {:?}", err, traceback.line, frames)
+        }
+    }
+}
+
+fn assert_same(programs: &[&str]) {
+    for code in programs {
+        assert_eq!(
+            run(code, Engine::TreeWalker),
+            run(code, Engine::Bytecode),
+            "{}",
+            code
+        );
+    }
+}
+
+#[test]
+fn variables_and_control_flow() {
+    assert_same(&[
+        "(+ 1 2 3)",
+        "(let (x 1) (y (+ x 1)) (+ x y))",
+        "(let (x 1) ((let (x 2) (set x 5)) x))",
+        "(let (x 1) (y x) ((set x 7) y))",
+        "(let (i 0) (s 0) ((while (< i 10) ((set s (+ s i)) (set i (+ i 1)))) s))",
+        "(let (i 0) (while (< i 3) (set i (+ i 1))))",
+        "(while 0 1)",
+        "(if 0 1)",
+        "(if (list) 1 2)",
+        "(cond ((> 1 2) 1) ((< 1 2) 2) (else 3))",
+        "(cond ((> 1 2) 1))",
+        "(cond (else 3) (bad clause here))",
+        "'(a b (c))",
+        ":key",
+        "()",
+        "(list \"ab\" (quote x) 1)",
+        "(let (s \"text\") (length s))",
+    ]);
+}
+
+#[test]
+fn lambdas_closures_and_arguments() {
+    assert_same(&[
+        "(let (f (lambda () (x y) (+ x y))) (call f 1 2))",
+        "(let (n 10) (f (lambda (n) (x) (+ x n))) ((set n 20) (call f 1)))",
+        "(let (x 1) (f (lambda () (a) (set a 5))) ((call f x) x))",
+        "(let (f (lambda () (a &optional (b 10) c) (list a b c))) (list (call f 1) (call f 1 2 3)))",
+        "(let (f (lambda () (a &rest more) more)) (call f 1 2 3))",
+        "(let (f (lambda () (&key (x 1) y) (list x y))) (call f :y 2))",
+        "(let (f (lambda () (x) x)) (call f))",
+        "(let (f (lambda () (x) x)) (call f 1 2))",
+        "(let (f (lambda () (x) x)) (map f (list 1 2 3)))",
+        "(map (lambda () (x) (+ x 1)) (filter (lambda () (x) (> x 1)) (list 1 2 3)))",
+        "(reduce + (list 1 2 3 4))",
+        "(call + 1 2)",
+        "(call 5 1)",
+        "(let (f (lambda () () +)) (call (call f) 1 2))",
+        "(let (make-adder (lambda () (n) (lambda (n) (x) (+ x n)))) (call (call make-adder 3) 4))",
+    ]);
+}
+
+#[test]
+fn recursion_and_tail_calls() {
+    assert_same(&[
+        r#"(let (count-up ())
+            ((set count-up (lambda (count-up) (n) (if (< n 5000) (call count-up (+ n 1)) n)))
+             (call count-up 0)))"#,
+        r#"(let (deep ())
+            ((set deep (lambda (deep) (n) (if (< n 5000) (+ 1 (call deep (+ n 1))) 0)))
+             (call deep 0)))"#,
+        r#"(let (tree ())
+            ((set tree (lambda (tree) (n) (if (> n 10) 1 (+ (call tree (+ n 1)) (call tree (+ n 1))))))
+             (call tree 0)))"#,
+        r#"(let (loop ())
+            ((set loop (lambda (loop) (n)
+                (cond ((> n 1000) n) (else (let (m (+ n 1)) (call loop m))))))
+             (call loop 0)))"#,
+    ]);
+}
+
+#[test]
+fn errors_and_tracebacks() {
+    assert_same(&[
+        "undefined",
+        "(set undefined 1)",
+        "(let (x 1) (set y x))",
+        "(let (f (lambda () (x) (+ x nothing))) (g (lambda (f) (y) (+ 1 (call f y)))) (call g 2))",
+        "(if 1 2 3 4)",
+        "(lambda (1) () 1)",
+        "(unknown-form 1 2)",
+        "(1 2 3)",
+        "(+ 1 (quote a))",
+        "(let (x 1) (let (x 2) (3 4) x))",
+        "(raise 5)",
+    ]);
+}
+
+#[test]
+fn forms_run_by_evaluator() {
+    assert_same(&[
+        "(defstruct point x y) (call point-y (call make-point 1 2))",
+        "(let (a 1) ((defstruct box v) (let (b (call make-box a)) (call box-v b))))",
+        "(let (i 0) ((while (< i 2) ((defstruct p v) (set i (+ i 1)))) (call p-v (call make-p i))))",
+        "(let (x 1) ((defstruct s v) x))",
+        "(let (x 5) (try (+ x undefined) (catch e (+ x 1)) (finally (set x 0))))",
+        "(let (x 5) ((unwind-protect (set x 6) (set x (+ x 1))) x))",
+        "(let (x 2) `(a ,x ,@(list x x)))",
+        "(defmacro inc! (v) `(set ,v (+ ,v 1))) (let (x 1) ((inc! x) (inc! x) x))",
+        "(let (x 1) ((defmacro twice (e) `(+ ,e ,e)) (twice x)))",
+        "(call/cc (lambda () (k) (+ 1 (call k 41))))",
+        r#"(let (g (generator (lambda () () ((yield 1) (yield 2) (yield 3)))))
+            (generator->list (map (lambda () (x) (+ x 10)) g)))"#,
+        "(let (v (vector 1 2)) ((vector-push! v 3) v))",
+    ]);
+}
+
+// Programs with more variables than are searched without index
+#[test]
+fn shadowing_in_large_environments() {
+    let bindings: String = (0..40).map(|i| format!("(v{} {}) ", i, i)).collect();
+    let programs: Vec<String> = [
+        "(let (x 1) (let (x 2) (+ x v39)))",
+        "(let (x 1) ((let (x 2) x) x))",
+        "(let (v5 100) ((set v5 (+ v5 1)) (list v5 v6)))",
+        "(let (v0 (let (v0 7) v0)) (+ v0 v1))",
+        "(let (i 0) ((while (< i 3) (let (v2 i) (set i (+ v2 1)))) (list i v2)))",
+        "(let (x 1) ((try (raise 2) (catch x (set v3 x))) (list x v3)))",
+        "(let (x 1) ((defstruct cell v) (call cell-v (call make-cell x))))",
+        "(let (f (lambda (v0 v39) (v0) (+ v0 v39))) (call f 5))",
+    ]
+    .iter()
+    .map(|body| format!("(let {}{})", bindings, body))
+    .collect();
+    let programs: Vec<&str> = programs.iter().map(String::as_str).collect();
+    assert_same(&programs);
+    assert_eq!(
+        run(programs[4], Engine::TreeWalker),
+        "(Number(3.0) Number(2.0))"
+    );
+}
+
+#[test]
+fn lambda_values_match_across_engines() {
+    let code = "(let (a 1) (lambda (a) (x &optional (y 2)) (+ x y a)))";
+    let lambdas: Vec<SExpr> = [Engine::TreeWalker, Engine::Bytecode]
+        .into_iter()
+        .map(|engine| {
+            let mut interpreter = Interpreter::new();
+            interpreter.set_engine(engine);
+            interpreter.eval_str(code).unwrap()
+        })
+        .collect();
+    // Bytecode engine keeps the body in a reference cell
+    assert!(matches!(&lambdas[0], SExpr::List(list) if !matches!(list[3], SExpr::Ref(_))));
+    assert!(matches!(&lambdas[1], SExpr::List(list) if matches!(list[3], SExpr::Ref(_))));
+    assert_eq!(show(&lambdas[0]), show(&lambdas[1]));
+
+    // Lambda created by one engine is called by the other
+    for (lambda, engine) in lambdas
+        .into_iter()
+        .zip([Engine::Bytecode, Engine::TreeWalker])
+    {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_engine(engine);
+        interpreter.set_global("f", lambda);
+        let result = interpreter
+            .eval_str("(list (call f 3) (map f (list 10)))")
+            .unwrap();
+        assert_eq!(show(&result), "(Number(6.0) (Number(13.0)))");
+    }
+}
@@ -0,0 +1,124 @@
+use tk_lisp_test_1::{Engine, Interpreter, SExpr};
+
+// Value with references followed
+fn show(value: &SExpr) -> String {
+    match value {
+        SExpr::Atom(atom) => format!("{:?}", atom),
+        SExpr::List(list) => {
+            let elems: Vec<String> = list.iter().map(show).collect();
+            format!("({})", elems.join(" "))
+        }
+        SExpr::Ref(cell) => show(&cell.lock().unwrap()),
+        other => format!("{:?}", other),
+    }
+}
+
+// Shown result or error message of `code` on both engines, which must agree
+fn eval(code: &str) -> Result<String, String> {
+    let results: Vec<Result<String, String>> = [Engine::TreeWalker, Engine::Bytecode]
+        .into_iter()
+        .map(|engine| {
+            let mut interpreter = Interpreter::new();
+            interpreter.set_engine(engine);
+            interpreter
+                .eval_str(code)
+                .map(|value| show(&value))
+                .map_err(|err| err.to_string())
+        })
+        .collect();
+    assert_eq!(results[0], results[1], "engines disagree on {}", code);
+    results[0].clone()
+}
+
+fn assert_evals_to(code: &str, expected: &str) {
+    assert_eq!(eval(code), eval(expected), "{}", code);
+}
+
+fn eval_error(code: &str) -> String {
+    match eval(code) {
+        Ok(value) => panic!("Expected error from {}, got {}", code, value),
+        Err(err) => err,
+    }
+}
+
+#[test]
+fn equal_compares_structure() {
+    assert_evals_to(
+        "(equal? (list 1 (list 2 (quote a))) (list 1 (list 2 (quote a))))",
+        "1",
+    );
+    assert_evals_to("(equal? (list 1 2) (list 1 2 3))", "0");
+    assert_evals_to("(equal? \"abc\" \"abc\")", "1");
+    assert_evals_to("(equal? [1 [2]] [1 [2]])", "1");
+    assert_evals_to("(equal? [1] (list 1))", "0");
+    assert_evals_to("(equal? {1 (list 2)} {1 (list 2)})", "1");
+    assert_evals_to("(equal? {1 2} {1 3})", "0");
+    assert_evals_to("(equal? (quote a) (quote b))", "0");
+}
+
+#[test]
+fn equal_terminates_on_cyclic_values() {
+    assert_evals_to(
+        "(let (v1 [0]) (v2 [0]) ((vector-set! v1 0 v1) (vector-set! v2 0 v2) (equal? v1 v2)))",
+        "1",
+    );
+}
+
+#[test]
+fn eq_and_eqv_compare_identity() {
+    assert_evals_to("(eq? (quote a) (quote a))", "1");
+    assert_evals_to("(eq? () ())", "1");
+    assert_evals_to("(eq? (list 1) (list 1))", "0");
+    assert_evals_to("(eq? [1] [1])", "0");
+    assert_evals_to("(let (v [1]) (w ()) ((set w v) (eq? v w)))", "1");
+    assert_evals_to("(let (h {}) (eq? h h))", "1");
+    assert_evals_to("(eqv? 2 (+ 1 1))", "1");
+    assert_evals_to("(eq? 2 (+ 1 1))", "1");
+    assert_evals_to("(eqv? \"a\" \"a\")", "0");
+}
+
+#[test]
+fn type_predicates_recognise_values() {
+    assert_evals_to(
+        "(list (number? 1) (number? (quote a)) (number? (list 1)))",
+        "(list 1 0 0)",
+    );
+    assert_evals_to(
+        "(list (symbol? (quote a)) (symbol? 1) (symbol? \"a\"))",
+        "(list 1 0 0)",
+    );
+    assert_evals_to(
+        "(list (string? \"ab\") (string? ()) (string? (list 1000)) (string? (quote a)))",
+        "(list 1 1 0 0)",
+    );
+    assert_evals_to(
+        "(list (procedure? +) (procedure? (lambda () (x) x)) (procedure? (quote car)))",
+        "(list 1 1 0)",
+    );
+}
+
+#[test]
+fn quoted_lists_shaped_like_builtins_are_not_procedures() {
+    assert_evals_to(
+        "(list (procedure? (quote (builtin +))) (equal? + (quote (builtin +))) (equal? + +))",
+        "(list 0 0 1)",
+    );
+    assert!(eval("(map (quote (builtin +)) (list 1))").is_err());
+    assert!(eval("(apply (quote (builtin list)) (list 1))").is_err());
+    // Builtins with several names
+    assert_evals_to(
+        "(map car (list (list 1) (list 2)))",
+        "(map first (list (list 1) (list 2)))",
+    );
+}
+
+#[test]
+fn comparison_operators_reject_non_numbers() {
+    let err = eval_error("(< (quote a) (quote b))");
+    assert!(
+        err.contains("use `equal?` to compare other values"),
+        "{}",
+        err
+    );
+    assert!(eval_error("(equal? 1)").contains("equal?"));
+}
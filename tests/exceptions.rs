@@ -0,0 +1,54 @@
+use tk_lisp_test_1::{Atom, Interpreter, LimitError, Raised, SExpr};
+
+fn eval_number(code: &str) -> f64 {
+    match Interpreter::new().eval_str(code).unwrap() {
+        SExpr::Atom(Atom::Number(num)) => num,
+        other => panic!("Expected number, got {:?}", other),
+    }
+}
+
+#[test]
+fn try_catches_raised_values_and_interpreter_errors() {
+    let result = eval_number(
+        r#"
+        (let
+            (cleanups 0)
+            (
+                (+
+                    (try (raise 40) (catch e e))
+                    (try (call undefined-function)
+                        (catch e (if (equal? (condition-kind e) 'undefined-variable) 1 0))
+                        (finally (set cleanups (+ cleanups 1))))
+                    (unwind-protect 0 (set cleanups (+ cleanups 1)))
+                    cleanups)
+            )
+        )"#,
+    );
+    assert_eq!(result, 43.0);
+}
+
+#[test]
+fn uncaught_raise_reaches_embedder() {
+    let err = Interpreter::new()
+        .eval_str("(try (raise 7) (finally ()))")
+        .unwrap_err();
+    match err.downcast_ref::<Raised>() {
+        Some(Raised {
+            value: SExpr::Atom(Atom::Number(num)),
+        }) => assert_eq!(*num, 7.0),
+        _ => panic!("Expected raised number, got {}", err),
+    }
+}
+
+#[test]
+fn exhausted_fuel_is_not_catchable() {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_fuel(Some(1000));
+    let err = interpreter
+        .eval_str("(try (while 1 ()) (catch e 0))")
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<LimitError>(),
+        Some(&LimitError::FuelExhausted)
+    );
+}
@@ -0,0 +1,74 @@
+use std::thread;
+use std::time::{Duration, Instant};
+use tk_lisp_test_1::{Engine, Interpreter, LimitError};
+
+// Limit error evaluation of `code` fails with
+fn limit_error(interpreter: &mut Interpreter, code: &str) -> LimitError {
+    let err = interpreter.eval_str(code).unwrap_err();
+    match err.downcast_ref::<LimitError>() {
+        Some(limit_err) => limit_err.clone(),
+        None => panic!("Expected limit error, got {}", err),
+    }
+}
+
+#[test]
+fn running_out_of_fuel_stops_evaluation() {
+    for engine in [Engine::TreeWalker, Engine::Bytecode] {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_engine(engine);
+        interpreter.set_fuel(Some(1000));
+        assert_eq!(
+            limit_error(&mut interpreter, "(while 1 ())"),
+            LimitError::FuelExhausted
+        );
+        assert_eq!(interpreter.remaining_fuel(), Some(0));
+        interpreter.set_fuel(None);
+        assert!(interpreter.eval_str("(+ 1 2)").is_ok());
+    }
+}
+
+#[test]
+fn passed_deadline_stops_evaluation() {
+    for engine in [Engine::TreeWalker, Engine::Bytecode] {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_engine(engine);
+        let start = Instant::now();
+        interpreter.set_timeout(Duration::from_millis(50));
+        assert_eq!(
+            limit_error(&mut interpreter, "(while 1 ())"),
+            LimitError::Timeout
+        );
+        assert!(start.elapsed() < Duration::from_secs(10));
+        // Deadline stays passed until it's moved
+        assert_eq!(
+            limit_error(&mut interpreter, "(while 1 ())"),
+            LimitError::Timeout
+        );
+        interpreter.set_deadline(None);
+        assert!(interpreter.eval_str("(+ 1 2)").is_ok());
+    }
+}
+
+#[test]
+fn cancel_handle_stops_evaluation_from_another_thread() {
+    for engine in [Engine::TreeWalker, Engine::Bytecode] {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_engine(engine);
+        let handle = interpreter.cancel_handle();
+        let canceller = thread::spawn({
+            let handle = handle.clone();
+            move || {
+                thread::sleep(Duration::from_millis(50));
+                handle.cancel();
+            }
+        });
+        assert_eq!(
+            limit_error(&mut interpreter, "(while 1 ())"),
+            LimitError::Cancelled
+        );
+        canceller.join().unwrap();
+        assert!(handle.is_cancelled());
+        handle.reset();
+        assert!(interpreter.eval_str("(+ 1 2)").is_ok());
+    }
+}
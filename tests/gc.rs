@@ -0,0 +1,106 @@
+use std::sync::Arc;
+use tk_lisp_test_1::{Atom, Engine, Interpreter, SExpr};
+
+fn eval_number(interpreter: &mut Interpreter, code: &str) -> f64 {
+    match interpreter.eval_str(code).unwrap() {
+        SExpr::Atom(Atom::Number(num)) => num,
+        other => panic!("Expected number, got {:?}", other),
+    }
+}
+
+// First variable cell captured by closure `(lambda-captured ((name cell)...) ...)`
+fn captured_cell(closure: &SExpr) -> &SExpr {
+    match closure {
+        SExpr::List(list) => match &list[1] {
+            SExpr::List(captures) => match &captures[0] {
+                SExpr::List(capture) => &capture[1],
+                other => panic!("Expected capture, got {:?}", other),
+            },
+            other => panic!("Expected captures, got {:?}", other),
+        },
+        other => panic!("Expected closure, got {:?}", other),
+    }
+}
+
+#[test]
+fn closure_capturing_its_own_variable_is_reclaimed() {
+    for engine in [Engine::TreeWalker, Engine::Bytecode] {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_engine(engine);
+        let closure = interpreter
+            .eval_str("(let (f ()) ((set f (lambda (f) () f)) f))")
+            .unwrap();
+        let cell = match captured_cell(&closure) {
+            SExpr::Ref(cell) => Arc::downgrade(cell),
+            other => panic!("Expected variable cell, got {:?}", other),
+        };
+        drop(closure);
+        assert!(
+            cell.upgrade().is_some(),
+            "cycle is not freed by reference counting"
+        );
+        assert_eq!(interpreter.collect_garbage(), 1);
+        assert!(cell.upgrade().is_none());
+    }
+}
+
+#[test]
+fn cyclic_containers_are_reclaimed() {
+    let mut interpreter = Interpreter::new();
+    let vector = match interpreter
+        .eval_str("(let (v (make-vector 1)) ((vector-set! v 0 v) v))")
+        .unwrap()
+    {
+        SExpr::Vector(vector) => Arc::downgrade(&vector),
+        other => panic!("Expected vector, got {:?}", other),
+    };
+    let table = match interpreter
+        .eval_str("(let (a (make-hash)) (b (make-hash 'a a)) ((hash-set! a 'b b) a))")
+        .unwrap()
+    {
+        SExpr::Hash(table) => Arc::downgrade(&table),
+        other => panic!("Expected hash, got {:?}", other),
+    };
+    assert!(vector.upgrade().is_some() && table.upgrade().is_some());
+    assert!(eval_number(&mut interpreter, "(gc)") >= 3.0);
+    assert!(vector.upgrade().is_none() && table.upgrade().is_none());
+    assert_eq!(eval_number(&mut interpreter, "(gc)"), 0.0);
+}
+
+#[test]
+fn reachable_values_survive_collection() {
+    let code = r#"
+        (let (v (make-vector 1)) (h (make-hash))
+            ((vector-set! v 0 v)
+             (hash-set! h 'v v)
+             (hash-set! h 'h h)
+             (gc)
+             (list (gc) (eq? (vector-ref (hash-get h 'v) 0) v) (eq? (hash-get h 'h) h))))"#;
+    let result = Interpreter::new().eval_str(code).unwrap();
+    let flags: Vec<f64> = match result {
+        SExpr::List(list) => list
+            .iter()
+            .map(|elem| match elem {
+                SExpr::Atom(Atom::Number(num)) => *num,
+                other => panic!("Expected number, got {:?}", other),
+            })
+            .collect(),
+        other => panic!("Expected list, got {:?}", other),
+    };
+    assert_eq!(flags, vec![0.0, 1.0, 1.0]);
+}
+
+#[test]
+fn heap_is_collected_automatically_as_it_grows() {
+    let mut interpreter = Interpreter::new();
+    let code = r#"
+        (let (i 0)
+            ((while (< i 5000)
+                ((let (v (make-vector 1)) (vector-set! v 0 v))
+                 (set i (+ i 1))))
+             (hash-get (heap-stats) 'collections)))"#;
+    assert!(eval_number(&mut interpreter, code) > 0.0);
+    let stats = interpreter.heap_stats();
+    assert!(stats.reclaimed > 0);
+    assert!(stats.objects < 5000, "{} objects left", stats.objects);
+}
@@ -0,0 +1,136 @@
+use tk_lisp_test_1::{Atom, Interpreter, LimitError, SExpr};
+
+fn eval_number(code: &str) -> f64 {
+    match Interpreter::new().eval_str(code).unwrap() {
+        SExpr::Atom(Atom::Number(num)) => num,
+        other => panic!("Expected number, got {:?}", other),
+    }
+}
+
+#[test]
+fn infinite_generator_is_consumed_lazily() {
+    let result = eval_number(
+        r#"
+        (let
+            (naturals (generator (lambda () ()
+                (let (i 0) (while (= 1 1) ((yield i) (set i (+ i 1))))))))
+            (big (filter (lambda () (x) (> x 10)) (map (lambda () (x) (+ x x)) naturals)))
+            (reduce + (generator->list big 3))
+        )"#,
+    );
+    // 12 + 14 + 16
+    assert_eq!(result, 42.0);
+}
+
+#[test]
+fn exhausted_generator_raises_condition() {
+    let result = eval_number(
+        r#"
+        (let
+            (g (generator (lambda () () ((yield 1) (yield 2)))))
+            (sum 0)
+            ((for-each (lambda (sum) (x) (set sum (+ sum x))) g)
+             (if (done? g)
+                 (try (next g)
+                      (catch e (if (equal? (condition-kind e) (quote generator-exhausted)) sum 0)))
+                 0))
+        )"#,
+    );
+    assert_eq!(result, 3.0);
+}
+
+#[test]
+fn dropped_generator_runs_cleanups() {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_global("cleanups", SExpr::Atom(Atom::Number(0.0)));
+    interpreter
+        .eval_str(
+            "(let (g (generator (lambda (cleanups) () \
+                (unwind-protect (yield 1) (set cleanups (+ cleanups 1)))))) (next g))",
+        )
+        .unwrap();
+    let result = interpreter.eval_str("cleanups").unwrap();
+    assert!(matches!(result, SExpr::Atom(Atom::Number(num)) if num == 1.0));
+}
+
+fn limit_error(interpreter: &mut Interpreter, code: &str) -> LimitError {
+    let err = interpreter.eval_str(code).unwrap_err();
+    match err.downcast_ref::<LimitError>() {
+        Some(limit_err) => limit_err.clone(),
+        None => panic!("Expected limit error, got {}", err),
+    }
+}
+
+// Starts `n` generators suspended in `yield` and keeps them in global `started`
+fn start_generators(n: usize) -> String {
+    format!(
+        "(let (i 0) (while (< i {}) ((set started (cons (generator (lambda () () (yield 1))) started)) \
+            (next (first started)) (set i (+ i 1)))))",
+        n
+    )
+}
+
+#[test]
+fn running_generators_are_capped() {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_global("started", SExpr::List(vec![]));
+    interpreter.set_max_generators(3);
+    assert!(interpreter.eval_str(&start_generators(3)).is_ok());
+    // Generators suspended by the previous evaluation were closed when it ended
+    assert!(interpreter.eval_str(&start_generators(3)).is_ok());
+    assert_eq!(
+        limit_error(&mut interpreter, &start_generators(4)),
+        LimitError::GeneratorLimitExceeded { limit: 3 }
+    );
+    // Generators which weren't started don't count
+    assert!(interpreter
+        .eval_str("(let (i 0) (while (< i 10) ((set started (cons (generator (lambda () () (yield 1))) started)) (set i (+ i 1)))))")
+        .is_ok());
+}
+
+#[test]
+fn generator_stacks_count_against_memory_limit() {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_global("started", SExpr::List(vec![]));
+    interpreter.set_memory_limit(Some(20 * 1024 * 1024));
+    assert!(interpreter.eval_str(&start_generators(2)).is_ok());
+    assert!(matches!(
+        limit_error(&mut interpreter, &start_generators(3)),
+        LimitError::MemoryLimitExceeded { .. }
+    ));
+    // Stacks of closed generators are released
+    assert!(interpreter.memory_usage() < 1024 * 1024);
+}
+
+#[test]
+fn suspended_generators_are_closed_when_evaluation_ends() {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_global("cleanups", SExpr::Atom(Atom::Number(0.0)));
+    interpreter.set_global("g", SExpr::List(vec![]));
+    interpreter
+        .eval_str(
+            "(set g (generator (lambda (cleanups) () \
+                (unwind-protect ((yield 1) (yield 2)) (set cleanups (+ cleanups 1)))))) (next g)",
+        )
+        .unwrap();
+    let cleanups = interpreter.eval_str("cleanups").unwrap();
+    assert!(matches!(cleanups, SExpr::Atom(Atom::Number(num)) if num == 1.0));
+    let done = interpreter.eval_str("(done? g)").unwrap();
+    assert!(matches!(done, SExpr::Atom(Atom::Number(num)) if num == 1.0));
+}
+
+#[test]
+fn generator_cycles_are_reclaimed() {
+    let mut interpreter = Interpreter::new();
+    // Generator captures variable holding it
+    interpreter
+        .eval_str("(let (g ()) ((set g (generator (lambda (g) () (yield g)))) 0))")
+        .unwrap();
+    assert_eq!(interpreter.collect_garbage(), 2);
+    // Closing suspended generator when evaluation ends breaks the cycle
+    interpreter
+        .eval_str("(let (g ()) ((set g (generator (lambda (g) () (yield g)))) (next g) 0))")
+        .unwrap();
+    interpreter.collect_garbage();
+    assert_eq!(interpreter.heap_stats().objects, 0);
+}
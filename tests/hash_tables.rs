@@ -0,0 +1,109 @@
+use tk_lisp_test_1::{Engine, Interpreter, SExpr};
+
+// Value with references followed
+fn show(value: &SExpr) -> String {
+    match value {
+        SExpr::Atom(atom) => format!("{:?}", atom),
+        SExpr::List(list) => {
+            let elems: Vec<String> = list.iter().map(show).collect();
+            format!("({})", elems.join(" "))
+        }
+        SExpr::Ref(cell) => show(&cell.lock().unwrap()),
+        SExpr::Hash(table) => format!("{{{} entries}}", table.lock().unwrap().len()),
+        other => format!("{:?}", other),
+    }
+}
+
+// Shown result or error message of `code` on both engines, which must agree
+fn eval(code: &str) -> Result<String, String> {
+    let results: Vec<Result<String, String>> = [Engine::TreeWalker, Engine::Bytecode]
+        .into_iter()
+        .map(|engine| {
+            let mut interpreter = Interpreter::new();
+            interpreter.set_engine(engine);
+            interpreter
+                .eval_str(code)
+                .map(|value| show(&value))
+                .map_err(|err| err.to_string())
+        })
+        .collect();
+    assert_eq!(results[0], results[1], "engines disagree on {}", code);
+    results[0].clone()
+}
+
+fn assert_evals_to(code: &str, expected: &str) {
+    assert_eq!(eval(code), eval(expected), "{}", code);
+}
+
+fn eval_error(code: &str) -> String {
+    match eval(code) {
+        Ok(value) => panic!("Expected error from {}, got {}", code, value),
+        Err(err) => err,
+    }
+}
+
+#[test]
+fn literal_creates_table_with_entries() {
+    assert_evals_to("(hash-count {1 2 \"a\" 3 (quote b) 4})", "3");
+    assert_evals_to("(hash-count {})", "0");
+    assert_evals_to("(hash-get {1 (+ 1 1)} 1)", "2");
+    assert_evals_to("(hash-count (make-hash 1 2 1 3))", "1");
+    assert!(eval("{1 2 3}")
+        .unwrap_err()
+        .contains("even number of elements"));
+    assert!(eval("{1 2")
+        .unwrap_err()
+        .contains("not closed by right brace"));
+    assert!(eval_error("(make-hash 1)").contains("pairs of keys and values"));
+}
+
+#[test]
+fn keys_are_numbers_strings_or_symbols() {
+    assert_evals_to(
+        "(let (h {1 (quote one) \"two\" 2 (quote three) 3}) (list (hash-get h 1) (hash-get h \"two\") (hash-get h (quote three))))",
+        "(list (quote one) 2 3)",
+    );
+    // Strings are compared by contents, and symbols are distinct from strings
+    assert_evals_to("(hash-contains? {\"ab\" 1} (append \"a\" \"b\"))", "1");
+    assert_evals_to("(hash-contains? {(quote ab) 1} \"ab\")", "0");
+    assert!(eval_error("(hash-get {} (list (list 1)))")
+        .contains("key must be a number, string or symbol"));
+    assert!(eval_error("(hash-get (list) 1)").contains("`hash-get` expects a hash table"));
+}
+
+#[test]
+fn missing_keys_give_default() {
+    assert_evals_to("(hash-get {1 2} 3)", "()");
+    assert_evals_to("(hash-get {1 2} 3 (quote none))", "(quote none)");
+    assert!(eval_error("(hash-get {1 2})").contains("2 or 3 argument(s)"));
+}
+
+#[test]
+fn tables_are_mutated_in_place() {
+    assert_evals_to(
+        "(let (h {}) ((hash-set! h (quote a) 1) (hash-set! h (quote b) 2) (hash-set! h (quote a) 3) (list (hash-count h) (hash-get h (quote a)))))",
+        "(list 2 3)",
+    );
+    assert_evals_to(
+        "(let (h {1 2 3 4}) ((list (hash-remove! h 1) (hash-remove! h 1) (hash-count h))))",
+        "(list 2 () 1)",
+    );
+    // Table is shared, not copied, by variables holding it
+    assert_evals_to(
+        "(let (h {}) (g ()) ((set g h) (hash-set! g 1 2) (hash-get h 1)))",
+        "2",
+    );
+}
+
+#[test]
+fn keys_and_values_list_entries() {
+    assert_evals_to(
+        "(sort (hash-keys {1 (quote a) 3 (quote b) 2 (quote c)}) <)",
+        "(list 1 2 3)",
+    );
+    assert_evals_to(
+        "(sort (hash-values {(quote a) 1 (quote b) 3 (quote c) 2}) <)",
+        "(list 1 2 3)",
+    );
+    assert_evals_to("(hash-keys {})", "()");
+}
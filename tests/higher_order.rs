@@ -0,0 +1,139 @@
+use tk_lisp_test_1::{Engine, Interpreter, SExpr};
+
+// Value with references followed
+fn show(value: &SExpr) -> String {
+    match value {
+        SExpr::Atom(atom) => format!("{:?}", atom),
+        SExpr::List(list) => {
+            let elems: Vec<String> = list.iter().map(show).collect();
+            format!("({})", elems.join(" "))
+        }
+        SExpr::Ref(cell) => show(&cell.lock().unwrap()),
+        other => format!("{:?}", other),
+    }
+}
+
+// Shown result or error message of `code` on both engines, which must agree
+fn eval(code: &str) -> Result<String, String> {
+    let results: Vec<Result<String, String>> = [Engine::TreeWalker, Engine::Bytecode]
+        .into_iter()
+        .map(|engine| {
+            let mut interpreter = Interpreter::new();
+            interpreter.set_engine(engine);
+            interpreter
+                .eval_str(code)
+                .map(|value| show(&value))
+                .map_err(|err| err.to_string())
+        })
+        .collect();
+    assert_eq!(results[0], results[1], "engines disagree on {}", code);
+    results[0].clone()
+}
+
+fn assert_evals_to(code: &str, expected: &str) {
+    assert_eq!(eval(code), eval(expected), "{}", code);
+}
+
+fn eval_error(code: &str) -> String {
+    match eval(code) {
+        Ok(value) => panic!("Expected error from {}, got {}", code, value),
+        Err(err) => err,
+    }
+}
+
+#[test]
+fn map_calls_function_with_elements_of_all_lists() {
+    assert_evals_to("(map (lambda () (x) (+ x 1)) (list 1 2 3))", "(list 2 3 4)");
+    assert_evals_to("(map + (list 1 2 3) (list 10 20))", "(list 11 22)");
+    assert_evals_to(
+        "(map list (list 1 2) (list 3 4) (list 5 6))",
+        "(list (list 1 3 5) (list 2 4 6))",
+    );
+    assert_evals_to("(map + ())", "()");
+    assert!(eval_error("(map +)").contains("at least one list"));
+    assert!(eval_error("(map + 1)").contains("`map` expects a list"));
+}
+
+#[test]
+fn for_each_and_filter_visit_elements_in_order() {
+    assert_evals_to(
+        "(let (acc ()) ((for-each (lambda (acc) (x) (set acc (cons x acc))) (list 1 2 3)) acc))",
+        "(list 3 2 1)",
+    );
+    assert_evals_to(
+        "(filter (lambda () (x) (> x 1)) (list 3 1 2 0))",
+        "(list 3 2)",
+    );
+}
+
+#[test]
+fn folds_combine_elements_from_either_end() {
+    assert_evals_to("(reduce + (list 1 2 3))", "6");
+    assert_evals_to("(reduce + 10 ())", "10");
+    assert_evals_to("(fold-left list () (list 1 2))", "(list (list () 1) 2)");
+    assert_evals_to("(fold-right list () (list 1 2))", "(list 1 (list 2 ()))");
+    assert_evals_to("(fold-right cons () (list 1 2 3))", "(list 1 2 3)");
+    assert!(eval_error("(reduce + ())").contains("empty list without initial value"));
+}
+
+#[test]
+fn apply_spreads_last_list_as_arguments() {
+    assert_evals_to("(apply + (list 1 2))", "3");
+    assert_evals_to("(apply list 1 2 (list 3 4))", "(list 1 2 3 4)");
+    assert_evals_to("(apply (lambda () (&rest r) r) ())", "()");
+    assert!(eval_error("(apply +)").contains("`apply` expects"));
+}
+
+#[test]
+fn any_and_every_stop_at_first_decisive_element() {
+    assert_evals_to("(any (lambda () (x) (> x 2)) (list 1 3))", "1");
+    assert_evals_to("(any (lambda () (x) (> x 2)) ())", "0");
+    assert_evals_to("(every (lambda () (x) (> x 0)) (list 1 3))", "1");
+    assert_evals_to("(every (lambda () (x) (> x 1)) (list 1 3))", "0");
+    // Stops before the element the predicate would fail on
+    assert_evals_to("(any (lambda () (x) (> x 0)) (list 1 (list)))", "1");
+}
+
+#[test]
+fn sort_is_stable_and_propagates_comparator_errors() {
+    assert_evals_to("(sort (list 3 1 2) <)", "(list 1 2 3)");
+    assert_evals_to(
+        "(sort (list (list 1 0) (list 0 1) (list 1 1) (list 0 2)) (lambda () (a b) (< (first a) (first b))))",
+        "(list (list 0 1) (list 0 2) (list 1 0) (list 1 1))",
+    );
+    assert_evals_to("(sort () <)", "()");
+    assert!(eval("(sort (list 1 (list 2)) <)").is_err());
+}
+
+#[test]
+fn range_counts_by_step() {
+    assert_evals_to("(range 3)", "(list 0 1 2)");
+    assert_evals_to("(range 2 5)", "(list 2 3 4)");
+    assert_evals_to("(range 0 7 3)", "(list 0 3 6)");
+    assert_evals_to("(range 5 2)", "()");
+    assert!(eval_error("(range 1 2 0)").contains("step must not be 0"));
+}
+
+#[test]
+fn range_too_long_to_allocate_is_an_error() {
+    assert!(eval_error("(range 100000000000)").contains("too large"));
+    assert!(eval_error("(range 5 100000000005)").contains("too large"));
+}
+
+#[test]
+fn range_of_infinite_bounds_is_an_error() {
+    let inf = "(let (x 1) ((while (< x (+ x x)) (set x (+ x x))) x))";
+    let err = eval_error(&format!("(range {})", inf));
+    assert!(
+        err.contains("`range`: length inf is not a finite number"),
+        "{}",
+        err
+    );
+    // Infinite start and end give NaN length
+    let err = eval_error(&format!("(range {} {})", inf, inf));
+    assert!(
+        err.contains("`range`: length NaN is not a finite number"),
+        "{}",
+        err
+    );
+}
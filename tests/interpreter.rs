@@ -0,0 +1,94 @@
+use tk_lisp_test_1::{Atom, Engine, EvalContext, Interpreter, Raised, SExpr};
+
+fn number(value: SExpr) -> f64 {
+    match value {
+        SExpr::Atom(Atom::Number(num)) => num,
+        other => panic!("Expected number, got {:?}", other),
+    }
+}
+
+#[test]
+fn eval_str_returns_value_of_last_expression() {
+    let mut interpreter = Interpreter::new();
+    assert_eq!(
+        number(interpreter.eval_str("(+ 1 2) (+ 3 4)").unwrap()),
+        7.0
+    );
+    assert!(matches!(
+        interpreter.eval_str("").unwrap(),
+        SExpr::List(list) if list.is_empty()
+    ));
+}
+
+#[test]
+fn tree_walker_is_default_engine() {
+    assert_eq!(Interpreter::new().engine(), Engine::TreeWalker);
+}
+
+#[test]
+fn globals_persist_between_evaluations() {
+    for engine in [Engine::TreeWalker, Engine::Bytecode] {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_engine(engine);
+        assert_eq!(interpreter.engine(), engine);
+        interpreter.set_global("x", SExpr::Atom(Atom::Number(4.0)));
+        assert_eq!(number(interpreter.eval_str("(+ x 3)").unwrap()), 7.0);
+        interpreter.eval_str("(set x 10)").unwrap();
+        assert_eq!(number(interpreter.get_global("x").unwrap()), 10.0);
+        interpreter.set_global("x", SExpr::Atom(Atom::Number(1.0)));
+        assert_eq!(number(interpreter.eval_str("x").unwrap()), 1.0);
+        assert!(interpreter.get_global("undefined").is_none());
+    }
+}
+
+#[test]
+fn context_variables_are_read_and_written() {
+    let mut ctx = EvalContext::new();
+    assert!(ctx.get_var("y").is_none());
+    ctx.set_var("y", SExpr::Atom(Atom::Number(2.0)));
+    assert_eq!(number(ctx.get_var("y").unwrap()), 2.0);
+    ctx.set_var("y", SExpr::Atom(Atom::Number(5.0)));
+    assert_eq!(number(ctx.get_var("y").unwrap()), 5.0);
+    let mut interpreter = Interpreter::new();
+    interpreter
+        .context()
+        .set_var("z", SExpr::Atom(Atom::Number(3.0)));
+    assert_eq!(number(interpreter.eval_str("(+ z z)").unwrap()), 6.0);
+}
+
+#[test]
+fn errors_are_returned_to_embedder() {
+    for engine in [Engine::TreeWalker, Engine::Bytecode] {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_engine(engine);
+        let err = interpreter.eval_str("(+ 1 undefined)").unwrap_err();
+        assert!(err.to_string().contains("undefined"), "{}", err);
+        assert!(interpreter.eval_str("(+ 1").is_err());
+        let err = interpreter.eval_str("(raise 3)").unwrap_err();
+        assert!(err.is::<Raised>());
+        // Interpreter stays usable after errors
+        assert_eq!(number(interpreter.eval_str("(+ 1 1)").unwrap()), 2.0);
+    }
+}
+
+#[test]
+fn malformed_special_forms_fail_with_invalid_input() {
+    for engine in [Engine::TreeWalker, Engine::Bytecode] {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_engine(engine);
+        for (code, message) in [
+            ("(if)", "`if` must have 3 or 4 elements"),
+            ("(if 1)", "`if` must have 3 or 4 elements"),
+            ("(let () 1)", "`let` must have exactly 2 elements"),
+            ("(let (x) x)", "`let` must have exactly 2 elements"),
+            ("(let (x 1 2) x)", "`let` must have exactly 2 elements"),
+        ] {
+            let err = interpreter.eval_str(code).unwrap_err();
+            match err.downcast_ref::<std::io::Error>() {
+                Some(io_err) => assert_eq!(io_err.kind(), std::io::ErrorKind::InvalidInput),
+                None => panic!("Expected invalid input error for {}, got {}", code, err),
+            }
+            assert!(err.to_string().contains(message), "{}: {}", code, err);
+        }
+    }
+}
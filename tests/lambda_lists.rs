@@ -0,0 +1,110 @@
+use tk_lisp_test_1::{Engine, Interpreter, SExpr};
+
+// Result of `code` on both engines, which must agree
+fn eval(code: &str) -> Result<String, String> {
+    let results: Vec<Result<String, String>> = [Engine::TreeWalker, Engine::Bytecode]
+        .into_iter()
+        .map(|engine| {
+            let mut interpreter = Interpreter::new();
+            interpreter.set_engine(engine);
+            interpreter
+                .eval_str(code)
+                .map(|value| show(&value))
+                .map_err(|err| err.to_string())
+        })
+        .collect();
+    assert_eq!(results[0], results[1], "engines disagree on {}", code);
+    results[0].clone()
+}
+
+fn show(value: &SExpr) -> String {
+    match value {
+        SExpr::Atom(atom) => format!("{:?}", atom),
+        SExpr::List(list) => {
+            let elems: Vec<String> = list.iter().map(show).collect();
+            format!("({})", elems.join(" "))
+        }
+        SExpr::Ref(cell) => show(&cell.lock().unwrap()),
+        other => format!("{:?}", other),
+    }
+}
+
+// Calls lambda with parameter list `params` and body `(list names)` with `args`
+fn call(params: &str, names: &str, args: &str) -> Result<String, String> {
+    eval(&format!(
+        "(call (lambda () {} (list {})) {})",
+        params, names, args
+    ))
+}
+
+#[test]
+fn required_parameters_check_arity() {
+    assert_eq!(
+        call("(a b)", "a b", "1 2"),
+        Ok(String::from("(Number(1.0) Number(2.0))"))
+    );
+    let err = call("(a b)", "a b", "1").unwrap_err();
+    assert!(err.contains("expects 2 argument(s), got 1"), "{}", err);
+    let err = call("(a b)", "a b", "1 2 3").unwrap_err();
+    assert!(err.contains("expects 2 argument(s), got 3"), "{}", err);
+    assert_eq!(call("()", "", ""), Ok(String::from("()")));
+}
+
+#[test]
+fn optional_parameters_take_defaults() {
+    assert_eq!(
+        call("(a &optional (b 5) c)", "a b c", "1"),
+        Ok(String::from("(Number(1.0) Number(5.0) ())"))
+    );
+    assert_eq!(
+        call("(a &optional (b 5) c)", "a b c", "1 2 3"),
+        Ok(String::from("(Number(1.0) Number(2.0) Number(3.0))"))
+    );
+    let err = call("(a &optional b)", "a b", "1 2 3").unwrap_err();
+    assert!(err.contains("at most 2 argument(s), got 3"), "{}", err);
+    let err = call("(a &optional b)", "a b", "").unwrap_err();
+    assert!(err.contains("at least 1 argument(s), got 0"), "{}", err);
+    // Defaults can refer to earlier parameters
+    assert_eq!(
+        eval("(call (lambda () (a &optional (b (+ a 1))) b) 4)"),
+        Ok(String::from("Number(5.0)"))
+    );
+}
+
+#[test]
+fn rest_parameter_collects_remaining_arguments() {
+    assert_eq!(
+        call("(a &rest more)", "a more", "1"),
+        Ok(String::from("(Number(1.0) ())"))
+    );
+    assert_eq!(
+        call("(a &rest more)", "a more", "1 2 3"),
+        Ok(String::from("(Number(1.0) (Number(2.0) Number(3.0)))"))
+    );
+    assert_eq!(call("(&rest more)", "more", ""), Ok(String::from("(())")));
+    assert!(eval("(call (lambda () (&rest) 1))").is_err());
+}
+
+#[test]
+fn keyword_parameters_are_matched_by_name() {
+    assert_eq!(
+        call("(&key (x 1) y)", "x y", ":y 2"),
+        Ok(String::from("(Number(1.0) Number(2.0))"))
+    );
+    assert_eq!(
+        call("(&key x y)", "x y", ":y 2 :x 3 :x 4"),
+        Ok(String::from("(Number(3.0) Number(2.0))"))
+    );
+    let err = call("(&key x)", "x", ":z 1").unwrap_err();
+    assert!(err.contains("Unknown keyword argument :z"), "{}", err);
+    let err = call("(&key x)", "x", ":x").unwrap_err();
+    assert!(err.contains("pairs"), "{}", err);
+    let err = call("(&key x)", "x", "1 2").unwrap_err();
+    assert!(err.contains("pairs"), "{}", err);
+}
+
+#[test]
+fn misplaced_lambda_list_keywords_are_errors() {
+    let err = call("(&rest r &optional o)", "r o", "").unwrap_err();
+    assert!(err.contains("Misplaced lambda list keyword"), "{}", err);
+}
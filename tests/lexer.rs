@@ -0,0 +1,31 @@
+use tk_lisp_test_1::lexer::{self, Token};
+
+#[test]
+fn tokens_at_end_of_input_are_complete() {
+    let tokens = lexer::lex(String::from("(+ 12 x1")).unwrap();
+    assert!(matches!(tokens[0], Token::LeftParen));
+    assert!(matches!(&tokens[1], Token::Symbol(sym) if *sym == "+"));
+    assert!(matches!(tokens[2], Token::Number(num) if num == 12.0));
+    assert!(matches!(&tokens[3], Token::Symbol(sym) if *sym == "x1"));
+    assert_eq!(tokens.len(), 4);
+}
+
+#[test]
+fn strings_are_unescaped() {
+    let tokens = lexer::lex(String::from(r#""a\"b\n""#)).unwrap();
+    assert!(matches!(&tokens[0], Token::String(text) if text == "a\"b\n"));
+}
+
+#[test]
+fn malformed_strings_are_errors() {
+    assert!(lexer::lex(String::from("\"abc")).is_err());
+    assert!(lexer::lex(String::from("\"abc\\")).is_err());
+    assert!(lexer::lex(String::from(r#""a\qb""#)).is_err());
+}
+
+#[test]
+fn tokens_know_their_lines() {
+    let (tokens, lines) = lexer::lex_with_lines(String::from("(a\n  b\n\n c)")).unwrap();
+    assert_eq!(tokens.len(), 5);
+    assert_eq!(lines, vec![1, 1, 2, 4, 4]);
+}
@@ -0,0 +1,89 @@
+use tk_lisp_test_1::{Engine, Interpreter, SExpr};
+
+// Value with references followed
+fn show(value: &SExpr) -> String {
+    match value {
+        SExpr::Atom(atom) => format!("{:?}", atom),
+        SExpr::List(list) => {
+            let elems: Vec<String> = list.iter().map(show).collect();
+            format!("({})", elems.join(" "))
+        }
+        SExpr::Ref(cell) => show(&cell.lock().unwrap()),
+        other => format!("{:?}", other),
+    }
+}
+
+// Shown result or error message of `code` on both engines, which must agree
+fn eval(code: &str) -> Result<String, String> {
+    let results: Vec<Result<String, String>> = [Engine::TreeWalker, Engine::Bytecode]
+        .into_iter()
+        .map(|engine| {
+            let mut interpreter = Interpreter::new();
+            interpreter.set_engine(engine);
+            interpreter
+                .eval_str(code)
+                .map(|value| show(&value))
+                .map_err(|err| err.to_string())
+        })
+        .collect();
+    assert_eq!(results[0], results[1], "engines disagree on {}", code);
+    results[0].clone()
+}
+
+fn assert_evals_to(code: &str, expected: &str) {
+    assert_eq!(eval(code), eval(expected), "{}", code);
+}
+
+fn eval_error(code: &str) -> String {
+    match eval(code) {
+        Ok(value) => panic!("Expected error from {}, got {}", code, value),
+        Err(err) => err,
+    }
+}
+
+#[test]
+fn cons_first_and_rest_build_and_split_lists() {
+    assert_evals_to("(cons 1 (list 2 3))", "(list 1 2 3)");
+    assert_evals_to("(cons 1 ())", "(list 1)");
+    assert_evals_to("(first (list 1 2 3))", "1");
+    assert_evals_to("(rest (list 1 2 3))", "(list 2 3)");
+    assert_evals_to("(rest (list 1))", "()");
+    assert!(eval_error("(first ())").contains("`first` of empty list"));
+    assert!(eval_error("(rest ())").contains("`rest` of empty list"));
+    assert!(eval_error("(cons 1 2)").contains("`cons` expects a list"));
+    assert!(eval_error("(cons 1)").contains("cons"));
+}
+
+#[test]
+fn length_nth_and_last_inspect_lists() {
+    assert_evals_to("(length (list 1 2 3))", "3");
+    assert_evals_to("(length ())", "0");
+    assert_evals_to("(nth 0 (list 4 5 6))", "4");
+    assert_evals_to("(nth 2 (list 4 5 6))", "6");
+    assert_evals_to("(last (list 4 5 6))", "6");
+    assert!(eval_error("(nth 3 (list 4 5 6))").contains("nth"));
+    assert!(eval_error("(nth (list 0) (list 4 5 6))").contains("nth"));
+    assert!(eval_error("(last ())").contains("`last` of empty list"));
+}
+
+#[test]
+fn append_and_reverse_return_new_lists() {
+    assert_evals_to("(append (list 1 2) () (list 3))", "(list 1 2 3)");
+    assert_evals_to("(append)", "()");
+    assert_evals_to("(reverse (list 1 2 3))", "(list 3 2 1)");
+    assert_evals_to(
+        "(let (l (list 1 2)) ((reverse l) (append l (list 3)) l))",
+        "(list 1 2)",
+    );
+    assert!(eval_error("(append (list 1) 2)").contains("`append` expects a list"));
+}
+
+#[test]
+fn predicates_recognise_lists() {
+    assert_evals_to("(null? ())", "1");
+    assert_evals_to("(null? (list 1))", "0");
+    assert_evals_to("(null? 0)", "0");
+    assert_evals_to("(list? (list 1))", "1");
+    assert_evals_to("(list? ())", "1");
+    assert_evals_to("(list? 1)", "0");
+}
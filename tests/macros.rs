@@ -0,0 +1,76 @@
+use tk_lisp_test_1::{Atom, Interpreter, SExpr};
+
+fn eval_number(code: &str) -> f64 {
+    match Interpreter::new().eval_str(code).unwrap() {
+        SExpr::Atom(Atom::Number(num)) => num,
+        other => panic!("Expected number, got {:?}", other),
+    }
+}
+
+#[test]
+fn macro_receives_unevaluated_arguments() {
+    let result = eval_number(
+        r#"
+        (defmacro unless (cond &rest body) `(if ,cond () (,@body)))
+        (defmacro inc! (var) `(set ,var (+ ,var 1)))
+        (let
+            (x 1)
+            (
+                (unless (> x 5) (inc! x) (inc! x))
+                (unless (< x 5) (inc! x))
+                x
+            )
+        )"#,
+    );
+    assert_eq!(result, 3.0);
+}
+
+#[test]
+fn macroexpand_expands_until_not_macro_call() {
+    let mut interpreter = Interpreter::new();
+    interpreter
+        .eval_str(
+            r#"
+            (defmacro inc! (var) `(set ,var (+ ,var 1)))
+            (defmacro my-inc! (var) `(inc! ,var))"#,
+        )
+        .unwrap();
+    for check in [
+        "(equal? (macroexpand-1 '(my-inc! y)) '(inc! y))",
+        "(equal? (macroexpand '(my-inc! y)) '(set y (+ y 1)))",
+        "(equal? (macroexpand '(+ y 1)) '(+ y 1))",
+    ] {
+        match interpreter.eval_str(check).unwrap() {
+            SExpr::Atom(Atom::Number(num)) => assert_eq!(num, 1.0, "{}", check),
+            other => panic!("Expected number, got {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn syntax_rules_matches_ellipsis_and_literals() {
+    let result = eval_number(
+        r#"
+        (define-syntax sum
+            (syntax-rules (of)
+                ((_ of x) x)
+                ((_ of x rest ...) (+ x (sum of rest ...)))))
+        (sum of 1 2 3 4)"#,
+    );
+    assert_eq!(result, 10.0);
+}
+
+#[test]
+fn syntax_rules_renames_introduced_bindings() {
+    let result = eval_number(
+        r#"
+        (define-syntax my-or
+            (syntax-rules ()
+                ((_ a b) (let (t a) (if t t b)))))
+        (let
+            (t 5)
+            (my-or 0 t)
+        )"#,
+    );
+    assert_eq!(result, 5.0);
+}
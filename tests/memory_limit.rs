@@ -0,0 +1,52 @@
+use tk_lisp_test_1::{Engine, Interpreter, LimitError};
+
+const LIMIT: usize = 64 * 1024;
+
+fn assert_memory_limit_exceeded(code: &str) {
+    for engine in [Engine::TreeWalker, Engine::Bytecode] {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_engine(engine);
+        interpreter.set_memory_limit(Some(LIMIT));
+        let err = interpreter.eval_str(code).unwrap_err();
+        match err.downcast_ref::<LimitError>() {
+            Some(LimitError::MemoryLimitExceeded { limit, used }) => {
+                assert_eq!(*limit, LIMIT);
+                assert!(*used > LIMIT, "{} bytes used", used);
+            }
+            _ => panic!("Expected memory limit error, got {}", err),
+        }
+        // Memory held by the failed evaluation is freed
+        assert!(interpreter.memory_usage() < LIMIT);
+        assert!(interpreter.eval_str("(list 1 2 3)").is_ok());
+    }
+}
+
+#[test]
+fn long_list_exceeds_memory_limit() {
+    let elems: Vec<String> = (0..5000).map(|i| i.to_string()).collect();
+    assert_memory_limit_exceeded(&format!("(let (l (list {})) 0)", elems.join(" ")));
+}
+
+#[test]
+fn long_string_exceeds_memory_limit() {
+    assert_memory_limit_exceeded(&format!("(let (s \"{}\") 0)", "a".repeat(5000)));
+}
+
+#[test]
+fn many_bindings_exceed_memory_limit() {
+    let bindings: Vec<String> = (0..2000).map(|i| format!("(v{} {})", i, i)).collect();
+    assert_memory_limit_exceeded(&format!("(let {} 0)", bindings.join(" ")));
+}
+
+#[test]
+fn evaluation_within_memory_limit_succeeds() {
+    for engine in [Engine::TreeWalker, Engine::Bytecode] {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_engine(engine);
+        interpreter.set_memory_limit(Some(LIMIT));
+        assert!(interpreter
+            .eval_str("(let (l (list 1 2 3)) (s \"abc\") (list l s))")
+            .is_ok());
+        assert!(interpreter.memory_usage() < LIMIT);
+    }
+}
@@ -0,0 +1,118 @@
+use tk_lisp_test_1::{Engine, Interpreter, SExpr};
+
+// Value with references followed
+fn show(value: &SExpr) -> String {
+    match value {
+        SExpr::Atom(atom) => format!("{:?}", atom),
+        SExpr::List(list) => {
+            let elems: Vec<String> = list.iter().map(show).collect();
+            format!("({})", elems.join(" "))
+        }
+        SExpr::Ref(cell) => show(&cell.lock().unwrap()),
+        other => format!("{:?}", other),
+    }
+}
+
+// Shown result or error message of `code` on both engines, which must agree
+fn eval(code: &str) -> Result<String, String> {
+    let results: Vec<Result<String, String>> = [Engine::TreeWalker, Engine::Bytecode]
+        .into_iter()
+        .map(|engine| {
+            let mut interpreter = Interpreter::new();
+            interpreter.set_engine(engine);
+            interpreter
+                .eval_str(code)
+                .map(|value| show(&value))
+                .map_err(|err| err.to_string())
+        })
+        .collect();
+    assert_eq!(results[0], results[1], "engines disagree on {}", code);
+    results[0].clone()
+}
+
+fn assert_evals_to(code: &str, expected: &str) {
+    assert_eq!(eval(code), eval(expected), "{}", code);
+}
+
+fn eval_error(code: &str) -> String {
+    match eval(code) {
+        Ok(value) => panic!("Expected error from {}, got {}", code, value),
+        Err(err) => err,
+    }
+}
+
+#[test]
+fn reader_shorthands_expand_to_forms() {
+    assert_evals_to("(equal? ''a '(quote a))", "1");
+    assert_evals_to("(equal? '`a '(quasiquote a))", "1");
+    assert_evals_to("(equal? ',a '(unquote a))", "1");
+    assert_evals_to("(equal? ',@a '(unquote-splicing a))", "1");
+    assert_evals_to("(quote (a b))", "'(a b)");
+    assert_evals_to("(quasiquote (a (unquote (+ 1 2))))", "'(a 3)");
+}
+
+#[test]
+fn quasiquote_evaluates_unquotes() {
+    assert_evals_to("`a", "'a");
+    assert_evals_to("`(a b)", "'(a b)");
+    assert_evals_to("(let (x 2) `(a ,x (b ,(+ x 1))))", "'(a 2 (b 3))");
+    assert_evals_to("`,(+ 1 2)", "3");
+}
+
+#[test]
+fn unquote_splicing_inserts_list_elements() {
+    assert_evals_to("(let (x 2) `(a ,@(list x x) b))", "'(a 2 2 b)");
+    assert_evals_to("`(a ,@() b)", "'(a b)");
+    assert_evals_to("`((,@(list 1 2)) 3)", "'((1 2) 3)");
+}
+
+#[test]
+fn nested_quasiquote_keeps_inner_unquotes() {
+    assert_evals_to("(let (x 2) `(a `(b ,(c ,x))))", "'(a `(b ,(c 2)))");
+    assert_evals_to("(let (x 2) `(a `(b ,,x)))", "'(a `(b ,2))");
+    assert_evals_to("`(a `(b ,(c ,@(list 1 2))))", "'(a `(b ,(c 1 2)))");
+    assert_evals_to("`(a `(b ,@(c)))", "'(a `(b ,@(c)))");
+    assert_evals_to("(let (x 1) `(a ``(,,,x)))", "'(a ``(,,1))");
+}
+
+#[test]
+fn unquote_outside_quasiquote_fails() {
+    let error = eval_error(",x");
+    assert!(
+        error.contains("`unquote` used outside of quasiquote"),
+        "{}",
+        error
+    );
+    let error = eval_error("(unquote-splicing (list 1))");
+    assert!(
+        error.contains("`unquote-splicing` used outside of quasiquote"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn unquote_splicing_requires_list() {
+    let error = eval_error("`(a ,@1)");
+    assert!(
+        error.contains("Argument of `unquote-splicing` must evaluate to a list"),
+        "{}",
+        error
+    );
+    let error = eval_error("`,@(list 1)");
+    assert!(
+        error.contains("`unquote-splicing` must be used inside a list"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn quasiquote_checks_form() {
+    let error = eval_error("(quasiquote a b)");
+    assert!(
+        error.contains("`quasiquote` must have exactly 2 elements"),
+        "{}",
+        error
+    );
+}
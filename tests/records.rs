@@ -0,0 +1,156 @@
+use tk_lisp_test_1::{Engine, Interpreter, SExpr};
+
+// Value with references followed
+fn show(value: &SExpr) -> String {
+    match value {
+        SExpr::Atom(atom) => format!("{:?}", atom),
+        SExpr::List(list) => {
+            let elems: Vec<String> = list.iter().map(show).collect();
+            format!("({})", elems.join(" "))
+        }
+        SExpr::Ref(cell) => show(&cell.lock().unwrap()),
+        SExpr::RecordType(record_type) => format!("#<type {}>", record_type.name),
+        SExpr::Record(record) => {
+            let record = record.lock().unwrap();
+            let fields: Vec<String> = record.fields.iter().map(show).collect();
+            format!("#<{} {}>", record.record_type.name, fields.join(" "))
+        }
+        other => format!("{:?}", other),
+    }
+}
+
+// Shown result or error message of `code` on both engines, which must agree
+fn eval(code: &str) -> Result<String, String> {
+    let results: Vec<Result<String, String>> = [Engine::TreeWalker, Engine::Bytecode]
+        .into_iter()
+        .map(|engine| {
+            let mut interpreter = Interpreter::new();
+            interpreter.set_engine(engine);
+            interpreter
+                .eval_str(code)
+                .map(|value| show(&value))
+                .map_err(|err| err.to_string())
+        })
+        .collect();
+    assert_eq!(results[0], results[1], "engines disagree on {}", code);
+    results[0].clone()
+}
+
+fn assert_evals_to(code: &str, expected: &str) {
+    assert_eq!(eval(code), eval(expected), "{}", code);
+}
+
+fn eval_error(code: &str) -> String {
+    match eval(code) {
+        Ok(value) => panic!("Expected error from {}, got {}", code, value),
+        Err(err) => err,
+    }
+}
+
+#[test]
+fn defstruct_defines_constructor_and_accessors() {
+    assert_eq!(
+        eval("(defstruct point x y)"),
+        Ok(String::from("#<type point>"))
+    );
+    assert_eq!(
+        eval("(defstruct point x y) (call make-point 1 2)"),
+        Ok(String::from("#<point Number(1.0) Number(2.0)>"))
+    );
+    assert_evals_to(
+        "(defstruct point x y) (call point-x (call make-point 1 2))",
+        "1",
+    );
+    assert_evals_to(
+        "(defstruct point x y) (call point-y (call make-point 1 2))",
+        "2",
+    );
+    assert_eq!(
+        eval("(defstruct unit) (call make-unit)"),
+        Ok(String::from("#<unit >"))
+    );
+}
+
+#[test]
+fn defstruct_defines_predicate() {
+    assert_evals_to(
+        "(defstruct point x y) (call point? (call make-point 1 2))",
+        "1",
+    );
+    assert_evals_to("(defstruct point x y) (call point? (list 1 2))", "0");
+    assert_evals_to(
+        "(defstruct point x y) (defstruct pair x y) (call point? (call make-pair 1 2))",
+        "0",
+    );
+}
+
+#[test]
+fn setters_update_record_in_place() {
+    assert_evals_to(
+        "(defstruct point x y)
+         (let (p (call make-point 1 2))
+            ((call set-point-x! p 5) (call point-x p)))",
+        "5",
+    );
+    assert_evals_to(
+        "(defstruct point x y)
+         (let (p (call make-point 1 2)) (call set-point-y! p 7))",
+        "7",
+    );
+    assert_evals_to(
+        "(defstruct box v)
+         (let (a (call make-box 1)) (b ())
+            ((set b a) (call set-box-v! a 3) (call box-v b)))",
+        "3",
+    );
+}
+
+#[test]
+fn records_of_same_type_name_are_distinct_types() {
+    assert_evals_to(
+        "(defstruct box v)
+         (let (old (call make-box 1))
+            ((defstruct box v) (call box? old)))",
+        "0",
+    );
+}
+
+#[test]
+fn accessors_reject_values_of_other_types() {
+    let error = eval_error("(defstruct point x y) (call point-x (list 1 2))");
+    assert!(
+        error.contains("`point-x` expects a record of type `point`"),
+        "{}",
+        error
+    );
+    let error = eval_error(
+        "(defstruct point x y) (defstruct pair x y) (call point-y (call make-pair 1 2))",
+    );
+    assert!(
+        error.contains("`point-y` expects a record of type `point`"),
+        "{}",
+        error
+    );
+    let error = eval_error("(defstruct point x y) (call set-point-x! 1 2)");
+    assert!(
+        error.contains("`set-point-x!` expects a record of type `point`"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn constructor_checks_argument_count() {
+    let error = eval_error("(defstruct point x y) (call make-point 1)");
+    assert!(error.contains("expects 2 argument(s), got 1"), "{}", error);
+    let error = eval_error("(defstruct point x y) (call make-point 1 2 3)");
+    assert!(error.contains("expects 2 argument(s), got 3"), "{}", error);
+}
+
+#[test]
+fn defstruct_requires_symbols() {
+    let error = eval_error("(defstruct)");
+    assert!(error.contains("`defstruct`"), "{}", error);
+    let error = eval_error("(defstruct point 1)");
+    assert!(error.contains("`defstruct`"), "{}", error);
+}
@@ -0,0 +1,78 @@
+use std::thread;
+use tk_lisp_test_1::{Engine, Interpreter, LimitError};
+
+// Runs `code`, which must fail with a limit error, on a thread with 2 MB stack
+fn limit_error_on_small_stack(code: &'static str, max_depth: usize) -> LimitError {
+    thread::Builder::new()
+        .stack_size(2 * 1024 * 1024)
+        .spawn(move || {
+            let errors: Vec<LimitError> = [Engine::TreeWalker, Engine::Bytecode]
+                .into_iter()
+                .map(|engine| {
+                    let mut interpreter = Interpreter::new();
+                    interpreter.set_engine(engine);
+                    interpreter.set_max_recursion_depth(max_depth);
+                    let err = interpreter.eval_str(code).unwrap_err();
+                    let limit_err = err.downcast_ref::<LimitError>().cloned();
+                    // Interpreter stays usable after running out of stack
+                    assert!(interpreter.eval_str("(+ 1 2)").is_ok());
+                    limit_err.unwrap_or_else(|| panic!("Expected limit error, got {}", err))
+                })
+                .collect();
+            assert_eq!(
+                std::mem::discriminant(&errors[0]),
+                std::mem::discriminant(&errors[1]),
+                "engines disagree: {:?}",
+                errors
+            );
+            errors[0].clone()
+        })
+        .unwrap()
+        .join()
+        .unwrap()
+}
+
+#[test]
+fn plain_recursion_stops_at_depth_limit() {
+    let err = limit_error_on_small_stack(
+        "(let (f ()) ((set f (lambda (f) (n) (+ 1 (call f n)))) (call f 0)))",
+        1000,
+    );
+    assert!(
+        matches!(err, LimitError::RecursionDepthExceeded { limit: 1000, .. }),
+        "{:?}",
+        err
+    );
+}
+
+#[test]
+fn recursion_through_map_runs_out_of_stack_safely() {
+    let err = limit_error_on_small_stack(
+        r#"
+        (let (f ())
+            ((set f (lambda (f) (n) (map (lambda (f) (x) (call f x)) (list n))))
+             (call f 0)))"#,
+        usize::MAX,
+    );
+    assert!(
+        matches!(err, LimitError::StackExhausted { .. }),
+        "{:?}",
+        err
+    );
+}
+
+#[test]
+fn recursion_through_call_cc_runs_out_of_stack_safely() {
+    let err = limit_error_on_small_stack(
+        r#"
+        (let (f ())
+            ((set f (lambda (f) (n) (+ 1 (call/cc (lambda (f n) (k) (call f (+ n 1)))))))
+             (call f 0)))"#,
+        usize::MAX,
+    );
+    assert!(
+        matches!(err, LimitError::StackExhausted { .. }),
+        "{:?}",
+        err
+    );
+}
@@ -0,0 +1,99 @@
+use tk_lisp_test_1::{Atom, Engine, Interpreter, LimitError, SExpr, Symbol};
+
+const LIMIT: usize = 64 * 1024;
+
+fn eval_number(code: &str) -> f64 {
+    match Interpreter::new().eval_str(code).unwrap() {
+        SExpr::Atom(Atom::Number(num)) => num,
+        other => panic!("Expected number, got {:?}", other),
+    }
+}
+
+// Fills vector, which is allocated once, with 100 symbols made by `make` from long name `name`
+fn fill_vector_with_symbols(make: &str) -> String {
+    let name = "n".repeat(1000);
+    format!(
+        r#"(let (name '{}) (v (make-vector 100 0)) (i 0)
+            (while (< i 100) ((vector-set! v i {}) (set i (+ i 1)))))"#,
+        name, make
+    )
+}
+
+#[test]
+fn symbols_with_the_same_name_are_identical() {
+    assert_eq!(Symbol::intern("point"), Symbol::intern("point"));
+    assert_ne!(Symbol::intern("point"), Symbol::intern("points"));
+    match Interpreter::new().eval_str("'point").unwrap() {
+        SExpr::Atom(Atom::Symbol(sym)) => assert_eq!(sym, Symbol::intern("point")),
+        other => panic!("Expected symbol, got {:?}", other),
+    }
+}
+
+#[test]
+fn symbols_convert_to_and_from_strings() {
+    assert_eq!(eval_number("(eq? (string->symbol \"abc\") 'abc)"), 1.0);
+    assert_eq!(eval_number("(equal? (symbol->string 'abc) \"abc\")"), 1.0);
+    assert_eq!(
+        eval_number("(length (symbol->string (string->symbol \"\")))"),
+        0.0
+    );
+    assert!(Interpreter::new().eval_str("(symbol->string 5)").is_err());
+    assert!(Interpreter::new()
+        .eval_str("(string->symbol 'abc)")
+        .is_err());
+}
+
+#[test]
+fn gensym_creates_uninterned_symbols() {
+    let code = r#"
+        (let (g (gensym 'tmp))
+            (list
+                (eq? g g)
+                (eq? g (string->symbol (symbol->string g)))
+                (eq? g (gensym 'tmp))))"#;
+    let result = Interpreter::new().eval_str(code).unwrap();
+    let flags: Vec<f64> = match result {
+        SExpr::List(list) => list
+            .iter()
+            .map(|elem| match elem {
+                SExpr::Atom(Atom::Number(num)) => *num,
+                other => panic!("Expected number, got {:?}", other),
+            })
+            .collect(),
+        other => panic!("Expected list, got {:?}", other),
+    };
+    assert_eq!(flags, vec![1.0, 0.0, 0.0]);
+    let fresh = Symbol::uninterned(String::from("point"));
+    assert_ne!(fresh, Symbol::intern("point"));
+    assert_eq!(fresh.as_str(), "point");
+}
+
+#[test]
+fn symbols_stay_identical_when_unused_names_are_dropped() {
+    let kept = Symbol::intern("kept-symbol");
+    for i in 0..10_000 {
+        Symbol::intern(&format!("temporary-{}", i));
+    }
+    assert_eq!(kept, Symbol::intern("kept-symbol"));
+    assert_ne!(Symbol::intern("temporary-0"), kept);
+    assert_eq!(Symbol::intern("temporary-0"), Symbol::intern("temporary-0"));
+}
+
+#[test]
+fn created_symbols_count_against_memory_limit() {
+    for make in ["(gensym name)", "(string->symbol (symbol->string name))"] {
+        let code = fill_vector_with_symbols(make);
+        for engine in [Engine::TreeWalker, Engine::Bytecode] {
+            let mut interpreter = Interpreter::new();
+            interpreter.set_engine(engine);
+            interpreter.set_memory_limit(Some(LIMIT));
+            let err = interpreter.eval_str(&code).unwrap_err();
+            match err.downcast_ref::<LimitError>() {
+                Some(LimitError::MemoryLimitExceeded { limit, .. }) => assert_eq!(*limit, LIMIT),
+                _ => panic!("Expected memory limit error, got {}", err),
+            }
+            interpreter.set_memory_limit(Some(16 * LIMIT));
+            assert!(interpreter.eval_str(&code).is_ok(), "{}", make);
+        }
+    }
+}
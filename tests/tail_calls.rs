@@ -0,0 +1,46 @@
+use tk_lisp_test_1::{Atom, Interpreter, SExpr};
+
+fn eval_number(code: &str) -> f64 {
+    match Interpreter::new().eval_str(code).unwrap() {
+        SExpr::Atom(Atom::Number(num)) => num,
+        other => panic!("Expected number, got {:?}", other),
+    }
+}
+
+#[test]
+fn tail_recursive_loop_runs_in_constant_stack() {
+    let result = eval_number(
+        r#"
+        (let
+            (count-up ())
+            (
+                (set count-up (lambda (count-up) (n)
+                    (if (< n 1000000)
+                        (call count-up (+ n 1))
+                        n)))
+                (call count-up 0)
+            )
+        )"#,
+    );
+    assert_eq!(result, 1000000.0);
+}
+
+#[test]
+fn tail_calls_through_cond_and_blocks() {
+    let result = eval_number(
+        r#"
+        (let
+            (count-up ())
+            (
+                (set count-up (lambda (count-up) (n acc)
+                    (cond
+                        ((>= n 100000) acc)
+                        (else (
+                            (let (next (+ n 1)) (call count-up next (+ acc 2)))
+                        )))))
+                (call count-up 0 0)
+            )
+        )"#,
+    );
+    assert_eq!(result, 200000.0);
+}
@@ -0,0 +1,72 @@
+use std::thread;
+use tk_lisp_test_1::{Atom, Interpreter, Module, SExpr};
+
+fn number(value: SExpr) -> f64 {
+    match value {
+        SExpr::Atom(Atom::Number(num)) => num,
+        other => panic!("Expected number, got {:?}", other),
+    }
+}
+
+fn assert_send_sync<T: Send + Sync>() {}
+
+#[test]
+fn interpreter_state_is_thread_safe() {
+    assert_send_sync::<Interpreter>();
+    assert_send_sync::<Module>();
+    assert_send_sync::<SExpr>();
+}
+
+#[test]
+fn independent_interpreters_run_concurrently() {
+    let module = Module::parse(
+        r#"
+        (let (i 0) (s 0)
+            ((while (< i 10000) ((set s (+ s n)) (set i (+ i 1))))
+             (let (v (make-vector 1 s)) ((vector-set! v 0 v) s))))"#,
+    )
+    .unwrap();
+    let handles: Vec<_> = (0..8)
+        .map(|n| {
+            let module = module.clone();
+            thread::spawn(move || {
+                let mut interpreter = Interpreter::new();
+                interpreter.set_global("n", SExpr::Atom(Atom::Number(n as f64)));
+                let result = number(interpreter.eval_module(&module).unwrap());
+                interpreter.collect_garbage();
+                result
+            })
+        })
+        .collect();
+    for (n, handle) in handles.into_iter().enumerate() {
+        assert_eq!(handle.join().unwrap(), 10000.0 * n as f64);
+    }
+}
+
+#[test]
+fn interpreter_moves_across_threads() {
+    let mut interpreter = Interpreter::new();
+    interpreter
+        .eval_str("(defmacro twice (x) `(+ ,x ,x))")
+        .unwrap();
+    interpreter.set_global("counter", SExpr::Atom(Atom::Number(1.0)));
+    let mut interpreter = thread::spawn(move || {
+        interpreter
+            .eval_str("(set counter (twice counter))")
+            .unwrap();
+        interpreter
+    })
+    .join()
+    .unwrap();
+    let result = interpreter.eval_str("(twice counter)").unwrap();
+    assert_eq!(number(result), 4.0);
+}
+
+#[test]
+fn evaluation_errors_cross_threads() {
+    let error = thread::spawn(|| Interpreter::new().eval_str("(undefined-function 1)"))
+        .join()
+        .unwrap()
+        .unwrap_err();
+    assert!(!error.to_string().is_empty());
+}
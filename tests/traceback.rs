@@ -0,0 +1,89 @@
+use tk_lisp_test_1::{Atom, Engine, Interpreter, SExpr};
+
+#[test]
+fn failed_evaluation_records_lisp_call_stack() {
+    let mut interpreter = Interpreter::new();
+    let result = interpreter.eval_str(
+        r#"
+        (print "")
+        (let
+            (inner ())
+            (outer ())
+            (
+                (set inner (lambda () (x) (+ x undefined-variable)))
+                (set outer (lambda (inner) (n) (+ 1 (call inner (+ n 1)))))
+                (call outer 5)
+            )
+        )"#,
+    );
+    assert!(result.is_err());
+
+    let traceback = interpreter.traceback().unwrap();
+    assert_eq!(traceback.line, Some(3));
+    let names: Vec<&str> = traceback
+        .frames
+        .iter()
+        .map(|frame| frame.name.as_str())
+        .collect();
+    assert_eq!(names, ["outer", "inner"]);
+    assert!(matches!(
+        traceback.frames[1].args.as_slice(),
+        [SExpr::Atom(Atom::Number(num))] if *num == 6.0
+    ));
+
+    interpreter.eval_str("(+ 1 2)").unwrap();
+    assert!(interpreter.traceback().is_none());
+}
+
+#[test]
+fn caught_errors_do_not_leave_traceback() {
+    let mut interpreter = Interpreter::new();
+    interpreter
+        .eval_str(
+            r#"
+            (let
+                (fail (lambda () () (raise 1)))
+                (try (call fail) (catch e e))
+            )"#,
+        )
+        .unwrap();
+    assert!(interpreter.context().take_traceback().is_empty());
+}
+
+#[test]
+fn frames_record_lines_of_calls_and_lambdas() {
+    let code = r#"
+        (let
+            (inner (lambda () (x)
+                (+ x undefined-variable)))
+            (outer (lambda (inner) (n)
+                (map
+                    inner
+                    (list n))))
+            (call outer
+                5))"#;
+    for engine in [Engine::TreeWalker, Engine::Bytecode] {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_engine(engine);
+        assert!(interpreter.eval_str(code).is_err());
+        let traceback = interpreter.traceback().unwrap();
+        let frames: Vec<(&str, Option<usize>)> = traceback
+            .frames
+            .iter()
+            .map(|frame| (frame.name.as_str(), frame.line))
+            .collect();
+        // `outer` is called by `call` form, `inner` by `map`, so its definition is shown
+        assert_eq!(frames, [("outer", Some(9)), ("<lambda>", Some(3))]);
+        let printed = traceback.to_string();
+        assert!(
+            printed.contains("  in outer (5) at line 9\n"),
+            "{}",
+            printed
+        );
+        assert!(
+            printed.contains("  in <lambda> (5) at line 3\n"),
+            "{}",
+            printed
+        );
+    }
+}
@@ -0,0 +1,116 @@
+use tk_lisp_test_1::{Engine, Interpreter, SExpr};
+
+// Value with references followed
+fn show(value: &SExpr) -> String {
+    match value {
+        SExpr::Atom(atom) => format!("{:?}", atom),
+        SExpr::List(list) => {
+            let elems: Vec<String> = list.iter().map(show).collect();
+            format!("({})", elems.join(" "))
+        }
+        SExpr::Ref(cell) => show(&cell.lock().unwrap()),
+        SExpr::Hash(table) => format!("{{{} entries}}", table.lock().unwrap().len()),
+        SExpr::Vector(vector) => {
+            let elems: Vec<String> = vector.lock().unwrap().iter().map(show).collect();
+            format!("[{}]", elems.join(" "))
+        }
+        other => format!("{:?}", other),
+    }
+}
+
+// Shown result or error message of `code` on both engines, which must agree
+fn eval(code: &str) -> Result<String, String> {
+    let results: Vec<Result<String, String>> = [Engine::TreeWalker, Engine::Bytecode]
+        .into_iter()
+        .map(|engine| {
+            let mut interpreter = Interpreter::new();
+            interpreter.set_engine(engine);
+            interpreter
+                .eval_str(code)
+                .map(|value| show(&value))
+                .map_err(|err| err.to_string())
+        })
+        .collect();
+    assert_eq!(results[0], results[1], "engines disagree on {}", code);
+    results[0].clone()
+}
+
+fn assert_evals_to(code: &str, expected: &str) {
+    assert_eq!(eval(code), eval(expected), "{}", code);
+}
+
+fn eval_error(code: &str) -> String {
+    match eval(code) {
+        Ok(value) => panic!("Expected error from {}, got {}", code, value),
+        Err(err) => err,
+    }
+}
+
+#[test]
+fn literal_and_constructors_create_vectors() {
+    assert_evals_to("[1 (+ 1 1) 3]", "(vector 1 2 3)");
+    assert_evals_to("(vector-length [])", "0");
+    assert_evals_to(
+        "(make-vector 3 (quote x))",
+        "[(quote x) (quote x) (quote x)]",
+    );
+    assert_evals_to("(make-vector 2)", "[() ()]");
+    assert_evals_to("(list->vector (list 1 2))", "[1 2]");
+    assert_evals_to("(vector->list [1 2])", "(list 1 2)");
+    assert!(eval("[1 2").unwrap_err().contains("not closed"));
+}
+
+#[test]
+fn elements_are_read_and_written_by_index() {
+    assert_evals_to("(vector-ref [4 5 6] 0)", "4");
+    assert_evals_to("(vector-ref [4 5 6] 2)", "6");
+    assert_evals_to(
+        "(let (v [1 2 3]) ((vector-set! v 1 (quote b)) v))",
+        "[1 (quote b) 3]",
+    );
+    assert_evals_to(
+        "(let (v []) ((list (vector-push! v (quote a)) (vector-push! v (quote b)) v)))",
+        "(list 1 2 [(quote a) (quote b)])",
+    );
+    // Vector is shared, not copied, by variables holding it
+    assert_evals_to(
+        "(let (v [0]) (w ()) ((set w v) (vector-set! w 0 1) (vector-ref v 0)))",
+        "1",
+    );
+}
+
+#[test]
+fn out_of_bounds_indices_are_errors() {
+    let err = eval_error("(vector-ref [4 5 6] 3)");
+    assert!(
+        err.contains("index 3 out of bounds for length 3"),
+        "{}",
+        err
+    );
+    let err = eval_error("(vector-set! [] 0 1)");
+    assert!(
+        err.contains("index 0 out of bounds for length 0"),
+        "{}",
+        err
+    );
+    assert!(eval_error("(vector-ref [1] (list 0))").contains("`vector-ref` expects a number"));
+    assert!(eval_error("(vector-ref (list 1) 0)").contains("`vector-ref` expects a vector"));
+}
+
+#[test]
+fn invalid_lengths_are_errors() {
+    assert!(eval_error("(make-vector (list 1))").contains("expects a number"));
+    let err = eval_error("(make-vector 100000000000 0)");
+    assert!(
+        err.contains("`make-vector`: length 100000000000 is too large"),
+        "{}",
+        err
+    );
+    let inf = "(let (x 1) ((while (< x (+ x x)) (set x (+ x x))) x))";
+    let err = eval_error(&format!("(make-vector {} 0)", inf));
+    assert!(
+        err.contains("`make-vector`: length inf is not a finite number"),
+        "{}",
+        err
+    );
+}